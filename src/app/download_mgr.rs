@@ -1,14 +1,16 @@
+use std::sync::{Arc, RwLock, atomic::AtomicBool};
+
 use super::{
-    App, EpData, GpodderRequest, Options, PathBuf, Result, anyhow, downloads, fs,
-    sanitize_with_options,
+    App, Download, DownloadStatus, EpData, GpodderRequest, Options, PathBuf, Result, anyhow,
+    downloads, fs, sanitize_with_options,
 };
 
 impl App {
     /// Given a podcast index (and not an episode index), this will send a
     /// vector of jobs to download all episodes in the
-    /// podcast. If given an episode index as well, it will download just that
-    /// episode.
-    pub fn download(&mut self, pod_id: i64, ep_id: Option<i64>) -> Result<()> {
+    /// podcast. If given episode indexes as well, it will download just those
+    /// episodes.
+    pub fn download(&self, pod_id: i64, ep_ids: Option<&[i64]>) -> Result<()> {
         let pod_title;
         let mut ep_data = Vec::new();
         {
@@ -19,30 +21,33 @@ impl App {
             let podcast = podcast.read().expect("RwLock read should not fail");
             pod_title = podcast.title.clone();
 
-            // if we are selecting one specific episode, just grab that one;
+            // if we are selecting specific episodes, just grab those;
             // otherwise, loop through them all
-            match ep_id {
-                Some(ep_id) => {
-                    // grab just the relevant data we need
-                    let data = podcast
-                        .episodes
-                        .map_single(ep_id, |ep| {
-                            (
-                                EpData {
-                                    id: ep.id,
-                                    pod_id: ep.pod_id,
-                                    title: ep.title.clone(),
-                                    url: ep.url.clone(),
-                                    pubdate: ep.pubdate,
-                                    file_path: None,
-                                    duration: None,
-                                },
-                                ep.path.is_none(),
-                            )
-                        })
-                        .ok_or_else(|| anyhow!("ep_id: {ep_id} does not exist"))?;
-                    if data.1 {
-                        ep_data.push(data.0);
+            match ep_ids {
+                Some(ep_ids) => {
+                    for &ep_id in ep_ids {
+                        // grab just the relevant data we need
+                        let data = podcast
+                            .episodes
+                            .map_single(ep_id, |ep| {
+                                (
+                                    EpData {
+                                        id: ep.id,
+                                        pod_id: ep.pod_id,
+                                        title: ep.title.clone(),
+                                        url: ep.url.clone(),
+                                        pubdate: ep.pubdate,
+                                        file_path: None,
+                                        duration: None,
+                                        loudness: None,
+                                    },
+                                    ep.path.is_none(),
+                                )
+                            })
+                            .ok_or_else(|| anyhow!("ep_id: {ep_id} does not exist"))?;
+                        if data.1 {
+                            ep_data.push(data.0);
+                        }
                     }
                 }
                 None => {
@@ -58,6 +63,7 @@ impl App {
                                 pubdate: ep.pubdate,
                                 file_path: None,
                                 duration: ep.duration,
+                                loudness: None,
                             })
                         } else {
                             None
@@ -69,27 +75,24 @@ impl App {
 
         // Check against episodes currently being downloaded, so we don't
         // needlessly download them again
-        ep_data.retain(|ep| !self.download_tracker.contains(&ep.id));
+        ep_data.retain(|ep| !self.is_downloading(ep.id));
 
         if !ep_data.is_empty() {
             // add directory for podcast, create if it does not exist
-            let dir_name = sanitize_with_options(
-                &pod_title,
-                Options {
-                    truncate: true,
-                    windows: true, // for simplicity, we'll just use Windows-friendly paths for everyone
-                    replacement: "",
-                },
-            );
-            match self.create_podcast_dir(dir_name) {
+            match self.create_podcast_dir(&pod_title) {
                 Ok(path) => {
-                    for ep in &ep_data {
-                        self.download_tracker.insert(ep.id);
-                    }
+                    let ep_data = ep_data
+                        .into_iter()
+                        .map(|ep| {
+                            let cancel = self.track_download(&ep);
+                            (ep, cancel)
+                        })
+                        .collect();
                     downloads::download_list(
                         ep_data,
                         &path,
                         self.config.max_retries,
+                        self.config.normalize_loudness,
                         &self.semaphore,
                         &self.tx_to_main,
                     );
@@ -102,11 +105,12 @@ impl App {
     }
 
     /// Handles logic for what to do when a download successfully completes.
-    pub fn download_complete(&mut self, ep_data: EpData) -> Result<()> {
+    pub fn download_complete(&self, ep_data: EpData) -> Result<()> {
         let file_path = ep_data
             .file_path
             .ok_or_else(|| anyhow!("ep_data does not contain a file_path"))?;
-        self.db.insert_file(ep_data.id, &file_path)?;
+        self.db
+            .insert_file(ep_data.id, &file_path, ep_data.loudness)?;
         {
             let pod_id = ep_data.pod_id;
             let podcast = self
@@ -125,14 +129,16 @@ impl App {
                 .write()
                 .expect("RwLock write should not fail");
             episode.path = Some(file_path);
+            episode.loudness = ep_data.loudness;
+            episode.chapters_checked = false;
             if let Some(duration) = ep_data.duration {
                 episode.duration = Some(duration);
             }
         }
 
-        self.download_tracker.remove(&ep_data.id);
+        self.update_download(ep_data.id, |dl| dl.status = DownloadStatus::Complete);
         self.update_tracker_notif();
-        if self.download_tracker.is_empty() {
+        if self.pending_downloads() == 0 {
             self.notif_to_ui("Downloads complete.".to_string(), false);
         }
 
@@ -140,11 +146,107 @@ impl App {
         Ok(())
     }
 
+    /// Keeps a completely streamed episode like a finished download, unless
+    /// it was downloaded in the meantime.
+    pub fn stream_complete(
+        &self, ep_id: i64, streamed: PathBuf, mime_type: Option<&str>,
+    ) -> Result<()> {
+        let ep_data = self.podcasts.get_episodes_map().get(&ep_id).and_then(|ep| {
+            let ep = ep.read().expect("RwLock read should not fail");
+            (ep.path.is_none() && !self.is_downloading(ep.id)).then(|| EpData {
+                id: ep.id,
+                pod_id: ep.pod_id,
+                title: ep.title.clone(),
+                url: ep.url.clone(),
+                pubdate: ep.pubdate,
+                file_path: None,
+                duration: ep.duration,
+                loudness: None,
+            })
+        });
+        let Some(ep_data) = ep_data else {
+            fs::remove_file(&streamed)?;
+            return Ok(());
+        };
+        let pod_title = self
+            .podcasts
+            .map_single(ep_data.pod_id, |pod| pod.title.clone())
+            .ok_or_else(|| anyhow!("Failed to get pod_id: {}", ep_data.pod_id))?;
+        if let Ok(path) = self.create_podcast_dir(&pod_title) {
+            self.track_download(&ep_data);
+            // moving the file is all there is left to do
+            self.update_download(ep_data.id, |dl| dl.start(None));
+            downloads::keep_streamed(
+                ep_data,
+                streamed,
+                mime_type,
+                &path,
+                self.config.normalize_loudness,
+                &self.tx_to_main,
+            );
+        } else {
+            fs::remove_file(&streamed)?;
+            self.notif_to_ui(format!("Could not create dir: {pod_title}"), true);
+        }
+        self.update_tracker_notif();
+        Ok(())
+    }
+
+    /// Lists a new download of an episode, replacing an earlier one, and
+    /// returns the flag that cancels it.
+    fn track_download(&self, ep_data: &EpData) -> Arc<AtomicBool> {
+        let download = Download::new(ep_data);
+        let cancel = download.cancel_flag();
+        self.downloads.remove(ep_data.id);
+        self.downloads.push_arc(Arc::new(RwLock::new(download)));
+        cancel
+    }
+
+    /// Changes the listed download of an episode, if there is one
+    pub fn update_download(&self, ep_id: i64, f: impl FnOnce(&mut Download)) {
+        if let Some(download) = self.downloads.get(ep_id) {
+            f(&mut download.write().expect("RwLock write should not fail"));
+        }
+    }
+
+    /// Whether an episode is waiting to be downloaded or downloading
+    fn is_downloading(&self, ep_id: i64) -> bool {
+        self.downloads
+            .map_single(ep_id, Download::is_pending)
+            .unwrap_or(false)
+    }
+
+    /// Number of downloads waiting or running
+    pub fn pending_downloads(&self) -> usize {
+        self.downloads
+            .filter_map(|dl| {
+                dl.read()
+                    .expect("RwLock read should not fail")
+                    .is_pending()
+                    .then_some(())
+            })
+            .len()
+    }
+
+    /// Stops a download, removing what it already wrote.
+    pub fn cancel_download(&self, ep_id: i64) {
+        self.update_download(ep_id, Download::cancel);
+        self.update_tracker_notif();
+    }
+
     /// Given a podcast title, creates a download directory for that podcast if
     /// it does not already exist.
-    pub fn create_podcast_dir(&self, pod_title: String) -> Result<PathBuf, std::io::Error> {
+    pub fn create_podcast_dir(&self, pod_title: &str) -> Result<PathBuf, std::io::Error> {
+        let dir_name = sanitize_with_options(
+            pod_title,
+            Options {
+                truncate: true,
+                windows: true, // for simplicity, we'll just use Windows-friendly paths for everyone
+                replacement: "",
+            },
+        );
         let mut download_path = self.config.download_path.clone();
-        download_path.push(pod_title);
+        download_path.push(dir_name);
         match std::fs::create_dir_all(&download_path) {
             Ok(()) => Ok(download_path),
             Err(err) => Err(err),
@@ -173,6 +275,7 @@ impl App {
                 .clone()
                 .ok_or_else(|| anyhow!("Episode has no path"))?;
             episode.path = None;
+            episode.loudness = None;
             (old_path, episode.title.clone())
         };
 
@@ -192,39 +295,72 @@ impl App {
     pub fn delete_files(&self, pod_id: i64) -> Result<()> {
         let mut eps_id_to_remove = Vec::new();
         let mut eps_path_to_remove = Vec::new();
+        self.take_files(pod_id, None, &mut eps_id_to_remove, &mut eps_path_to_remove)?;
+        self.remove_files(&eps_id_to_remove, &eps_path_to_remove);
+        Ok(())
+    }
 
-        {
-            let borrowed_map = self.podcasts.borrow_map();
-            let episodes = &borrowed_map
-                .get(&pod_id)
-                .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?
-                .read()
-                .expect("RwLock read should not fail")
-                .episodes;
-            let mut borrowed_ep_map = episodes.borrow_map();
+    /// Deletes the downloaded files of the given episodes, each with its
+    /// podcast.
+    pub fn delete_files_batch(&self, episodes: Vec<(i64, i64)>) -> Result<()> {
+        let mut eps_id_to_remove = Vec::new();
+        let mut eps_path_to_remove = Vec::new();
+        for (pod_id, ep_ids) in super::group_by_podcast(episodes) {
+            self.take_files(
+                pod_id,
+                Some(&ep_ids),
+                &mut eps_id_to_remove,
+                &mut eps_path_to_remove,
+            )?;
+        }
+        self.remove_files(&eps_id_to_remove, &eps_path_to_remove);
+        Ok(())
+    }
 
-            for (_, ep) in borrowed_ep_map.iter_mut() {
-                let mut ep = ep.write().expect("RwLock write should not fail");
-                if ep.path.is_some() {
-                    eps_path_to_remove.push(
-                        ep.path
-                            .clone()
-                            .ok_or_else(|| anyhow!("Failed to get episode path"))?,
-                    );
-                    eps_id_to_remove.push(ep.id);
-                    ep.path = None;
-                }
+    /// Collects the ids and the paths of the downloaded episodes of a
+    /// podcast, all of them or only the given ones, and forgets their files.
+    fn take_files(
+        &self, pod_id: i64, ep_ids: Option<&[i64]>, eps_id_to_remove: &mut Vec<i64>,
+        eps_path_to_remove: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let podcast = self
+            .podcasts
+            .get(pod_id)
+            .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?;
+        let episodes = podcast
+            .read()
+            .expect("RwLock read should not fail")
+            .episodes
+            .clone();
+        let borrowed_ep_map = episodes.borrow_map();
+
+        for (ep_id, ep) in borrowed_ep_map.iter() {
+            if ep_ids.is_some_and(|ep_ids| !ep_ids.contains(ep_id)) {
+                continue;
+            }
+            let mut ep = ep.write().expect("RwLock write should not fail");
+            if let Some(path) = ep.path.take() {
+                eps_path_to_remove.push(path);
+                eps_id_to_remove.push(ep.id);
+                ep.loudness = None;
             }
         }
+        drop(borrowed_ep_map);
+        Ok(())
+    }
+
+    /// Removes the files from the disk and the database, and tells how it
+    /// went.
+    fn remove_files(&self, eps_id_to_remove: &[i64], eps_path_to_remove: &[PathBuf]) {
         let mut success = true;
-        for path in &eps_path_to_remove {
+        for path in eps_path_to_remove {
             match fs::remove_file(path) {
                 Ok(()) => {}
                 Err(_) => success = false,
             }
         }
 
-        let res = self.db.remove_files(&eps_id_to_remove);
+        let res = self.db.remove_files(eps_id_to_remove);
         if res.is_err() {
             success = false;
         }
@@ -239,7 +375,6 @@ impl App {
         } else {
             self.notif_to_ui("Error while deleting files".to_string(), true);
         }
-        Ok(())
     }
 
     /// Removes a podcast from the list, optionally deleting local files first
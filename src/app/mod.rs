@@ -3,7 +3,7 @@ use std::cell::Cell;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
-use std::sync::{Arc, mpsc};
+use std::sync::{Arc, RwLock, mpsc};
 use std::time::{Duration, Instant};
 
 use tokio::sync::Semaphore;
@@ -12,15 +12,17 @@ use sanitize_filename::{Options, sanitize_with_options};
 
 use crate::gpodder::{EpisodeAction, GpodderMsg};
 use crate::{
+    artwork,
     config::{Config, MAX_DURATION},
     db::{Database, SyncResult},
-    downloads::{self, DownloadError, DownloadMsg, EpData},
+    downloads::{self, Download, DownloadError, DownloadMsg, DownloadStatus, EpData},
     feeds::{self, FeedMsg, PodcastFeed},
     gpodder::{Action, GpodderRequest},
     play_file,
+    player::PlayerEvent,
     types::{
-        Episode, FilterStatus, FilterType, Filters, LockVec, Menuable, Message, Podcast,
-        PodcastNoId, ShareableRwLock, SyncProgress,
+        Episode, FilterPanel, FilterType, Filters, LockVec, Menuable, Message, PanelFilters,
+        Podcast, PodcastNoId, ShareableRwLock, SortMode, SyncProgress,
     },
     ui::UiMsg,
     utils::{current_time_ms, get_unplayed_episodes, normalize_url, resolve_redirection},
@@ -36,7 +38,18 @@ pub enum MainMessage {
     SpawnNotif(String, u64, bool),
     SpawnPersistentNotif(String, bool),
     ClearPersistentNotif,
-    PlayCurrent(i64),
+    /// Episode to play, and the position to start from instead of the
+    /// saved one
+    PlayCurrent(i64, Option<Duration>),
+    /// Filters of each list, to show them
+    Filters(PanelFilters),
+    /// Order of a list, to show it
+    Sort(FilterPanel, SortMode),
+    /// Podcast, episode, title and exact position of the episode that was
+    /// playing when the app last closed
+    OfferResume(i64, i64, String, Duration),
+    /// Url of artwork and whether it was cached
+    Artwork(String, bool),
     TearDown,
 }
 
@@ -49,11 +62,18 @@ pub struct App {
     podcasts: LockVec<Podcast>,
     queue: LockVec<Episode>,
     unplayed: LockVec<Episode>,
-    filters: Filters,
+    filters: PanelFilters,
+    podcast_sort: SortMode,
+    unplayed_sort: SortMode,
     sync_counter: usize,
     sync_progress: ShareableRwLock<SyncProgress>,
     sync_tracker: Vec<SyncResult>,
-    download_tracker: HashSet<i64>,
+    /// Downloads of this session, shown in the downloads panel
+    downloads: LockVec<Download>,
+    /// Episodes whose chapters are being loaded
+    chapter_tracker: HashSet<i64>,
+    /// Urls of the artwork being fetched
+    artwork_tracker: HashSet<String>,
     last_filter_time_ms: Cell<u128>,
     pub tx_to_ui: mpsc::Sender<MainMessage>,
     pub tx_to_main: mpsc::Sender<Message>,
@@ -71,9 +91,12 @@ impl App {
         rx_to_main: mpsc::Receiver<Message>, tx_to_gpodder: mpsc::Sender<GpodderRequest>,
         tx_to_ui: mpsc::Sender<MainMessage>, podcast_list: LockVec<Podcast>,
         queue_items: LockVec<Episode>, unplayed_items: LockVec<Episode>,
-        sync_progress: ShareableRwLock<SyncProgress>,
+        download_items: LockVec<Download>, sync_progress: ShareableRwLock<SyncProgress>,
     ) -> Self {
         let semaphore = Arc::new(Semaphore::new(config.simultaneous_downloads));
+        let filters = load_filters(&db_inst);
+        let podcast_sort = load_sort(&db_inst, FilterPanel::Podcasts);
+        let unplayed_sort = load_sort(&db_inst, FilterPanel::Unplayed);
 
         Self {
             config,
@@ -82,11 +105,15 @@ impl App {
             podcasts: podcast_list,
             queue: queue_items,
             unplayed: unplayed_items,
-            filters: Filters::default(),
+            filters,
+            podcast_sort,
+            unplayed_sort,
             sync_counter: 0,
             sync_progress,
             sync_tracker: Vec::new(),
-            download_tracker: HashSet::new(),
+            downloads: download_items,
+            chapter_tracker: HashSet::new(),
+            artwork_tracker: HashSet::new(),
             last_filter_time_ms: 0.into(),
             tx_to_ui,
             tx_to_main,
@@ -102,6 +129,24 @@ impl App {
         if self.config.sync_on_start {
             self.sync(None);
         }
+        self.offer_resume()
+            .inspect_err(|err| log::warn!("Failed to read the last position: {err}"))
+            .ok();
+        self.update_unplayed(false);
+        self.update_filters(self.filters, false);
+        self.tx_to_ui
+            .send(MainMessage::Filters(self.filters))
+            .inspect_err(|err| log::error!("Failed to send filters to UI: {err}"))
+            .ok();
+        for (panel, mode) in [
+            (FilterPanel::Podcasts, self.podcast_sort),
+            (FilterPanel::Unplayed, self.unplayed_sort),
+        ] {
+            self.tx_to_ui
+                .send(MainMessage::Sort(panel, mode))
+                .inspect_err(|err| log::error!("Failed to send sort order to UI: {err}"))
+                .ok();
+        }
 
         let sync_interval = self
             .config
@@ -173,7 +218,22 @@ impl App {
                 Message::Ui(UiMsg::SyncGpodder) => self.gpodder_sync_pre(),
 
                 Message::Ui(UiMsg::Play(pod_id, ep_id, external)) => {
-                    self.play_file(pod_id, ep_id, external)
+                    self.play_file(pod_id, ep_id, external, None)
+                }
+
+                Message::Ui(UiMsg::Resume(pod_id, ep_id, position)) => {
+                    self.play_file(pod_id, ep_id, false, Some(position))
+                }
+
+                Message::Ui(UiMsg::FetchArtwork(url)) => {
+                    self.fetch_artwork(url);
+                    Ok(())
+                }
+                Message::Ui(UiMsg::OpenLink(url)) => {
+                    if let Err(err) = play_file::execute(&self.config.link_command, &url) {
+                        self.notif_to_ui(format!("Could not open link: {err}"), true);
+                    }
+                    Ok(())
                 }
 
                 Message::Ui(UiMsg::MarkPlayed(pod_id, ep_id, played)) => {
@@ -184,17 +244,69 @@ impl App {
                     self.mark_all_played(pod_id, played)
                 }
 
-                Message::Ui(UiMsg::UpdatePosition(pod_id, ep_id, position)) => {
-                    self.update_position(pod_id, ep_id, position)
+                Message::Ui(UiMsg::MarkPlayedBatch(episodes, played)) => {
+                    self.mark_played_batch(episodes, played)
+                }
+
+                Message::Ui(UiMsg::UpdatePosition(pod_id, ep_id, position, time_saved)) => {
+                    self.update_position(pod_id, ep_id, position, time_saved)
+                }
+
+                Message::Ui(UiMsg::Checkpoint(pod_id, ep_id, position)) => {
+                    self.checkpoint(pod_id, ep_id, position)
+                }
+
+                Message::Ui(UiMsg::SetSpeed(pod_id, speed)) => self.set_speed(pod_id, speed),
+
+                Message::Ui(UiMsg::SetVolumeOffset(pod_id, offset)) => {
+                    self.set_volume_offset(pod_id, offset)
+                }
+
+                Message::Ui(UiMsg::SetEqualizer(pod_id, preset)) => {
+                    self.set_equalizer(pod_id, preset)
                 }
 
-                Message::Ui(UiMsg::Download(pod_id, ep_id)) => self.download(pod_id, Some(ep_id)),
+                Message::Ui(UiMsg::SetSkips(pod_id, intro, outro)) => {
+                    self.set_skips(pod_id, intro, outro)
+                }
+
+                Message::Ui(UiMsg::Download(pod_id, ep_id)) => {
+                    self.download(pod_id, Some(&[ep_id]))
+                }
 
                 Message::Ui(UiMsg::DownloadAll(pod_id)) => self.download(pod_id, None),
 
+                Message::Ui(UiMsg::DownloadBatch(episodes)) => group_by_podcast(episodes)
+                    .into_iter()
+                    .try_for_each(|(pod_id, ep_ids)| self.download(pod_id, Some(&ep_ids))),
+
+                Message::Ui(UiMsg::CancelDownload(ep_id)) => {
+                    self.cancel_download(ep_id);
+                    Ok(())
+                }
+
                 Message::Dl(msg) => match msg {
+                    DownloadMsg::Started(ep_id, total) => {
+                        self.update_download(ep_id, |dl| dl.start(total));
+                        Ok(())
+                    }
+                    DownloadMsg::Progress(ep_id, done) => {
+                        self.update_download(ep_id, |dl| dl.done = done);
+                        Ok(())
+                    }
                     DownloadMsg::Complete(ep_data) => self.download_complete(ep_data),
+                    DownloadMsg::Streamed(ep_id, path, mime_type) => {
+                        self.stream_complete(ep_id, path, mime_type.as_deref())
+                    }
+                    DownloadMsg::Cancelled(ep) => {
+                        self.update_download(ep.id, |dl| dl.status = DownloadStatus::Cancelled);
+                        self.update_tracker_notif();
+                        self.notif_to_ui(format!("Cancelled download of \"{}\"", ep.title), false);
+                        Ok(())
+                    }
                     DownloadMsg::Error(ep, err) => {
+                        self.update_download(ep.id, |dl| dl.status = DownloadStatus::Failed);
+                        self.update_tracker_notif();
                         let msg = match err {
                             DownloadError::Response => {
                                 format!("Error sending download request. {}", ep.url)
@@ -213,70 +325,32 @@ impl App {
 
                 Message::Ui(UiMsg::Delete(pod_id, ep_id)) => self.delete_file(pod_id, ep_id),
                 Message::Ui(UiMsg::DeleteAll(pod_id)) => self.delete_files(pod_id),
+                Message::Ui(UiMsg::DeleteBatch(episodes)) => self.delete_files_batch(episodes),
 
                 Message::Ui(UiMsg::RemovePodcast(pod_id, delete_files)) => {
                     self.remove_podcast(pod_id, delete_files)
                 }
 
-                Message::Ui(UiMsg::FilterChange(filter_type)) => {
-                    let new_filter;
-                    let message;
-                    match filter_type {
-                        // We need to handle these separately because the order
-                        // that makes the most sense to me is different: played
-                        // goes from all -> neg -> pos. Downloaded goes from all
-                        // -> pos -> neg; this is purely based on the idea that
-                        // people are most likely to want to specifically find
-                        // unplayed episodes, or downloaded episodes
-                        FilterType::Played => {
-                            match self.filters.played {
-                                FilterStatus::All => {
-                                    new_filter = FilterStatus::NegativeCases;
-                                    message = "Unplayed only";
-                                }
-                                FilterStatus::NegativeCases => {
-                                    new_filter = FilterStatus::PositiveCases;
-                                    message = "Played only";
-                                }
-                                FilterStatus::PositiveCases => {
-                                    new_filter = FilterStatus::All;
-                                    message = "Played and unplayed";
-                                }
-                            }
-                            self.filters.played = new_filter;
-                        }
-                        FilterType::Downloaded => {
-                            match self.filters.downloaded {
-                                FilterStatus::All => {
-                                    new_filter = FilterStatus::PositiveCases;
-                                    message = "Downloaded only";
-                                }
-                                FilterStatus::PositiveCases => {
-                                    new_filter = FilterStatus::NegativeCases;
-                                    message = "Undownloaded only";
-                                }
-                                FilterStatus::NegativeCases => {
-                                    new_filter = FilterStatus::All;
-                                    message = "Downloaded and undownloaded";
-                                }
-                            }
-                            self.filters.downloaded = new_filter;
-                        }
-                    }
-                    // TODO: filters update filtered_order in LockVec but the UI
-                    // renders using the unfiltered order (map(..., false)). The UI
-                    // needs to use map(..., true) and len(true) to actually apply filters.
-                    self.notif_to_ui(format!("Filter: {message}"), false);
-                    self.update_filters(self.filters, false);
-                    Ok(())
+                Message::Ui(UiMsg::FilterChange(panel, filter_type)) => {
+                    self.change_filter(panel, filter_type)
                 }
+                Message::Ui(UiMsg::SortChange(panel, pod_id)) => self.change_sort(panel, pod_id),
                 Message::Ui(UiMsg::QueueModified) => self.write_queue(),
-                Message::Ui(UiMsg::Noop) => Ok(()),
+                Message::Player(PlayerEvent::Finished) => self.db.clear_checkpoint(),
+                Message::Player(PlayerEvent::Error(reason)) => {
+                    self.notif_to_ui(format!("Playback failed: {reason}"), true);
+                    Ok(())
+                }
+                Message::Ui(UiMsg::Noop) | Message::Player(_) => Ok(()),
                 Message::Gpodder(GpodderMsg::SubscriptionChanges(
                     subscription_changes,
                     episode_actions,
                     timestamp,
                 )) => self.gpodder_sync_pos(subscription_changes, episode_actions, timestamp),
+                Message::Chapters(pod_id, ep_id, chapters) => {
+                    self.chapters_loaded(pod_id, ep_id, chapters)
+                }
+                Message::Artwork(url, fetched) => self.artwork_fetched(url, fetched),
                 Message::Gpodder(GpodderMsg::Error(msg)) => {
                     self.notif_to_ui(msg, true);
                     Ok(())
@@ -336,7 +410,7 @@ impl App {
 
     /// Updates the persistent notification about downloading files.
     pub fn update_tracker_notif(&self) {
-        let dl_len = self.download_tracker.len();
+        let dl_len = self.pending_downloads();
         let dl_plural = if dl_len > 1 { "s" } else { "" };
 
         if dl_len > 0 {
@@ -352,46 +426,99 @@ impl App {
             let cur_unplayed = get_unplayed_episodes(&self.podcasts);
             self.unplayed.replace_all_arc(cur_unplayed);
         }
-        self.unplayed.sort();
-        self.unplayed.reverse();
+        self.unplayed.sort_by_mode(self.unplayed_sort);
+        // the podcasts may be ordered by their unplayed episodes
+        self.podcasts.sort_by_mode(self.podcast_sort);
+    }
+
+    /// Switches a list to its next order, and saves it for the next start.
+    /// The episodes are ordered per podcast.
+    fn change_sort(&mut self, panel: FilterPanel, pod_id: Option<i64>) -> Result<()> {
+        let mode = match panel {
+            FilterPanel::Podcasts => {
+                self.podcast_sort = self.podcast_sort.next(panel);
+                self.podcasts.sort_by_mode(self.podcast_sort);
+                self.podcast_sort
+            }
+            FilterPanel::Unplayed => {
+                self.unplayed_sort = self.unplayed_sort.next(panel);
+                self.unplayed.sort_by_mode(self.unplayed_sort);
+                self.unplayed_sort
+            }
+            FilterPanel::Episodes => {
+                let pod_id = pod_id.ok_or_else(|| anyhow!("No podcast to sort"))?;
+                let pod = self
+                    .podcasts
+                    .get(pod_id)
+                    .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?;
+                let mut pod = pod.write().expect("RwLock write should not fail");
+                pod.sort = pod.sort.next(panel);
+                pod.episodes.sort_by_mode(pod.sort);
+                self.db.set_podcast_sort(pod_id, pod.sort)?;
+                pod.sort
+            }
+        };
+        self.notif_to_ui(format!("Sort: {}", mode.label()), false);
+        self.tx_to_ui.send(MainMessage::Sort(panel, mode))?;
+        match panel.sort_param_key() {
+            Some(key) => self.db.set_param(key, mode.name()),
+            None => Ok(()),
+        }
+    }
+
+    /// Switches a filter of a list, and saves it for the next start.
+    fn change_filter(&mut self, panel: FilterPanel, filter_type: FilterType) -> Result<()> {
+        let filters = self.filters.get_mut(panel);
+        let message = filters.toggle(filter_type);
+        let param = filters.to_param();
+        self.notif_to_ui(format!("Filter: {message}"), false);
+        self.tx_to_ui.send(MainMessage::Filters(self.filters))?;
+        self.update_filters(self.filters, false);
+        self.db.set_param(panel.param_key(), &param)
     }
 
     // Updates the user-selected filters to show only played/unplayed or
     // downloaded/not downloaded episodes.
     // TODO: this needs to be optimized, I think it is provoking screen issues
-    pub fn update_filters(&self, filters: Filters, in_loop: bool) {
-        {
-            let current_time = current_time_ms();
-            if in_loop && current_time - self.last_filter_time_ms.get() < 200 {
-                return;
-            }
-            self.last_filter_time_ms.set(current_time);
-
-            let pod_map = self.podcasts.borrow_map();
-            for pod in pod_map.values() {
-                let episodes = &pod.read().expect("RwLock read should not fail").episodes;
-                let new_filter = episodes.filter_map(|ep| {
-                    let ep = ep.read().expect("RwLock read should not fail");
-                    let play_filter = match filters.played {
-                        FilterStatus::All => false,
-                        FilterStatus::PositiveCases => !ep.is_played(),
-                        FilterStatus::NegativeCases => ep.is_played(),
-                    };
-                    let download_filter = match filters.downloaded {
-                        FilterStatus::All => false,
-                        FilterStatus::PositiveCases => ep.path.is_none(),
-                        FilterStatus::NegativeCases => ep.path.is_some(),
-                    };
-                    if play_filter | download_filter {
-                        None
-                    } else {
-                        Some(ep.id)
-                    }
-                });
-                let mut filtered_order = episodes.borrow_filtered_order();
-                *filtered_order = new_filter;
+    pub fn update_filters(&self, filters: PanelFilters, in_loop: bool) {
+        let current_time = current_time_ms();
+        if in_loop && current_time - self.last_filter_time_ms.get() < 200 {
+            return;
+        }
+        self.last_filter_time_ms.set(current_time);
+
+        let episode_filter = |filters: Filters| {
+            move |ep: &Arc<RwLock<Episode>>| {
+                let ep = ep.read().expect("RwLock read should not fail");
+                filters
+                    .allows(ep.is_played(), ep.path.is_some())
+                    .then_some(ep.id)
             }
+        };
+        let pod_map = self.podcasts.borrow_map();
+        for pod in pod_map.values() {
+            let episodes = &pod.read().expect("RwLock read should not fail").episodes;
+            let new_filter = episodes.filter_map(episode_filter(filters.episodes));
+            *episodes.borrow_filtered_order() = new_filter;
         }
+        drop(pod_map);
+
+        let new_filter = self.unplayed.filter_map(episode_filter(filters.unplayed));
+        *self.unplayed.borrow_filtered_order() = new_filter;
+
+        // a podcast counts as downloaded if any of its episodes is
+        let new_filter = self.podcasts.filter_map(|pod| {
+            let pod = pod.read().expect("RwLock read should not fail");
+            let downloaded = pod
+                .episodes
+                .map(|ep| ep.path.is_some(), false)
+                .contains(&true);
+            filters
+                .podcasts
+                .allows(pod.is_played(), downloaded)
+                .then_some(pod.id)
+        });
+        *self.podcasts.borrow_filtered_order() = new_filter;
     }
 
     fn update_queue(&self) {
@@ -404,4 +531,48 @@ impl App {
             }
         }
     }
+
+    /// Starts downloading artwork, unless it is being downloaded already.
+    fn fetch_artwork(&mut self, url: String) {
+        if self.artwork_tracker.insert(url.clone()) {
+            artwork::fetch_artwork(url, self.tx_to_main.clone());
+        }
+    }
+
+    /// Tells the UI the artwork can be shown, or that it is missing.
+    fn artwork_fetched(&mut self, url: String, fetched: bool) -> Result<()> {
+        self.artwork_tracker.remove(&url);
+        self.tx_to_ui.send(MainMessage::Artwork(url, fetched))?;
+        Ok(())
+    }
+}
+
+/// Groups episodes given with their podcast by podcast
+fn group_by_podcast(episodes: Vec<(i64, i64)>) -> HashMap<i64, Vec<i64>> {
+    let mut grouped: HashMap<i64, Vec<i64>> = HashMap::new();
+    for (pod_id, ep_id) in episodes {
+        grouped.entry(pod_id).or_default().push(ep_id);
+    }
+    grouped
+}
+
+/// Reads the filters saved for each list, none are active if they were
+/// never saved
+fn load_filters(db: &Database) -> PanelFilters {
+    let mut filters = PanelFilters::default();
+    for panel in FilterPanel::ALL {
+        if let Ok(param) = db.get_param(panel.param_key()) {
+            *filters.get_mut(panel) = Filters::from_param(&param);
+        }
+    }
+    filters
+}
+
+/// Reads the order saved for a list, its default one if it was never saved
+fn load_sort(db: &Database, panel: FilterPanel) -> SortMode {
+    panel
+        .sort_param_key()
+        .and_then(|key| db.get_param(key).ok())
+        .and_then(|name| SortMode::from_name(&name))
+        .unwrap_or_else(|| SortMode::default_for(panel))
 }
@@ -1,7 +1,12 @@
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::Utc;
 
 use super::{App, GpodderRequest, HashMap, MAX_DURATION, MainMessage, Result, anyhow, play_file};
+use crate::chapters::{self, Chapter};
 use crate::gpodder::EpisodePlayedData;
+use crate::player::EqPreset;
 use crate::types::Episode;
 
 impl App {
@@ -51,8 +56,10 @@ impl App {
 
     /// Attempts to play the given podcast episode via the built-in player or, if external is set,
     /// via the configured play command.
-    pub fn play_file(&self, pod_id: i64, ep_id: i64, external: bool) -> Result<()> {
-        let (ep_path, ep_url) = {
+    pub fn play_file(
+        &mut self, pod_id: i64, ep_id: i64, external: bool, resume_at: Option<Duration>,
+    ) -> Result<()> {
+        let (ep_path, ep_url, load_chapters) = {
             let pod = self
                 .podcasts
                 .get(pod_id)
@@ -67,7 +74,9 @@ impl App {
             if Some(episode.position) == episode.duration {
                 episode.position = 0;
             }
-            (episode.path.clone(), episode.url.clone())
+            let load_chapters = !episode.chapters_checked
+                && (episode.path.is_some() || episode.chapters_url.is_some());
+            (episode.path.clone(), episode.url.clone(), load_chapters)
         };
 
         let ep_path_or_url = match ep_path {
@@ -94,7 +103,58 @@ impl App {
                 self.notif_to_ui(format!("Could not mark episode played: {err}"), true);
             }
         } else {
-            self.tx_to_ui.send(MainMessage::PlayCurrent(ep_id))?;
+            self.tx_to_ui
+                .send(MainMessage::PlayCurrent(ep_id, resume_at))?;
+            if load_chapters {
+                self.load_chapters(pod_id, ep_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts loading the chapters of an episode, unless they are being
+    /// loaded already.
+    fn load_chapters(&mut self, pod_id: i64, ep_id: i64) -> Result<()> {
+        if !self.chapter_tracker.insert(ep_id) {
+            return Ok(());
+        }
+        let (path, url) = self
+            .podcasts
+            .get(pod_id)
+            .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?
+            .read()
+            .expect("RwLock read should not fail")
+            .episodes
+            .map_single(ep_id, |ep| (ep.path.clone(), ep.chapters_url.clone()))
+            .ok_or_else(|| anyhow!("Failed to get ep_id: {ep_id}"))?;
+        chapters::load_chapters(pod_id, ep_id, path, url, self.tx_to_main.clone());
+        Ok(())
+    }
+
+    /// Stores the chapters found for an episode, also when there are none
+    /// so they are not looked for again.
+    pub fn chapters_loaded(
+        &mut self, pod_id: i64, ep_id: i64, chapters: Option<Vec<Chapter>>,
+    ) -> Result<()> {
+        self.chapter_tracker.remove(&ep_id);
+        let Some(chapters) = chapters else {
+            return Ok(());
+        };
+        self.db.set_chapters(ep_id, &chapters)?;
+        let podcast = self
+            .podcasts
+            .get(pod_id)
+            .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?;
+        let episode = podcast
+            .read()
+            .expect("RwLock read should not fail")
+            .episodes
+            .get(ep_id)
+            .ok_or_else(|| anyhow!("Failed to get ep_id: {ep_id}"))?;
+        {
+            let mut episode = episode.write().expect("RwLock write should not fail");
+            episode.chapters = chapters;
+            episode.chapters_checked = true;
         }
         Ok(())
     }
@@ -229,55 +289,64 @@ impl App {
     /// played/unplayed, sending this info to the database and updating in
     /// self.podcasts
     pub fn mark_all_played(&mut self, pod_id: i64, played: bool) -> Result<()> {
-        let mut changed = false;
-        let (sync_list, db_list) = {
-            let podcast = self
-                .podcasts
-                .get(pod_id)
-                .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?;
-            let podcast_url = podcast
-                .read()
-                .expect("RwLock read should not fail")
-                .url
-                .clone();
+        let episodes = self
+            .podcasts
+            .get(pod_id)
+            .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?
+            .read()
+            .expect("RwLock read should not fail")
+            .episodes
+            .map(|ep| (pod_id, ep.id), false);
+        self.mark_played_batch(episodes, played)
+    }
 
-            let mut sync_list = Vec::new();
-            let mut db_list = Vec::new();
-            let episodes = &podcast
-                .read()
-                .expect("RwLock read should not fail")
-                .episodes;
-            for (ep_id, w_episode) in episodes.borrow_map().iter_mut() {
-                {
-                    let mut episode = w_episode.write().expect("RwLock write should not fail");
-                    if episode.played != played {
-                        changed = true;
-                        episode.played = played;
-                    }
+    /// Marks the given episodes, each with its podcast, as played/unplayed
+    /// in a single database transaction and gpodder request.
+    pub fn mark_played_batch(&mut self, episodes: Vec<(i64, i64)>, played: bool) -> Result<()> {
+        let mut changed = false;
+        let mut sync_list = Vec::new();
+        let mut db_list = Vec::new();
+        for (pod_id, ep_id) in episodes {
+            let (podcast_url, w_episode) = {
+                let podcast = self
+                    .podcasts
+                    .get(pod_id)
+                    .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?;
+                let podcast = podcast.read().expect("RwLock read should not fail");
+                let w_episode = podcast
+                    .episodes
+                    .get(ep_id)
+                    .ok_or_else(|| anyhow!("Failed to get ep_id: {ep_id}"))?;
+                (podcast.url.clone(), w_episode)
+            };
+            {
+                let mut episode = w_episode.write().expect("RwLock write should not fail");
+                if episode.played != played {
+                    changed = true;
+                    episode.played = played;
                 }
-                changed |= self.sync_unplayed_episode(*ep_id, w_episode);
+            }
+            changed |= self.sync_unplayed_episode(ep_id, &w_episode);
 
-                let episode = w_episode.read().expect("RwLock read should not fail");
-                if self.config.enable_sync {
-                    let duration = episode.duration.unwrap_or_else(|| {
-                        log::warn!(
-                            "Setting duration to infinity, else cannot mark as played on gpodder"
-                        );
-                        MAX_DURATION
-                    });
-                    let position = if played { duration } else { episode.position };
-                    sync_list.push(EpisodePlayedData {
-                        podcast_url: podcast_url.clone(),
-                        episode_url: episode.url.clone(),
-                        guid: episode.guid.clone(),
-                        position,
-                        duration,
-                    });
-                }
-                db_list.push((*ep_id, episode.position, episode.duration, played));
+            let episode = w_episode.read().expect("RwLock read should not fail");
+            if self.config.enable_sync {
+                let duration = episode.duration.unwrap_or_else(|| {
+                    log::warn!(
+                        "Setting duration to infinity, else cannot mark as played on gpodder"
+                    );
+                    MAX_DURATION
+                });
+                let position = if played { duration } else { episode.position };
+                sync_list.push(EpisodePlayedData {
+                    podcast_url,
+                    episode_url: episode.url.clone(),
+                    guid: episode.guid.clone(),
+                    position,
+                    duration,
+                });
             }
-            (sync_list, db_list)
-        };
+            db_list.push((ep_id, episode.position, episode.duration, played));
+        }
 
         self.apply_played_changes(changed);
         self.db.set_played_status_batch(db_list)?;
@@ -289,7 +358,9 @@ impl App {
         Ok(())
     }
 
-    pub fn update_position(&self, pod_id: i64, ep_id: i64, position: u64) -> Result<()> {
+    pub fn update_position(
+        &self, pod_id: i64, ep_id: i64, position: u64, time_saved: Duration,
+    ) -> Result<()> {
         let mut changed = false;
         let (duration, ep_url, ep_guid, pod_url) = {
             let podcast = self
@@ -312,12 +383,20 @@ impl App {
                     episode.played = true;
                 }
                 episode.position = position;
+                episode.time_saved += time_saved;
+                episode.last_played = Some(Utc::now());
             }
 
             changed |= self.sync_unplayed_episode(ep_id, &w_episode);
             let episode = w_episode.read().expect("RwLock read should not fail");
             self.db
                 .set_played_status(ep_id, episode.position, episode.duration, episode.played)?;
+            if !time_saved.is_zero() {
+                self.db.add_time_saved(ep_id, time_saved)?;
+            }
+            if let Some(last_played) = episode.last_played {
+                self.db.set_last_played(ep_id, last_played)?;
+            }
             (
                 episode.duration,
                 episode.url.clone(),
@@ -330,4 +409,90 @@ impl App {
         self.gpodder_mark_played(pod_url, ep_url, ep_guid, position, duration)?;
         Ok(())
     }
+
+    /// Saves the position of the episode being played, without syncing
+    /// it, so it is not lost if the app gets killed.
+    pub fn checkpoint(&mut self, pod_id: i64, ep_id: i64, position: Duration) -> Result<()> {
+        let episode = self
+            .podcasts
+            .get(pod_id)
+            .ok_or_else(|| anyhow!("Failed to get pod_id: {pod_id}"))?
+            .read()
+            .expect("RwLock read should not fail")
+            .episodes
+            .get(ep_id)
+            .ok_or_else(|| anyhow!("Failed to get ep_id: {ep_id}"))?;
+        let now = Utc::now();
+        {
+            let mut episode = episode.write().expect("RwLock write should not fail");
+            episode.position = position.as_secs();
+            episode.last_played = Some(now);
+        }
+        self.db.set_checkpoint(ep_id, position, now)
+    }
+
+    /// Asks the UI to offer resuming the episode that was playing when the
+    /// app last closed, unless it was played since.
+    pub fn offer_resume(&self) -> Result<()> {
+        let Some((ep_id, position)) = self.db.get_checkpoint()? else {
+            return Ok(());
+        };
+        let Some(episode) = self.podcasts.get_episodes_map().remove(&ep_id) else {
+            return self.db.clear_checkpoint();
+        };
+        let (pod_id, title, played) = {
+            let episode = episode.read().expect("RwLock read should not fail");
+            (episode.pod_id, episode.title.clone(), episode.played)
+        };
+        if played {
+            return self.db.clear_checkpoint();
+        }
+        self.tx_to_ui
+            .send(MainMessage::OfferResume(pod_id, ep_id, title, position))?;
+        Ok(())
+    }
+
+    /// Stores the playback speed chosen for the episodes of a podcast.
+    pub fn set_speed(&self, pod_id: i64, speed: f32) -> Result<()> {
+        self.db.set_podcast_speed(pod_id, speed)?;
+        if let Some(podcast) = self.podcasts.get(pod_id) {
+            podcast.write().expect("RwLock write should not fail").speed = speed;
+        }
+        Ok(())
+    }
+
+    /// Stores the volume adjustment chosen for the episodes of a podcast.
+    pub fn set_volume_offset(&self, pod_id: i64, offset: i32) -> Result<()> {
+        self.db.set_podcast_volume_offset(pod_id, offset)?;
+        if let Some(podcast) = self.podcasts.get(pod_id) {
+            podcast
+                .write()
+                .expect("RwLock write should not fail")
+                .volume_offset = offset;
+        }
+        Ok(())
+    }
+
+    /// Stores the equalizer preset chosen for the episodes of a podcast.
+    pub fn set_equalizer(&self, pod_id: i64, preset: EqPreset) -> Result<()> {
+        self.db.set_podcast_equalizer(pod_id, preset)?;
+        if let Some(podcast) = self.podcasts.get(pod_id) {
+            podcast
+                .write()
+                .expect("RwLock write should not fail")
+                .equalizer = Some(preset);
+        }
+        Ok(())
+    }
+
+    /// Stores the intro and outro skipped in the episodes of a podcast.
+    pub fn set_skips(&self, pod_id: i64, intro: u64, outro: u64) -> Result<()> {
+        self.db.set_podcast_skips(pod_id, intro, outro)?;
+        if let Some(podcast) = self.podcasts.get(pod_id) {
+            let mut podcast = podcast.write().expect("RwLock write should not fail");
+            podcast.skip_intro = intro;
+            podcast.skip_outro = outro;
+        }
+        Ok(())
+    }
 }
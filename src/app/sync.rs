@@ -135,6 +135,7 @@ impl App {
         if let Some(podcast) = self.podcasts.get(pod_id) {
             let pod = podcast.read().expect("RwLock read should not fail");
             pod.episodes.replace_all(episodes);
+            pod.episodes.sort_by_mode(pod.sort);
         }
         Ok(())
     }
@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use image::{ImageFormat, RgbImage};
+
+use crate::types::Message;
+use crate::utils::APP_USER_AGENT;
+
+/// Largest side of the cached artwork, in pixels
+const ARTWORK_SIZE: u32 = 600;
+
+/// How artwork is drawn in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtworkMode {
+    /// Picks one of the others from the terminal in use
+    Auto,
+    Kitty,
+    Sixel,
+    Iterm,
+    /// Unicode half blocks, two pixels per cell, which work anywhere
+    /// with true colors
+    HalfBlocks,
+    Off,
+}
+
+impl ArtworkMode {
+    const ALL: [Self; 6] = [
+        Self::Auto,
+        Self::Kitty,
+        Self::Sixel,
+        Self::Iterm,
+        Self::HalfBlocks,
+        Self::Off,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Kitty => "kitty",
+            Self::Sixel => "sixel",
+            Self::Iterm => "iterm",
+            Self::HalfBlocks => "halfblocks",
+            Self::Off => "off",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    /// Replaces auto with the graphics protocol the terminal is expected
+    /// to support
+    pub fn resolve(self) -> Self {
+        match self {
+            Self::Auto => detect(|key| std::env::var(key).ok()),
+            mode => mode,
+        }
+    }
+}
+
+/// Guesses the graphics protocol of the terminal from its environment.
+/// Multiplexers do not pass the images through reliably, so they get
+/// half blocks.
+fn detect(var: impl Fn(&str) -> Option<String>) -> ArtworkMode {
+    let term = var("TERM").unwrap_or_default();
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+    if var("TMUX").is_some() || var("ZELLIJ").is_some() || term.starts_with("screen") {
+        ArtworkMode::HalfBlocks
+    } else if var("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || program == "ghostty" {
+        ArtworkMode::Kitty
+    } else if program == "iTerm.app"
+        || program == "WezTerm"
+        || var("LC_TERMINAL").as_deref() == Some("iTerm2")
+    {
+        ArtworkMode::Iterm
+    } else if ["foot", "mlterm", "contour", "yaft"]
+        .iter()
+        .any(|name| term.starts_with(name))
+    {
+        ArtworkMode::Sixel
+    } else {
+        ArtworkMode::HalfBlocks
+    }
+}
+
+/// 64-bit FNV-1a hash, stable across runs unlike the std hasher
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Where the artwork at a url is cached, None if the system has no
+/// cache directory
+pub fn cached_path(url: &str) -> Option<PathBuf> {
+    let mut path = dirs::cache_dir()?;
+    path.push("hullcaster");
+    path.push("artwork");
+    path.push(format!("{:016x}.png", fnv1a(url)));
+    Some(path)
+}
+
+/// Opens artwork from the cache.
+pub fn load(path: &Path) -> Result<RgbImage> {
+    Ok(image::open(path)?.into_rgb8())
+}
+
+/// Spawns a new async task downloading artwork into the cache. Whether
+/// it worked is sent back to the main controller.
+pub fn fetch_artwork(url: String, tx_to_main: mpsc::Sender<Message>) {
+    tokio::spawn(async move {
+        let fetched = fetch(&url)
+            .await
+            .inspect_err(|err| log::warn!("Could not fetch artwork from {url}: {err}"))
+            .is_ok();
+        if tx_to_main.send(Message::Artwork(url, fetched)).is_err() {
+            log::error!("Failed to send artwork message: channel closed");
+        }
+    });
+}
+
+async fn fetch(url: &str) -> Result<()> {
+    let path = cached_path(url).ok_or_else(|| anyhow!("No cache directory"))?;
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(30))
+        .user_agent(APP_USER_AGENT)
+        .build()?;
+    let data = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    tokio::task::spawn_blocking(move || save(&data, &path)).await?
+}
+
+/// Scales down an image and stores it as png. It is written next to its
+/// final path first, so a half written file is never read.
+fn save(data: &[u8], path: &Path) -> Result<()> {
+    let mut art = image::load_from_memory(data)?;
+    if art.width() > ARTWORK_SIZE || art.height() > ARTWORK_SIZE {
+        art = art.thumbnail(ARTWORK_SIZE, ARTWORK_SIZE);
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("part");
+    art.into_rgb8()
+        .save_with_format(&partial, ImageFormat::Png)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    fn detect_with(vars: &[(&str, &str)]) -> ArtworkMode {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        detect(|key| vars.get(key).map(std::string::ToString::to_string))
+    }
+
+    #[test]
+    fn modes_by_name() {
+        for mode in ArtworkMode::ALL {
+            assert_eq!(ArtworkMode::from_name(mode.name()), Some(mode));
+        }
+        assert_eq!(ArtworkMode::from_name("ascii"), None);
+        assert_eq!(ArtworkMode::Sixel.resolve(), ArtworkMode::Sixel);
+    }
+
+    #[test]
+    fn detects_terminal() {
+        assert_eq!(detect_with(&[("TERM", "xterm-kitty")]), ArtworkMode::Kitty);
+        assert_eq!(
+            detect_with(&[("TERM", "xterm-256color"), ("KITTY_WINDOW_ID", "1")]),
+            ArtworkMode::Kitty
+        );
+        assert_eq!(
+            detect_with(&[("TERM_PROGRAM", "iTerm.app")]),
+            ArtworkMode::Iterm
+        );
+        assert_eq!(detect_with(&[("TERM", "foot-extra")]), ArtworkMode::Sixel);
+        assert_eq!(
+            detect_with(&[("TERM", "xterm-256color")]),
+            ArtworkMode::HalfBlocks
+        );
+        assert_eq!(
+            detect_with(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux")]),
+            ArtworkMode::HalfBlocks
+        );
+    }
+
+    #[test]
+    fn cache_names_stable() {
+        assert_eq!(fnv1a(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(
+            cached_path("https://example.com/a.jpg"),
+            cached_path("https://example.com/b.jpg")
+        );
+    }
+
+    #[test]
+    fn saves_scaled_down() {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(1200, 900, image::Rgb([200, 10, 10]))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!("hullcaster-art-{}", std::process::id()));
+        let path = dir.join("art.png");
+        save(&data, &path).unwrap();
+
+        let art = load(&path).unwrap();
+        assert_eq!(art.dimensions(), (600, 450));
+        assert!(!path.with_extension("part").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert!(save(b"not an image", &path).is_err());
+    }
+}
@@ -0,0 +1,826 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::config::CHAPTER_RESTART;
+use crate::types::Message;
+use crate::utils::APP_USER_AGENT;
+
+/// Most chapters read from an MP4 chapter track, more only come from a
+/// corrupt file
+const MAX_CHAPTERS: usize = 1000;
+
+/// Longest sample of a text track: the 16-bit length and the text
+const MAX_TEXT_SAMPLE: usize = 2 + u16::MAX as usize;
+
+/// Chapter of an episode, from the feed or embedded in the audio file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    /// Seconds from the start of the episode
+    pub start: u64,
+    pub title: String,
+}
+
+/// Sorts chapters by start, dropping any that start at the same time as
+/// an earlier one.
+fn sorted(mut chapters: Vec<Chapter>) -> Vec<Chapter> {
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters.dedup_by_key(|chapter| chapter.start);
+    chapters
+}
+
+/// Index of the chapter playing at `position`.
+pub fn current(chapters: &[Chapter], position: u64) -> Option<usize> {
+    chapters
+        .iter()
+        .rposition(|chapter| chapter.start <= position)
+}
+
+/// Start of the first chapter after `position`.
+pub fn next_start(chapters: &[Chapter], position: u64) -> Option<u64> {
+    chapters
+        .iter()
+        .map(|chapter| chapter.start)
+        .find(|&start| start > position)
+}
+
+/// Start of the chapter playing at `position`, or of the one before it if
+/// the current chapter only just started.
+pub fn previous_start(chapters: &[Chapter], position: u64) -> Option<u64> {
+    let index = current(chapters, position)?;
+    let start = chapters[index].start;
+    if position >= start + CHAPTER_RESTART || index == 0 {
+        Some(start)
+    } else {
+        Some(chapters[index - 1].start)
+    }
+}
+
+/// Spawns a new async task looking for the chapters of an episode, first
+/// embedded in the downloaded file and then at the chapters URL of the
+/// feed. The result is sent back to the main controller, even if empty,
+/// or None if the chapters URL could not be fetched.
+pub fn load_chapters(
+    pod_id: i64, ep_id: i64, path: Option<PathBuf>, url: Option<String>,
+    tx_to_main: mpsc::Sender<Message>,
+) {
+    tokio::spawn(async move {
+        let from_file = match path {
+            Some(path) => tokio::task::spawn_blocking(move || chapters_from_file(&path))
+                .await
+                .ok()
+                .and_then(|chapters| {
+                    chapters
+                        .inspect_err(|err| log::warn!("Could not read chapters: {err}"))
+                        .ok()
+                })
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let chapters = match url {
+            Some(url) if from_file.is_empty() => fetch_chapters(&url)
+                .await
+                .inspect_err(|err| log::warn!("Could not fetch chapters from {url}: {err}"))
+                .ok(),
+            _ => Some(from_file),
+        };
+        if tx_to_main
+            .send(Message::Chapters(pod_id, ep_id, chapters))
+            .is_err()
+        {
+            log::error!("Failed to send chapters message: channel closed");
+        }
+    });
+}
+
+/// Chapters file as defined by the Podcasting 2.0 namespace
+#[derive(Deserialize)]
+struct ChaptersJson {
+    chapters: Vec<ChapterJson>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChapterJson {
+    start_time: f64,
+    title: Option<String>,
+    /// Chapters left out of the table of contents only carry artwork or
+    /// links
+    toc: Option<bool>,
+}
+
+/// Parses a Podcasting 2.0 chapters file.
+pub fn parse_chapters_json(data: &[u8]) -> Result<Vec<Chapter>> {
+    let json: ChaptersJson = serde_json::from_slice(data)?;
+    let chapters = json
+        .chapters
+        .into_iter()
+        .filter(|chapter| chapter.toc != Some(false))
+        .enumerate()
+        .map(|(index, chapter)| {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let start = chapter.start_time.max(0.0) as u64;
+            Chapter {
+                start,
+                title: chapter
+                    .title
+                    .unwrap_or_else(|| format!("Chapter {}", index + 1)),
+            }
+        })
+        .collect();
+    Ok(sorted(chapters))
+}
+
+/// Downloads and parses the chapters file linked from a feed.
+async fn fetch_chapters(url: &str) -> Result<Vec<Chapter>> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(20))
+        .user_agent(APP_USER_AGENT)
+        .build()?;
+    let data = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    parse_chapters_json(&data)
+}
+
+/// Reads the chapters embedded in an audio file, from the `ID3v2` tag of
+/// MP3 files or the chapter track or list of MP4 files.
+pub fn chapters_from_file(path: &Path) -> Result<Vec<Chapter>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut head = [0; 8];
+    reader.read_exact(&mut head)?;
+    reader.seek(SeekFrom::Start(0))?;
+    if head.starts_with(b"ID3") {
+        id3_chapters(&mut reader)
+    } else if &head[4..] == b"ftyp" {
+        mp4_chapters(&mut reader)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+fn syncsafe(bytes: [u8; 4]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 7) | u32::from(byte & 0x7f))
+}
+
+/// Reads the CHAP frames of an `ID3v2.3` or `ID3v2.4` tag. If the tag has a
+/// top level CTOC frame, only the chapters it lists are kept.
+fn id3_chapters(reader: &mut impl Read) -> Result<Vec<Chapter>> {
+    let mut header = [0; 10];
+    reader.read_exact(&mut header)?;
+    let (version, flags) = (header[3], header[5]);
+    if !(3..=4).contains(&version) {
+        return Ok(Vec::new());
+    }
+    let mut tag = vec![0; syncsafe([header[6], header[7], header[8], header[9]]) as usize];
+    reader.read_exact(&mut tag)?;
+    if version == 3 && flags & 0x80 != 0 {
+        // unsynchronisation inserted a zero after every 0xff
+        let mut previous = 0;
+        tag.retain(|&byte| {
+            let keep = !(previous == 0xff && byte == 0);
+            previous = byte;
+            keep
+        });
+    }
+    let mut frames = &tag[..];
+    if flags & 0x40 != 0 {
+        // the extended header size does not count itself in ID3v2.3
+        let Some((&size, _)) = frames.split_first_chunk::<4>() else {
+            return Ok(Vec::new());
+        };
+        let skip = if version == 3 {
+            u32::from_be_bytes(size) as usize + 4
+        } else {
+            syncsafe(size) as usize
+        };
+        frames = frames.get(skip..).unwrap_or_default();
+    }
+
+    let mut chapters = Vec::new();
+    let mut toc = None;
+    for (id, body) in id3_frames(frames, version) {
+        match &id {
+            b"CHAP" => chapters.extend(parse_chap(body, version)),
+            b"CTOC" => {
+                if let Some((true, children)) = parse_ctoc(body) {
+                    toc = Some(children);
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(toc) = toc {
+        chapters.retain(|(element_id, _)| toc.contains(element_id));
+    }
+    Ok(sorted(
+        chapters.into_iter().map(|(_, chapter)| chapter).collect(),
+    ))
+}
+
+/// Splits `ID3v2` frames into their id and body, stopping at the padding.
+fn id3_frames(mut data: &[u8], version: u8) -> Vec<([u8; 4], &[u8])> {
+    let mut frames = Vec::new();
+    while let Some((header, rest)) = data.split_first_chunk::<10>()
+        && header[0] != 0
+    {
+        let size = [header[4], header[5], header[6], header[7]];
+        let size = if version == 4 {
+            syncsafe(size)
+        } else {
+            u32::from_be_bytes(size)
+        } as usize;
+        let Some(body) = rest.get(..size) else {
+            break;
+        };
+        frames.push(([header[0], header[1], header[2], header[3]], body));
+        data = &rest[size..];
+    }
+    frames
+}
+
+/// Parses a CHAP frame into its element id and chapter, titled by its
+/// TIT2 sub-frame.
+fn parse_chap(body: &[u8], version: u8) -> Option<(&[u8], Chapter)> {
+    let end = body.iter().position(|&byte| byte == 0)?;
+    let (element_id, rest) = (&body[..end], &body[end + 1..]);
+    // start and end time, then start and end byte offset
+    let (&times, sub_frames) = rest.split_first_chunk::<16>()?;
+    let start_ms = u32::from_be_bytes([times[0], times[1], times[2], times[3]]);
+    let title = id3_frames(sub_frames, version)
+        .into_iter()
+        .find(|(id, _)| id == b"TIT2")
+        .and_then(|(_, body)| decode_text(body))
+        .unwrap_or_default();
+    Some((
+        element_id,
+        Chapter {
+            start: u64::from(start_ms) / 1000,
+            title,
+        },
+    ))
+}
+
+/// Parses a CTOC frame into whether it is the top level table of contents
+/// and the element ids of its entries.
+fn parse_ctoc(body: &[u8]) -> Option<(bool, Vec<&[u8]>)> {
+    let end = body.iter().position(|&byte| byte == 0)?;
+    let (&[flags, count], mut rest) = body[end + 1..].split_first_chunk::<2>()?;
+    let mut children = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let end = rest.iter().position(|&byte| byte == 0)?;
+        children.push(&rest[..end]);
+        rest = &rest[end + 1..];
+    }
+    Some((flags & 0x02 != 0, children))
+}
+
+/// Decodes the body of an `ID3v2` text frame.
+fn decode_text(body: &[u8]) -> Option<String> {
+    let (&encoding, text) = body.split_first()?;
+    let text = match encoding {
+        0 => text.iter().map(|&byte| char::from(byte)).collect(),
+        1 | 2 => {
+            let (big_endian, text) = match text {
+                [0xff, 0xfe, rest @ ..] => (false, rest),
+                [0xfe, 0xff, rest @ ..] => (true, rest),
+                _ => (encoding == 2, text),
+            };
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(text).into_owned(),
+        _ => return None,
+    };
+    Some(text.trim_end_matches('\0').to_string())
+}
+
+/// Reads the chapters of an MP4 file from its `QuickTime` chapter track,
+/// the text track referenced by moov/trak/tref/chap, or else from the Nero
+/// chapter list in moov/udta/chpl.
+fn mp4_chapters<R: Read + Seek>(reader: &mut R) -> Result<Vec<Chapter>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let Some(end) = find_box(reader, file_len, *b"moov")? else {
+        return Ok(Vec::new());
+    };
+    let start = reader.stream_position()?;
+    let mut moov = vec![0; usize::try_from(end.saturating_sub(start))?];
+    reader.read_exact(&mut moov)?;
+
+    let mut chapters = Vec::new();
+    for (start, offset, size) in chapter_track_samples(&moov).unwrap_or_default() {
+        let size = size.min(MAX_TEXT_SAMPLE);
+        if offset.saturating_add(size as u64) > file_len {
+            continue;
+        }
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut sample = vec![0; size];
+        reader.read_exact(&mut sample)?;
+        chapters.push(Chapter {
+            start,
+            title: decode_text_sample(&sample),
+        });
+    }
+    if chapters.is_empty() {
+        chapters = child_box(&moov, &[*b"udta", *b"chpl"])
+            .map(parse_chpl)
+            .unwrap_or_default();
+    }
+    Ok(sorted(chapters))
+}
+
+/// Looks for a box of the given kind between the current position and
+/// `end`. If found, the reader is left at the start of its contents and
+/// the end of the box is returned.
+fn find_box<R: Read + Seek>(reader: &mut R, end: u64, kind: [u8; 4]) -> Result<Option<u64>> {
+    let mut position = reader.stream_position()?;
+    while position + 8 <= end {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+        let (header_size, size) =
+            match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+                // the box extends to the end of its parent
+                0 => (8, end - position),
+                1 => {
+                    let mut size = [0; 8];
+                    reader.read_exact(&mut size)?;
+                    (16, u64::from_be_bytes(size))
+                }
+                size => (8, u64::from(size)),
+            };
+        if size < header_size {
+            break;
+        }
+        if header[4..] == kind {
+            return Ok(Some((position + size).min(end)));
+        }
+        position += size;
+        reader.seek(SeekFrom::Start(position))?;
+    }
+    Ok(None)
+}
+
+/// Splits the contents of an MP4 box into its child boxes.
+fn mp4_boxes(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    while let Some((&header, rest)) = data.split_first_chunk::<8>() {
+        let kind = [header[4], header[5], header[6], header[7]];
+        let (content, size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]])
+        {
+            // the box extends to the end of its parent
+            0 => (rest, rest.len()),
+            1 => {
+                let Some((&size, rest)) = rest.split_first_chunk::<8>() else {
+                    break;
+                };
+                let Some(size) = usize::try_from(u64::from_be_bytes(size))
+                    .ok()
+                    .and_then(|size| size.checked_sub(16))
+                else {
+                    break;
+                };
+                (rest, size)
+            }
+            size => {
+                let Some(size) = (size as usize).checked_sub(8) else {
+                    break;
+                };
+                (rest, size)
+            }
+        };
+        let Some(body) = content.get(..size) else {
+            break;
+        };
+        boxes.push((kind, body));
+        data = &content[size..];
+    }
+    boxes
+}
+
+/// Contents of the box found by following the path of kinds down from
+/// `data`, taking the first box of each kind.
+fn child_box<'a>(data: &'a [u8], path: &[[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter().try_fold(data, |data, kind| {
+        mp4_boxes(data)
+            .into_iter()
+            .find(|(found, _)| found == kind)
+            .map(|(_, body)| body)
+    })
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(*data.get(at..)?.first_chunk::<4>()?))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(*data.get(at..)?.first_chunk::<8>()?))
+}
+
+/// Entries of a full box table with a 32-bit count after the version and
+/// flags, each `width` bytes long.
+fn table(data: &[u8], header: usize, width: usize) -> Option<impl Iterator<Item = &[u8]>> {
+    let count = be_u32(data, header)? as usize;
+    let entries = data.get(header + 4..)?;
+    (entries.len() / width >= count).then(|| entries.chunks_exact(width).take(count))
+}
+
+/// Finds the chapter track of a moov box, and returns the start in
+/// seconds, file offset and size of each of its samples.
+fn chapter_track_samples(moov: &[u8]) -> Option<Vec<(u64, u64, usize)>> {
+    let traks: Vec<&[u8]> = mp4_boxes(moov)
+        .into_iter()
+        .filter(|(kind, _)| kind == b"trak")
+        .map(|(_, body)| body)
+        .collect();
+    let chapter_id = traks
+        .iter()
+        .find_map(|trak| be_u32(child_box(trak, &[*b"tref", *b"chap"])?, 0))?;
+    let trak = traks.iter().find(|trak| {
+        child_box(trak, &[*b"tkhd"]).and_then(|tkhd| {
+            // the track id follows the creation and modification times
+            be_u32(tkhd, if tkhd.first() == Some(&1) { 20 } else { 12 })
+        }) == Some(chapter_id)
+    })?;
+    let mdhd = child_box(trak, &[*b"mdia", *b"mdhd"])?;
+    let timescale = be_u32(mdhd, if mdhd.first() == Some(&1) { 20 } else { 12 })?;
+    let stbl = child_box(trak, &[*b"mdia", *b"minf", *b"stbl"])?;
+
+    let mut starts = Vec::new();
+    let mut time = 0;
+    for entry in table(child_box(stbl, &[*b"stts"])?, 4, 8)? {
+        let count = be_u32(entry, 0)? as usize;
+        let delta = u64::from(be_u32(entry, 4)?);
+        for _ in 0..count.min(MAX_CHAPTERS - starts.len()) {
+            starts.push(time / u64::from(timescale.max(1)));
+            time += delta;
+        }
+    }
+    let stsz = child_box(stbl, &[*b"stsz"])?;
+    let sizes: Vec<usize> = match be_u32(stsz, 4)? {
+        0 => table(stsz, 8, 4)?
+            .take(MAX_CHAPTERS)
+            .map(|entry| be_u32(entry, 0).map(|size| size as usize))
+            .collect::<Option<_>>()?,
+        size => vec![size as usize; (be_u32(stsz, 8)? as usize).min(MAX_CHAPTERS)],
+    };
+    let chunks: Vec<u64> = if let Some(stco) = child_box(stbl, &[*b"stco"]) {
+        table(stco, 4, 4)?
+            .map(|entry| be_u32(entry, 0).map(u64::from))
+            .collect::<Option<_>>()?
+    } else {
+        table(child_box(stbl, &[*b"co64"])?, 4, 8)?
+            .map(|entry| be_u64(entry, 0))
+            .collect::<Option<_>>()?
+    };
+    // first chunk, numbered from 1, and samples in each chunk from there
+    let runs: Vec<(u32, u32)> = table(child_box(stbl, &[*b"stsc"])?, 4, 12)?
+        .map(|entry| Some((be_u32(entry, 0)?, be_u32(entry, 4)?)))
+        .collect::<Option<_>>()?;
+
+    let mut samples = Vec::new();
+    let mut sizes = sizes.into_iter();
+    for (chunk, mut offset) in (1..).zip(chunks) {
+        let per_chunk = runs
+            .iter()
+            .rfind(|(first, _)| *first <= chunk)
+            .map_or(0, |(_, count)| *count);
+        for _ in 0..per_chunk {
+            let Some(size) = sizes.next() else {
+                break;
+            };
+            samples.push((offset, size));
+            offset = offset.saturating_add(size as u64);
+        }
+    }
+    Some(
+        starts
+            .into_iter()
+            .zip(samples)
+            .map(|(start, (offset, size))| (start, offset, size))
+            .collect(),
+    )
+}
+
+/// Decodes a sample of a `QuickTime` text track: the length of the text,
+/// then the text in UTF-8 or in UTF-16 after a byte order mark.
+fn decode_text_sample(sample: &[u8]) -> String {
+    let Some((&length, text)) = sample.split_first_chunk::<2>() else {
+        return String::new();
+    };
+    let text = &text[..usize::from(u16::from_be_bytes(length)).min(text.len())];
+    match text {
+        [0xfe, 0xff, rest @ ..] | [0xff, 0xfe, rest @ ..] => {
+            let big_endian = text[0] == 0xfe;
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+/// Parses the contents of a chpl box, whose start times are in units of
+/// 100 nanoseconds.
+fn parse_chpl(data: &[u8]) -> Vec<Chapter> {
+    let mut chapters = Vec::new();
+    // version and flags, followed by 4 reserved bytes in version 1
+    let skip = if data.first() == Some(&0) { 4 } else { 8 };
+    let Some((&count, mut rest)) = data.get(skip..).and_then(<[u8]>::split_first) else {
+        return chapters;
+    };
+    for _ in 0..count {
+        let Some((&start, after_start)) = rest.split_first_chunk::<8>() else {
+            break;
+        };
+        let Some((&length, after_length)) = after_start.split_first() else {
+            break;
+        };
+        let Some(title) = after_length.get(..usize::from(length)) else {
+            break;
+        };
+        chapters.push(Chapter {
+            start: u64::from_be_bytes(start) / 10_000_000,
+            title: String::from_utf8_lossy(title).into_owned(),
+        });
+        rest = &after_length[usize::from(length)..];
+    }
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn chapter(start: u64, title: &str) -> Chapter {
+        Chapter {
+            start,
+            title: title.to_string(),
+        }
+    }
+
+    fn id3_frame(id: [u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend_from_slice(&u32::try_from(body.len()).unwrap().to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn chap_frame(element_id: &str, start_ms: u32, title: &str) -> Vec<u8> {
+        let mut body = element_id.as_bytes().to_vec();
+        body.push(0);
+        body.extend_from_slice(&start_ms.to_be_bytes());
+        body.extend_from_slice(&[0; 4]);
+        body.extend_from_slice(&[0xff; 8]);
+        let mut text = vec![3];
+        text.extend_from_slice(title.as_bytes());
+        body.extend(id3_frame(*b"TIT2", &text));
+        id3_frame(*b"CHAP", &body)
+    }
+
+    fn id3_tag(frames: &[Vec<u8>]) -> Vec<u8> {
+        let frames = frames.concat();
+        let size = u32::try_from(frames.len() + 10).unwrap();
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        tag.extend(
+            (0..4)
+                .rev()
+                .map(|i| u8::try_from((size >> (7 * i)) & 0x7f).unwrap()),
+        );
+        tag.extend(frames);
+        // padding
+        tag.extend_from_slice(&[0; 10]);
+        tag
+    }
+
+    #[test]
+    fn json_chapters() {
+        let json = br#"{
+            "version": "1.2.0",
+            "chapters": [
+                {"startTime": 95.5, "title": "Second"},
+                {"startTime": 0, "title": "First"},
+                {"startTime": 120, "title": "Artwork only", "toc": false},
+                {"startTime": 300}
+            ]
+        }"#;
+        assert_eq!(
+            parse_chapters_json(json).unwrap(),
+            vec![
+                chapter(0, "First"),
+                chapter(95, "Second"),
+                chapter(300, "Chapter 3")
+            ]
+        );
+        assert!(parse_chapters_json(b"{}").is_err());
+    }
+
+    #[test]
+    fn id3_chap_frames() {
+        let tag = id3_tag(&[
+            id3_frame(*b"TIT2", b"\x00Episode"),
+            chap_frame("ch1", 61_500, "Intro talk"),
+            chap_frame("ch0", 0, "Welcome"),
+        ]);
+        assert_eq!(
+            id3_chapters(&mut Cursor::new(tag)).unwrap(),
+            vec![chapter(0, "Welcome"), chapter(61, "Intro talk")]
+        );
+    }
+
+    #[test]
+    fn id3_top_level_toc_filters_chapters() {
+        let mut toc = b"toc\x00\x03\x01ch1\x00".to_vec();
+        toc.extend(id3_frame(*b"TIT2", b"\x00Contents"));
+        let tag = id3_tag(&[
+            id3_frame(*b"CTOC", &toc),
+            chap_frame("ch0", 0, "Hidden"),
+            chap_frame("ch1", 10_000, "Listed"),
+        ]);
+        assert_eq!(
+            id3_chapters(&mut Cursor::new(tag)).unwrap(),
+            vec![chapter(10, "Listed")]
+        );
+    }
+
+    #[test]
+    fn id3_text_encodings() {
+        assert_eq!(decode_text(b"\x00caf\xe9").unwrap(), "café");
+        assert_eq!(
+            decode_text(b"\x01\xff\xfeh\x00i\x00\x00\x00").unwrap(),
+            "hi"
+        );
+        assert_eq!(decode_text(b"\x02\x00h\x00i").unwrap(), "hi");
+        assert_eq!(decode_text("\x03ñu\0".as_bytes()).unwrap(), "ñu");
+        assert!(decode_text(b"\x07").is_none());
+    }
+
+    fn mp4_box(kind: [u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = u32::try_from(content.len() + 8)
+            .unwrap()
+            .to_be_bytes()
+            .to_vec();
+        data.extend_from_slice(&kind);
+        data.extend_from_slice(content);
+        data
+    }
+
+    /// Full box contents: version and flags, then big-endian words
+    fn full_box(kind: [u8; 4], words: &[u32]) -> Vec<u8> {
+        let content: Vec<u8> = std::iter::once(0)
+            .chain(words.iter().copied())
+            .flat_map(u32::to_be_bytes)
+            .collect();
+        mp4_box(kind, &content)
+    }
+
+    #[test]
+    fn mp4_chpl_box() {
+        let mut chpl = vec![1, 0, 0, 0, 0, 0, 0, 0, 2];
+        for (start, title) in [(0_u64, "Start"), (905_000_000, "Later")] {
+            chpl.extend_from_slice(&start.to_be_bytes());
+            chpl.push(u8::try_from(title.len()).unwrap());
+            chpl.extend_from_slice(title.as_bytes());
+        }
+        let udta = mp4_box(*b"udta", &mp4_box(*b"chpl", &chpl));
+        let moov = mp4_box(*b"moov", &[mp4_box(*b"mvhd", &[0; 20]), udta].concat());
+        let file = [
+            mp4_box(*b"ftyp", b"M4A "),
+            mp4_box(*b"mdat", &[0; 64]),
+            moov,
+        ]
+        .concat();
+        assert_eq!(
+            mp4_chapters(&mut Cursor::new(file)).unwrap(),
+            vec![chapter(0, "Start"), chapter(90, "Later")]
+        );
+    }
+
+    const FTYP: &[u8] = b"\0\0\0\x0cftypM4A ";
+
+    /// MP4 file with the samples of a chapter text track, track 2 with
+    /// times in milliseconds and the given sample table
+    fn chapter_track_file(stbl: &[u8], samples: &[u8]) -> Vec<u8> {
+        let mdia = [
+            full_box(*b"mdhd", &[0, 0, 1000, 0]),
+            mp4_box(*b"minf", &mp4_box(*b"stbl", stbl)),
+        ]
+        .concat();
+        let text_trak = [full_box(*b"tkhd", &[0, 0, 2]), mp4_box(*b"mdia", &mdia)].concat();
+        let audio_trak = [
+            full_box(*b"tkhd", &[0, 0, 1]),
+            mp4_box(*b"tref", &mp4_box(*b"chap", &2_u32.to_be_bytes())),
+        ]
+        .concat();
+        let moov = mp4_box(
+            *b"moov",
+            &[
+                mp4_box(*b"trak", &audio_trak),
+                mp4_box(*b"trak", &text_trak),
+            ]
+            .concat(),
+        );
+        [FTYP.to_vec(), mp4_box(*b"mdat", samples), moov].concat()
+    }
+
+    #[test]
+    fn mp4_chapter_track() {
+        let mut samples = Vec::new();
+        for title in ["Intro", "Über"] {
+            samples.extend_from_slice(&u16::try_from(title.len()).unwrap().to_be_bytes());
+            samples.extend_from_slice(title.as_bytes());
+        }
+        // UTF-16 with a byte order mark
+        samples.extend_from_slice(&[0, 6, 0xfe, 0xff, 0, b'E', 0, b'n']);
+        let offset = u32::try_from(FTYP.len() + 8).unwrap();
+
+        // starting at 0 s, 30 s and 95.5 s
+        let stbl = [
+            full_box(*b"stts", &[3, 1, 30_000, 1, 65_500, 1, 10_000]),
+            full_box(*b"stsz", &[0, 3, 7, 7, 8]),
+            full_box(*b"stsc", &[2, 1, 2, 1, 2, 1, 1]),
+            full_box(*b"stco", &[2, offset, offset + 14]),
+        ]
+        .concat();
+        assert_eq!(
+            mp4_chapters(&mut Cursor::new(chapter_track_file(&stbl, &samples))).unwrap(),
+            vec![chapter(0, "Intro"), chapter(30, "Über"), chapter(95, "En")]
+        );
+    }
+
+    #[test]
+    fn mp4_chapter_track_oversized_counts() {
+        let offset = u32::try_from(FTYP.len() + 8).unwrap();
+        let stbl = [
+            full_box(*b"stts", &[1, u32::MAX, 1000]),
+            full_box(*b"stsz", &[7, u32::MAX]),
+            full_box(*b"stsc", &[1, 1, u32::MAX, 1]),
+            full_box(*b"stco", &[1, offset]),
+        ]
+        .concat();
+        let samples = [&[0, 5][..], b"Intro"].concat();
+        let chapters = mp4_chapters(&mut Cursor::new(chapter_track_file(&stbl, &samples))).unwrap();
+        assert!(chapters.len() <= MAX_CHAPTERS);
+        assert_eq!(chapters.first(), Some(&chapter(0, "Intro")));
+
+        // samples that claim to be larger than the file are skipped
+        let stbl = [
+            full_box(*b"stts", &[1, 1, 1000]),
+            full_box(*b"stsz", &[u32::MAX, 1]),
+            full_box(*b"stsc", &[1, 1, 1, 1]),
+            full_box(*b"stco", &[1, offset]),
+        ]
+        .concat();
+        assert!(
+            mp4_chapters(&mut Cursor::new(chapter_track_file(&stbl, &samples)))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn chapter_navigation() {
+        let chapters = vec![chapter(0, "A"), chapter(60, "B"), chapter(200, "C")];
+        assert_eq!(current(&chapters, 59), Some(0));
+        assert_eq!(current(&chapters, 60), Some(1));
+        assert_eq!(next_start(&chapters, 60), Some(200));
+        assert_eq!(next_start(&chapters, 200), None);
+        // far enough into a chapter goes back to its start
+        assert_eq!(previous_start(&chapters, 100), Some(60));
+        assert_eq!(previous_start(&chapters, 61), Some(0));
+        assert_eq!(previous_start(&chapters, 1), Some(0));
+        assert_eq!(previous_start(&[], 10), None);
+    }
+}
@@ -5,7 +5,9 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use crate::artwork::ArtworkMode;
 use crate::keymap::Keybindings;
+use crate::player::EqPreset;
 use crate::ui::colors::AppColors;
 use crate::utils::{evaluate_in_shell, parse_create_dir};
 
@@ -27,8 +29,13 @@ pub const SCROLL_AMOUNT: u16 = 6;
 /// Amount of time between ticks in the event loop
 pub const TICK_RATE: u64 = 50;
 
-/// Amount of time between ticks in the event loop
-pub const SEEK_LENGTH: Duration = Duration::from_secs(30);
+/// Default short and long seek steps, in seconds
+pub const SEEK_SHORT: u64 = 30;
+pub const SEEK_LONG: u64 = 300;
+
+/// Seconds into a chapter after which going to the previous chapter
+/// restarts the current one instead
+pub const CHAPTER_RESTART: u64 = 3;
 
 /// Maximum duration of episode when unknown
 pub const MAX_DURATION: u64 = 10000;
@@ -36,12 +43,59 @@ pub const MAX_DURATION: u64 = 10000;
 /// Number of milliseconds on mute to avoid audio artifacts
 pub const FADING_TIME: u64 = 100;
 
+/// Range and step of the playback speed
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+pub const SPEED_STEP: f32 = 0.1;
+
+/// Step and maximum of the intro and outro skipped in episodes, in
+/// seconds
+pub const SKIP_STEP: u64 = 5;
+pub const MAX_SKIP: u64 = 600;
+
+/// How long the sleep timer fades out before pausing, and how often the
+/// volume is lowered meanwhile
+pub const SLEEP_FADE_TIME: Duration = Duration::from_secs(30);
+pub const FADE_OUT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the position of the episode being played is saved, so it
+/// survives the app being killed
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long before the end of an episode the next one in the queue is
+/// opened, and the longest crossfade between them, in seconds
+pub const PREBUFFER_TIME: u64 = 30;
+pub const MAX_CROSSFADE: u64 = 12;
+
+/// Pause lengths and the rewind applied when resuming after them, in
+/// seconds, limited by the `smart_rewind` option
+pub const SMART_REWIND_STEPS: [(u64, u64); 4] = [(60, 5), (600, 10), (3600, 20), (86400, 30)];
+
+/// Maximum and step of the volume, in percent
+pub const MAX_VOLUME: u16 = 150;
+pub const VOLUME_STEP: u16 = 5;
+
+/// Largest shift and step of the balance to one side, in percent
+pub const MAX_BALANCE: i8 = 100;
+pub const BALANCE_STEP: i8 = 10;
+
+/// How far the internal player seeks back and forward
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeekSteps {
+    pub short_back: Duration,
+    pub short_forward: Duration,
+    pub long_back: Duration,
+    pub long_forward: Duration,
+}
+
 /// Holds information about user configuration of program.
 #[derive(Debug, Clone)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct Config {
     pub download_path: PathBuf,
     pub play_command: String,
+    /// Command opening the links of show notes
+    pub link_command: String,
     pub simultaneous_downloads: usize,
     pub max_retries: usize,
     pub mark_as_played_on_play: bool,
@@ -54,6 +108,34 @@ pub struct Config {
     pub keybindings: Keybindings,
     pub colors: AppColors,
     pub confirm_quit: bool,
+    pub volume: u16,
+    pub normalize_loudness: bool,
+    pub target_loudness: f32,
+    pub skip_silence: bool,
+    pub seek_steps: SeekSteps,
+    /// Name of the preferred output device, None for the default one
+    pub output_device: Option<String>,
+    /// Seconds the end of an episode overlaps the start of the next one
+    /// in the queue, 0 plays them back to back
+    pub crossfade: u64,
+    /// Most the position goes back when resuming after a pause, zero
+    /// disables it
+    pub smart_rewind: Duration,
+    /// Saves streamed episodes as downloads once fully received
+    pub keep_streamed: bool,
+    /// Equalizer preset for podcasts without one of their own
+    pub equalizer: EqPreset,
+    /// Mixes all channels down to mono
+    pub mono: bool,
+    /// Balance between left and right in percent, negative towards the
+    /// left
+    pub balance: i8,
+    /// Searches the descriptions besides the titles
+    pub search_descriptions: bool,
+    /// Captures the mouse, which disables selecting text in the terminal
+    pub mouse: bool,
+    /// How podcast artwork is drawn, if at all
+    pub artwork: ArtworkMode,
 }
 
 /// A temporary struct used to deserialize data from the TOML configuration
@@ -62,6 +144,7 @@ pub struct Config {
 struct ConfigFromToml {
     download_path: Option<String>,
     play_command: Option<String>,
+    link_command: Option<String>,
     simultaneous_downloads: Option<usize>,
     max_retries: Option<usize>,
     mark_as_played_on_play: Option<bool>,
@@ -75,6 +158,24 @@ struct ConfigFromToml {
     keybindings: Option<KeybindingsFromToml>,
     colors: Option<AppColorsFromToml>,
     confirm_quit: Option<bool>,
+    volume: Option<u16>,
+    normalize_loudness: Option<bool>,
+    target_loudness: Option<f32>,
+    skip_silence: Option<bool>,
+    seek_short_back: Option<u64>,
+    seek_short_forward: Option<u64>,
+    seek_long_back: Option<u64>,
+    seek_long_forward: Option<u64>,
+    output_device: Option<String>,
+    crossfade: Option<u64>,
+    smart_rewind: Option<u64>,
+    keep_streamed: Option<bool>,
+    equalizer: Option<String>,
+    mono: Option<bool>,
+    balance: Option<i64>,
+    search_descriptions: Option<bool>,
+    mouse: Option<bool>,
+    artwork: Option<String>,
 }
 
 /// A temporary struct used to deserialize keybinding data from the TOML
@@ -106,14 +207,45 @@ pub struct KeybindingsFromToml {
     pub remove: Option<Vec<String>>,
     pub filter_played: Option<Vec<String>>,
     pub filter_downloaded: Option<Vec<String>>,
+    pub sort: Option<Vec<String>>,
     pub enqueue: Option<Vec<String>>,
+    pub select: Option<Vec<String>>,
+    pub select_range: Option<Vec<String>>,
     pub help: Option<Vec<String>>,
     pub quit: Option<Vec<String>>,
     pub unplayed_list: Option<Vec<String>>,
+    pub downloads_list: Option<Vec<String>>,
     pub back: Option<Vec<String>>,
     pub switch: Option<Vec<String>>,
     pub play_external: Option<Vec<String>>,
     pub reset_player: Option<Vec<String>>,
+    pub speed_up: Option<Vec<String>>,
+    pub speed_down: Option<Vec<String>>,
+    pub volume_up: Option<Vec<String>>,
+    pub volume_down: Option<Vec<String>>,
+    pub mute: Option<Vec<String>>,
+    pub sleep_timer: Option<Vec<String>>,
+    pub output_device: Option<Vec<String>>,
+    pub equalizer: Option<Vec<String>>,
+    pub mono: Option<Vec<String>>,
+    pub balance_left: Option<Vec<String>>,
+    pub balance_right: Option<Vec<String>>,
+    pub seek_back_long: Option<Vec<String>>,
+    pub seek_forward_long: Option<Vec<String>>,
+    pub go_to_timestamp: Option<Vec<String>>,
+    pub next_chapter: Option<Vec<String>>,
+    pub previous_chapter: Option<Vec<String>>,
+    pub search: Option<Vec<String>>,
+    pub next_match: Option<Vec<String>>,
+    pub previous_match: Option<Vec<String>>,
+    pub open_link: Option<Vec<String>>,
+    pub copy_link: Option<Vec<String>>,
+    pub intro_shorter: Option<Vec<String>>,
+    pub intro_longer: Option<Vec<String>>,
+    pub outro_shorter: Option<Vec<String>>,
+    pub outro_longer: Option<Vec<String>>,
+    /// Keys jumping to 0%, 10%, ... 90% of the episode, in this order
+    pub jump_to: Option<Vec<String>>,
 }
 
 /// A temporary struct used to deserialize colors data from the TOML
@@ -164,9 +296,16 @@ impl Config {
     }
 }
 
+/// Balance in percent, limited to what is possible on each side
+fn parse_balance(balance: i64) -> i8 {
+    let max = i64::from(MAX_BALANCE);
+    i8::try_from(balance.clamp(-max, max)).unwrap_or(0)
+}
+
 /// Takes the deserialized TOML configuration, and creates a Config struct
 /// that specifies user settings where indicated, and defaults for any
 /// settings that were not specified by the user.
+#[allow(clippy::too_many_lines)]
 fn config_with_defaults(config_toml: ConfigFromToml) -> Result<Config> {
     // specify keybindings
     let keymap = config_toml
@@ -193,6 +332,17 @@ fn config_with_defaults(config_toml: ConfigFromToml) -> Result<Config> {
         .as_deref()
         .map_or_else(|| "vlc %s".to_string(), std::string::ToString::to_string);
 
+    let link_command = config_toml.link_command.unwrap_or_else(|| {
+        if cfg!(target_os = "macos") {
+            "open %s"
+        } else if cfg!(target_os = "windows") {
+            "explorer %s"
+        } else {
+            "xdg-open %s"
+        }
+        .to_string()
+    });
+
     let simultaneous_downloads = match config_toml.simultaneous_downloads {
         Some(num) if num > 0 => num,
         Some(_) | None => 3,
@@ -227,9 +377,56 @@ fn config_with_defaults(config_toml: ConfigFromToml) -> Result<Config> {
 
     let confirm_quit = config_toml.confirm_quit.unwrap_or(true);
 
+    let volume = config_toml.volume.unwrap_or(100).min(MAX_VOLUME);
+
+    let normalize_loudness = config_toml.normalize_loudness.unwrap_or(false);
+
+    let target_loudness = match config_toml.target_loudness {
+        Some(lufs) if (-40.0..=0.0).contains(&lufs) => lufs,
+        Some(_) | None => -16.0,
+    };
+
+    let skip_silence = config_toml.skip_silence.unwrap_or(false);
+
+    let seek_step = |seconds: Option<u64>, default| match seconds {
+        Some(seconds) if seconds > 0 => Duration::from_secs(seconds),
+        Some(_) | None => Duration::from_secs(default),
+    };
+    let seek_steps = SeekSteps {
+        short_back: seek_step(config_toml.seek_short_back, SEEK_SHORT),
+        short_forward: seek_step(config_toml.seek_short_forward, SEEK_SHORT),
+        long_back: seek_step(config_toml.seek_long_back, SEEK_LONG),
+        long_forward: seek_step(config_toml.seek_long_forward, SEEK_LONG),
+    };
+
+    let output_device = config_toml
+        .output_device
+        .filter(|name| !name.trim().is_empty());
+
+    let crossfade = config_toml.crossfade.unwrap_or(0).min(MAX_CROSSFADE);
+
+    let smart_rewind = Duration::from_secs(config_toml.smart_rewind.unwrap_or(0));
+
+    let keep_streamed = config_toml.keep_streamed.unwrap_or(false);
+
+    let equalizer = config_toml.equalizer.map_or(EqPreset::Flat, |name| {
+        EqPreset::from_name(&name).unwrap_or_else(|| {
+            log::warn!("Unknown equalizer preset {name}, using flat");
+            EqPreset::Flat
+        })
+    });
+
+    let artwork = config_toml.artwork.map_or(ArtworkMode::Auto, |name| {
+        ArtworkMode::from_name(&name).unwrap_or_else(|| {
+            log::warn!("Unknown artwork mode {name}, using auto");
+            ArtworkMode::Auto
+        })
+    });
+
     Ok(Config {
         download_path,
         play_command,
+        link_command,
         simultaneous_downloads,
         max_retries,
         mark_as_played_on_play,
@@ -242,6 +439,21 @@ fn config_with_defaults(config_toml: ConfigFromToml) -> Result<Config> {
         keybindings: keymap,
         colors,
         confirm_quit,
+        volume,
+        normalize_loudness,
+        target_loudness,
+        skip_silence,
+        seek_steps,
+        output_device,
+        crossfade,
+        smart_rewind,
+        keep_streamed,
+        equalizer,
+        mono: config_toml.mono.unwrap_or(false),
+        balance: config_toml.balance.map_or(0, parse_balance),
+        search_descriptions: config_toml.search_descriptions.unwrap_or(false),
+        mouse: config_toml.mouse.unwrap_or(true),
+        artwork,
     })
 }
 
@@ -348,6 +560,167 @@ mod tests {
         assert!(!config.confirm_quit);
     }
 
+    #[test]
+    fn volume_default_and_limit() {
+        assert_eq!(parse_config("").unwrap().volume, 100);
+        assert_eq!(parse_config("volume = 80").unwrap().volume, 80);
+        assert_eq!(parse_config("volume = 500").unwrap().volume, MAX_VOLUME);
+    }
+
+    #[test]
+    fn loudness_settings() {
+        let config = parse_config("").unwrap();
+        assert!(!config.normalize_loudness);
+        assert!((config.target_loudness + 16.0).abs() < f32::EPSILON);
+
+        let config = parse_config(
+            r"
+            normalize_loudness = true
+            target_loudness = -19.0
+            ",
+        )
+        .unwrap();
+        assert!(config.normalize_loudness);
+        assert!((config.target_loudness + 19.0).abs() < f32::EPSILON);
+
+        let config = parse_config("target_loudness = 10.0").unwrap();
+        assert!((config.target_loudness + 16.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn skip_silence_default() {
+        assert!(!parse_config("").unwrap().skip_silence);
+        assert!(parse_config("skip_silence = true").unwrap().skip_silence);
+    }
+
+    #[test]
+    fn seek_steps() {
+        let config = parse_config(
+            r"
+            seek_short_back = 10
+            seek_long_forward = 600
+            seek_long_back = 0
+            ",
+        )
+        .unwrap();
+        assert_eq!(config.seek_steps.short_back, Duration::from_secs(10));
+        assert_eq!(
+            config.seek_steps.short_forward,
+            Duration::from_secs(SEEK_SHORT)
+        );
+        assert_eq!(config.seek_steps.long_back, Duration::from_secs(SEEK_LONG));
+        assert_eq!(config.seek_steps.long_forward, Duration::from_mins(10));
+    }
+
+    #[test]
+    fn output_device() {
+        assert_eq!(parse_config("").unwrap().output_device, None);
+        assert_eq!(
+            parse_config(r#"output_device = "  ""#)
+                .unwrap()
+                .output_device,
+            None
+        );
+        assert_eq!(
+            parse_config(r#"output_device = "USB Headset""#)
+                .unwrap()
+                .output_device
+                .as_deref(),
+            Some("USB Headset")
+        );
+    }
+
+    #[test]
+    fn crossfade_default_and_limit() {
+        assert_eq!(parse_config("").unwrap().crossfade, 0);
+        assert_eq!(parse_config("crossfade = 5").unwrap().crossfade, 5);
+        assert_eq!(
+            parse_config("crossfade = 600").unwrap().crossfade,
+            MAX_CROSSFADE
+        );
+    }
+
+    #[test]
+    fn smart_rewind_disabled_by_default() {
+        assert!(parse_config("").unwrap().smart_rewind.is_zero());
+        assert_eq!(
+            parse_config("smart_rewind = 20").unwrap().smart_rewind,
+            Duration::from_secs(20)
+        );
+    }
+
+    #[test]
+    fn keep_streamed_disabled_by_default() {
+        assert!(!parse_config("").unwrap().keep_streamed);
+        assert!(parse_config("keep_streamed = true").unwrap().keep_streamed);
+    }
+
+    #[test]
+    fn equalizer_preset() {
+        assert_eq!(parse_config("").unwrap().equalizer, EqPreset::Flat);
+        assert_eq!(
+            parse_config(r#"equalizer = "voice_boost""#)
+                .unwrap()
+                .equalizer,
+            EqPreset::VoiceBoost
+        );
+        assert_eq!(
+            parse_config(r#"equalizer = "loud""#).unwrap().equalizer,
+            EqPreset::Flat
+        );
+    }
+
+    #[test]
+    fn mono_and_balance() {
+        let config = parse_config("").unwrap();
+        assert!(!config.mono);
+        assert_eq!(config.balance, 0);
+        let config = parse_config("mono = true\nbalance = -30").unwrap();
+        assert!(config.mono);
+        assert_eq!(config.balance, -30);
+        assert_eq!(parse_config("balance = 400").unwrap().balance, MAX_BALANCE);
+        assert_eq!(
+            parse_config("balance = -400").unwrap().balance,
+            -MAX_BALANCE
+        );
+    }
+
+    #[test]
+    fn search_descriptions() {
+        assert!(!parse_config("").unwrap().search_descriptions);
+        assert!(
+            parse_config("search_descriptions = true")
+                .unwrap()
+                .search_descriptions
+        );
+    }
+
+    #[test]
+    fn link_command() {
+        assert!(parse_config("").unwrap().link_command.ends_with(" %s"));
+        let config = parse_config("link_command = \"firefox\"").unwrap();
+        assert_eq!(config.link_command, "firefox");
+    }
+
+    #[test]
+    fn mouse_capture() {
+        assert!(parse_config("").unwrap().mouse);
+        assert!(!parse_config("mouse = false").unwrap().mouse);
+    }
+
+    #[test]
+    fn artwork_mode() {
+        assert_eq!(parse_config("").unwrap().artwork, ArtworkMode::Auto);
+        assert_eq!(
+            parse_config(r#"artwork = "sixel""#).unwrap().artwork,
+            ArtworkMode::Sixel
+        );
+        assert_eq!(
+            parse_config(r#"artwork = "ascii""#).unwrap().artwork,
+            ArtworkMode::Auto
+        );
+    }
+
     #[test]
     fn invalid_toml_errors() {
         assert!(parse_config("not valid toml [[[").is_err());
@@ -1,13 +1,20 @@
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use rusqlite::{Connection, params};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
 use semver::Version;
 
-use crate::types::{Episode, EpisodeNoId, LockVec, NewEpisode, Podcast, PodcastNoId};
+use crate::chapters::Chapter;
+use crate::player::EqPreset;
+use crate::types::{Episode, EpisodeNoId, LockVec, NewEpisode, Podcast, PodcastNoId, SortMode};
 use crate::utils::convert_date;
 
+/// Key of the params entry with the episode being played and its position
+const CHECKPOINT: &str = "checkpoint";
+
 pub struct SyncResult {
     pub added: Vec<NewEpisode>,
     pub updated: Vec<i64>,
@@ -166,6 +173,61 @@ impl Database {
             params![],
         )
         .with_context(|| "Could not create params database table")?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS chapters (
+                id INTEGER PRIMARY KEY NOT NULL,
+                episode_id INTEGER NOT NULL,
+                start INTEGER NOT NULL,
+                title TEXT NOT NULL,
+                FOREIGN KEY(episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+            );",
+            params![],
+        )
+        .with_context(|| "Could not create chapters database table")?;
+
+        // columns added after the tables were first released, existing
+        // databases get them on startup
+        Self::add_column(conn, "podcasts", "speed", "REAL NOT NULL DEFAULT 1.0")?;
+        Self::add_column(
+            conn,
+            "podcasts",
+            "volume_offset",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        Self::add_column(conn, "files", "loudness", "REAL")?;
+        Self::add_column(conn, "episodes", "time_saved", "INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column(conn, "podcasts", "skip_intro", "INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column(conn, "podcasts", "skip_outro", "INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column(conn, "episodes", "chapters_url", "TEXT")?;
+        Self::add_column(conn, "episodes", "last_played", "INTEGER")?;
+        Self::add_column(conn, "podcasts", "equalizer", "TEXT")?;
+        Self::add_column(conn, "podcasts", "sort_order", "TEXT")?;
+        Self::add_column(conn, "podcasts", "image_url", "TEXT")?;
+        Self::add_column(conn, "episodes", "image_url", "TEXT")?;
+        Self::add_column(
+            conn,
+            "episodes",
+            "chapters_checked",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        Ok(())
+    }
+
+    /// Adds a column to a table, unless it already exists.
+    fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info(?) WHERE name = ?);",
+            params![table, column],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            conn.execute(
+                &format!("ALTER TABLE {table} ADD COLUMN {column} {definition};"),
+                params![],
+            )
+            .with_context(|| format!("Could not add column {column} to {table} table"))?;
+        }
         Ok(())
     }
 
@@ -177,8 +239,8 @@ impl Database {
         {
             let mut stmt = tx.prepare_cached(
                 "INSERT INTO podcasts (title, url, description, author,
-                explicit, last_checked)
-                VALUES (?, ?, ?, ?, ?, ?);",
+                explicit, image_url, last_checked)
+                VALUES (?, ?, ?, ?, ?, ?, ?);",
             )?;
             stmt.execute(params![
                 podcast.title,
@@ -186,6 +248,7 @@ impl Database {
                 podcast.description,
                 podcast.author,
                 podcast.explicit,
+                podcast.image_url,
                 podcast.last_checked.timestamp()
             ])?;
         }
@@ -222,8 +285,9 @@ impl Database {
 
         let mut stmt = conn.prepare_cached(
             "INSERT INTO episodes (podcast_id, title, url, guid,
-                description, pubdate, duration, played, position)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+                description, pubdate, duration, played, position, chapters_url,
+                image_url)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
         )?;
         let duration: Option<i64> = episode
             .duration
@@ -239,19 +303,26 @@ impl Database {
             duration,
             false,
             0,
+            episode.chapters_url,
+            episode.image_url,
         ])?;
         Ok(conn.last_insert_rowid())
     }
 
-    /// Inserts a filepath to a downloaded episode.
-    pub fn insert_file(&self, episode_id: i64, path: &Path) -> Result<()> {
+    /// Inserts a filepath to a downloaded episode, along with its
+    /// integrated loudness in LUFS if it was measured.
+    pub fn insert_file(&self, episode_id: i64, path: &Path, loudness: Option<f32>) -> Result<()> {
         let conn = self.conn()?;
 
         let mut stmt = conn.prepare_cached(
-            "INSERT INTO files (episode_id, path)
-                VALUES (?, ?);",
+            "INSERT INTO files (episode_id, path, loudness)
+                VALUES (?, ?, ?);",
         )?;
-        stmt.execute(params![episode_id, path.to_str(),])?;
+        stmt.execute(params![episode_id, path.to_str(), loudness])?;
+        // the file may have chapters the feed did not
+        let mut stmt =
+            conn.prepare_cached("UPDATE episodes SET chapters_checked = 0 WHERE id = ?;")?;
+        stmt.execute(params![episode_id])?;
         Ok(())
     }
 
@@ -300,7 +371,7 @@ impl Database {
             let conn = self.conn()?;
             let mut stmt = conn.prepare_cached(
                 "UPDATE podcasts SET title = ?, url = ?, description = ?,
-            author = ?, explicit = ?, last_checked = ?
+            author = ?, explicit = ?, image_url = ?, last_checked = ?
             WHERE id = ?;",
             )?;
             stmt.execute(params![
@@ -309,6 +380,7 @@ impl Database {
                 podcast.description,
                 podcast.author,
                 podcast.explicit,
+                podcast.image_url,
                 podcast.last_checked.timestamp(),
                 pod_id,
             ])?;
@@ -387,7 +459,9 @@ impl Database {
                     let mut stmt = tx.prepare_cached(
                         "UPDATE episodes SET title = ?, url = ?,
                             guid = ?, description = ?, pubdate = ?,
-                            duration = ? WHERE id = ?;",
+                            duration = ?, chapters_url = ?7, image_url = ?,
+                            chapters_checked = chapters_checked AND chapters_url IS ?7
+                            WHERE id = ?;",
                     )?;
                     let duration: Option<i64> = new_ep
                         .duration
@@ -400,6 +474,8 @@ impl Database {
                         new_ep.description,
                         new_pd,
                         duration,
+                        new_ep.chapters_url,
+                        new_ep.image_url,
                         id,
                     ])?;
                     update_ep.push(id);
@@ -438,6 +514,8 @@ impl Database {
             && new_ep.url == old_ep.url
             && new_ep.guid == old_ep.guid
             && new_ep.description == old_ep.description
+            && new_ep.chapters_url == old_ep.chapters_url
+            && new_ep.image_url == old_ep.image_url
             // do not update duration, we can take it from the audio file
             // && new_ep.duration == old_ep.duration
             && pd_match)
@@ -447,6 +525,122 @@ impl Database {
         false
     }
 
+    /// Sets the playback speed used for the episodes of a podcast.
+    pub fn set_podcast_speed(&self, podcast_id: i64, speed: f32) -> Result<()> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare_cached("UPDATE podcasts SET speed = ? WHERE id = ?;")?;
+        stmt.execute(params![speed, podcast_id])?;
+        Ok(())
+    }
+
+    /// Sets the volume adjustment of a podcast, in percent points.
+    pub fn set_podcast_volume_offset(&self, podcast_id: i64, offset: i32) -> Result<()> {
+        let conn = self.conn()?;
+        let mut stmt =
+            conn.prepare_cached("UPDATE podcasts SET volume_offset = ? WHERE id = ?;")?;
+        stmt.execute(params![offset, podcast_id])?;
+        Ok(())
+    }
+
+    /// Adds to the time saved by skipping silence in an episode, stored in
+    /// milliseconds.
+    pub fn add_time_saved(&self, episode_id: i64, time_saved: Duration) -> Result<()> {
+        let conn = self.conn()?;
+        let mut stmt =
+            conn.prepare_cached("UPDATE episodes SET time_saved = time_saved + ? WHERE id = ?;")?;
+        let millis: i64 = time_saved.as_millis().try_into()?;
+        stmt.execute(params![millis, episode_id])?;
+        Ok(())
+    }
+
+    /// Records when the position of an episode was saved while listening.
+    pub fn set_last_played(&self, episode_id: i64, time: DateTime<Utc>) -> Result<()> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare_cached("UPDATE episodes SET last_played = ? WHERE id = ?;")?;
+        stmt.execute(params![time.timestamp(), episode_id])?;
+        Ok(())
+    }
+
+    /// Saves the position of the episode being played. The episode keeps
+    /// it in seconds like any other, the checkpoint in milliseconds, and
+    /// only the last episode has one.
+    pub fn set_checkpoint(
+        &mut self, episode_id: i64, position: Duration, time: DateTime<Utc>,
+    ) -> Result<()> {
+        let conn = self.conn_mut()?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "UPDATE episodes SET position = ?, last_played = ? WHERE id = ?;",
+            )?;
+            let seconds: i64 = position.as_secs().try_into()?;
+            stmt.execute(params![seconds, time.timestamp(), episode_id])?;
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO params (key, value)
+                    VALUES (?, ?);",
+            )?;
+            let millis = position.as_millis();
+            stmt.execute(params![CHECKPOINT, format!("{episode_id} {millis}")])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns the episode with a checkpoint and its position, if any.
+    pub fn get_checkpoint(&self) -> Result<Option<(i64, Duration)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare_cached("SELECT value FROM params WHERE key = ?;")?;
+        let Some(value) = stmt
+            .query_row(params![CHECKPOINT], |row| row.get::<_, String>(0))
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let (episode_id, millis) = value
+            .split_once(' ')
+            .ok_or_else(|| anyhow::anyhow!("Invalid checkpoint: {value}"))?;
+        Ok(Some((
+            episode_id.parse()?,
+            Duration::from_millis(millis.parse()?),
+        )))
+    }
+
+    /// Forgets the checkpoint, once nothing is being played.
+    pub fn clear_checkpoint(&self) -> Result<()> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare_cached("DELETE FROM params WHERE key = ?;")?;
+        stmt.execute(params![CHECKPOINT])?;
+        Ok(())
+    }
+
+    /// Sets how many seconds are skipped at the start and the end of the
+    /// episodes of a podcast.
+    pub fn set_podcast_skips(&self, podcast_id: i64, intro: u64, outro: u64) -> Result<()> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare_cached("UPDATE podcasts SET skip_intro = ?, skip_outro = ? WHERE id = ?;")?;
+        let intro: i64 = intro.try_into()?;
+        let outro: i64 = outro.try_into()?;
+        stmt.execute(params![intro, outro, podcast_id])?;
+        Ok(())
+    }
+
+    /// Sets the equalizer preset of a podcast.
+    pub fn set_podcast_equalizer(&self, podcast_id: i64, preset: EqPreset) -> Result<()> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare_cached("UPDATE podcasts SET equalizer = ? WHERE id = ?;")?;
+        stmt.execute(params![preset.name(), podcast_id])?;
+        Ok(())
+    }
+
+    /// Sets the order of the episodes of a podcast.
+    pub fn set_podcast_sort(&self, podcast_id: i64, mode: SortMode) -> Result<()> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare_cached("UPDATE podcasts SET sort_order = ? WHERE id = ?;")?;
+        stmt.execute(params![mode.name(), podcast_id])?;
+        Ok(())
+    }
+
     /// Updates an episode to mark it as played or unplayed.
     pub fn set_played_status(
         &self, episode_id: i64, position: u64, duration: Option<u64>, played: bool,
@@ -484,6 +678,7 @@ impl Database {
     }
 
     /// Generates list of all podcasts in database using a single query.
+    #[allow(clippy::too_many_lines)]
     pub fn get_podcasts(&self) -> Result<Vec<Podcast>> {
         let conn = self.conn()?;
 
@@ -496,6 +691,8 @@ impl Database {
             let title: String = row.get("title")?;
             let last_checked = convert_date(row.get("last_checked")?)
                 .map_err(|_| rusqlite::Error::InvalidQuery)?;
+            let equalizer: Option<String> = row.get("equalizer")?;
+            let sort: Option<String> = row.get("sort_order")?;
             Ok(Podcast {
                 id,
                 title,
@@ -503,7 +700,17 @@ impl Database {
                 description: row.get("description")?,
                 author: row.get("author")?,
                 explicit: row.get("explicit")?,
+                image_url: row.get("image_url")?,
                 last_checked,
+                speed: row.get("speed")?,
+                volume_offset: row.get("volume_offset")?,
+                skip_intro: u64::try_from(row.get::<&str, i64>("skip_intro")?).unwrap_or_default(),
+                skip_outro: u64::try_from(row.get::<&str, i64>("skip_outro")?).unwrap_or_default(),
+                equalizer: equalizer.as_deref().and_then(EqPreset::from_name),
+                sort: sort
+                    .as_deref()
+                    .and_then(SortMode::from_name)
+                    .unwrap_or_default(),
                 episodes: LockVec::new(vec![]),
             })
         })?;
@@ -515,7 +722,7 @@ impl Database {
 
         // Fetch all episodes + files in one query and group by podcast
         let mut ep_stmt = conn.prepare_cached(
-            "SELECT episodes.*, files.path FROM episodes
+            "SELECT episodes.*, files.path, files.loudness FROM episodes
                 LEFT JOIN files ON episodes.id = files.episode_id
                 ORDER BY episodes.podcast_id, pubdate DESC;",
         )?;
@@ -525,6 +732,8 @@ impl Database {
             let path = row.get::<&str, String>("path").ok().map(PathBuf::from);
             let pubdate: Option<i64> = row.get("pubdate")?;
             let pubdate = pubdate.and_then(|ts| convert_date(ts).ok());
+            let last_played: Option<i64> = row.get("last_played")?;
+            let last_played = last_played.and_then(|ts| convert_date(ts).ok());
             let duration: Option<i64> = row.get("duration")?;
             let position: Option<i64> = row.get("position")?;
             let duration = duration
@@ -549,6 +758,15 @@ impl Database {
                 duration,
                 position,
                 path,
+                loudness: row.get("loudness")?,
+                time_saved: Duration::from_millis(
+                    u64::try_from(row.get::<&str, i64>("time_saved")?).unwrap_or_default(),
+                ),
+                chapters_url: row.get("chapters_url")?,
+                image_url: row.get("image_url")?,
+                chapters: Vec::new(),
+                chapters_checked: row.get("chapters_checked")?,
+                last_played,
                 played: row.get("played").unwrap_or(false),
             })
         })?;
@@ -559,12 +777,20 @@ impl Database {
             episodes_by_pod.entry(ep.pod_id).or_default().push(ep);
         }
 
+        let mut chapters = self.get_chapters(None)?;
+        for episodes in episodes_by_pod.values_mut() {
+            for ep in episodes {
+                ep.chapters = chapters.remove(&ep.id).unwrap_or_default();
+            }
+        }
+
         // Assemble podcasts with their episodes
         let mut podcasts = Vec::with_capacity(pod_order.len());
         for id in pod_order {
             if let Some(mut podcast) = podcasts_map.remove(&id) {
                 let episodes = episodes_by_pod.remove(&id).unwrap_or_default();
                 podcast.episodes = LockVec::new(episodes);
+                podcast.episodes.sort_by_mode(podcast.sort);
                 podcasts.push(podcast);
             }
         }
@@ -589,6 +815,8 @@ impl Database {
             let path = row.get::<&str, String>("path").ok().map(PathBuf::from);
             let pubdate: Option<i64> = row.get("pubdate")?;
             let pubdate = pubdate.and_then(|ts| convert_date(ts).ok());
+            let last_played: Option<i64> = row.get("last_played")?;
+            let last_played = last_played.and_then(|ts| convert_date(ts).ok());
             let duration: Option<i64> = row.get("duration")?;
             let position: Option<i64> = row.get("position")?;
             let duration = duration
@@ -613,13 +841,80 @@ impl Database {
                 duration,
                 position,
                 path,
+                loudness: row.get("loudness")?,
+                time_saved: Duration::from_millis(
+                    u64::try_from(row.get::<&str, i64>("time_saved")?).unwrap_or_default(),
+                ),
+                chapters_url: row.get("chapters_url")?,
+                image_url: row.get("image_url")?,
+                chapters: Vec::new(),
+                chapters_checked: row.get("chapters_checked")?,
+                last_played,
                 played: row.get("played").unwrap_or(false),
             })
         })?;
-        let episodes = episode_iter.flatten().collect();
+        let mut episodes: Vec<Episode> = episode_iter.flatten().collect();
+        let mut chapters = self.get_chapters(Some(pod_id))?;
+        for ep in &mut episodes {
+            ep.chapters = chapters.remove(&ep.id).unwrap_or_default();
+        }
         Ok(episodes)
     }
 
+    /// Returns the chapters of every episode, or only of the episodes of
+    /// one podcast, sorted by start.
+    fn get_chapters(&self, pod_id: Option<i64>) -> Result<HashMap<i64, Vec<Chapter>>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT chapters.episode_id, chapters.start, chapters.title FROM chapters
+                JOIN episodes ON episodes.id = chapters.episode_id
+                WHERE ?1 IS NULL OR episodes.podcast_id = ?1
+                ORDER BY chapters.episode_id, chapters.start;",
+        )?;
+        let chapter_iter = stmt.query_map(params![pod_id], |row| {
+            Ok((
+                row.get::<usize, i64>(0)?,
+                Chapter {
+                    start: u64::try_from(row.get::<usize, i64>(1)?).unwrap_or_default(),
+                    title: row.get(2)?,
+                },
+            ))
+        })?;
+        let mut chapters: HashMap<i64, Vec<Chapter>> = HashMap::new();
+        for chapter in chapter_iter {
+            let (ep_id, chapter) = chapter?;
+            chapters.entry(ep_id).or_default().push(chapter);
+        }
+        Ok(chapters)
+    }
+
+    /// Replaces the chapters stored for an episode, and records that they
+    /// were looked for even when there are none.
+    pub fn set_chapters(&mut self, episode_id: i64, chapters: &[Chapter]) -> Result<()> {
+        let conn = self.conn_mut()?;
+        let tx = conn.transaction()?;
+        {
+            tx.execute(
+                "DELETE FROM chapters WHERE episode_id = ?;",
+                params![episode_id],
+            )?;
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO chapters (episode_id, start, title)
+                    VALUES (?, ?, ?);",
+            )?;
+            for chapter in chapters {
+                let start: i64 = chapter.start.try_into()?;
+                stmt.execute(params![episode_id, start, chapter.title])?;
+            }
+            tx.execute(
+                "UPDATE episodes SET chapters_checked = 1 WHERE id = ?;",
+                params![episode_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Generates list of episodes for a given podcast.
     pub fn get_queue(&self) -> Result<Vec<i64>> {
         let conn = self.conn()?;
@@ -653,6 +948,7 @@ impl Database {
     /// Deletes all rows in all tables
     pub fn clear_db(&self) -> Result<()> {
         let conn = self.conn()?;
+        conn.execute("DELETE FROM chapters;", params![])?;
         conn.execute("DELETE FROM files;", params![])?;
         conn.execute("DELETE FROM episodes;", params![])?;
         conn.execute("DELETE FROM podcasts;", params![])?;
@@ -685,6 +981,7 @@ mod tests {
             description: Some("A test podcast".to_string()),
             author: Some("Test Author".to_string()),
             explicit: Some(false),
+            image_url: Some("https://example.com/art.jpg".to_string()),
             last_checked: Utc::now(),
             episodes: vec![
                 EpisodeNoId {
@@ -694,6 +991,8 @@ mod tests {
                     description: "First episode".to_string(),
                     pubdate: Some(Utc::now()),
                     duration: Some(3600),
+                    chapters_url: None,
+                    image_url: Some("https://example.com/ep1.jpg".to_string()),
                 },
                 EpisodeNoId {
                     title: "Episode 2".to_string(),
@@ -702,6 +1001,8 @@ mod tests {
                     description: "Second episode".to_string(),
                     pubdate: Some(Utc::now()),
                     duration: Some(1800),
+                    chapters_url: None,
+                    image_url: None,
                 },
             ],
         }
@@ -722,6 +1023,8 @@ mod tests {
             .unwrap();
         conn.execute("SELECT 1 FROM params LIMIT 1;", params![])
             .unwrap();
+        conn.execute("SELECT 1 FROM chapters LIMIT 1;", params![])
+            .unwrap();
     }
 
     #[test]
@@ -783,7 +1086,7 @@ mod tests {
 
         // Add a file to one episode
         let eps = db.get_episodes(pod_id).unwrap();
-        db.insert_file(eps[0].id, Path::new("/tmp/test.mp3"))
+        db.insert_file(eps[0].id, Path::new("/tmp/test.mp3"), None)
             .unwrap();
 
         db.remove_podcast(pod_id).unwrap();
@@ -831,6 +1134,8 @@ mod tests {
             description: "Third episode".to_string(),
             pubdate: Some(Utc::now()),
             duration: Some(900),
+            chapters_url: None,
+            image_url: None,
         });
 
         let result = db.update_podcast(pod_id, &updated).unwrap();
@@ -862,6 +1167,46 @@ mod tests {
         assert_eq!(revised.title, "Episode 1 - Revised");
     }
 
+    #[test]
+    fn artwork_urls_stored_and_updated() {
+        let mut db = Database::connect_in_memory().unwrap();
+        let podcast = sample_podcast();
+        db.insert_podcast(&podcast).unwrap();
+
+        let podcasts = db.get_podcasts().unwrap();
+        let pod_id = podcasts[0].id;
+        assert_eq!(
+            podcasts[0].image_url.as_deref(),
+            Some("https://example.com/art.jpg")
+        );
+
+        let mut updated = podcast.clone();
+        updated.image_url = Some("https://example.com/new.jpg".to_string());
+        updated.episodes[1].image_url = Some("https://example.com/ep2.jpg".to_string());
+        let result = db.update_podcast(pod_id, &updated).unwrap();
+        assert_eq!(result.updated.len(), 1);
+
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!(
+            podcasts[0].image_url.as_deref(),
+            Some("https://example.com/new.jpg")
+        );
+        let eps = db.get_episodes(pod_id).unwrap();
+        let image = |guid: &str| {
+            eps.iter()
+                .find(|e| e.guid == guid)
+                .and_then(|e| e.image_url.clone())
+        };
+        assert_eq!(
+            image("guid-ep1").as_deref(),
+            Some("https://example.com/ep1.jpg")
+        );
+        assert_eq!(
+            image("guid-ep2").as_deref(),
+            Some("https://example.com/ep2.jpg")
+        );
+    }
+
     #[test]
     fn set_played_status() {
         let mut db = Database::connect_in_memory().unwrap();
@@ -906,6 +1251,209 @@ mod tests {
         assert!(!ep1.played);
     }
 
+    #[test]
+    fn podcast_speed_defaults_and_updates() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+
+        let podcasts = db.get_podcasts().unwrap();
+        assert!((podcasts[0].speed - 1.0).abs() < f32::EPSILON);
+
+        db.set_podcast_speed(podcasts[0].id, 1.5).unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        assert!((podcasts[0].speed - 1.5).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn podcast_volume_offset_defaults_and_updates() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!(podcasts[0].volume_offset, 0);
+
+        db.set_podcast_volume_offset(podcasts[0].id, -15).unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!(podcasts[0].volume_offset, -15);
+    }
+
+    #[test]
+    fn podcast_equalizer_defaults_and_updates() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!(podcasts[0].equalizer, None);
+
+        db.set_podcast_equalizer(podcasts[0].id, EqPreset::VoiceBoost)
+            .unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!(podcasts[0].equalizer, Some(EqPreset::VoiceBoost));
+    }
+
+    #[test]
+    fn podcast_sort_defaults_and_updates() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!(podcasts[0].sort, SortMode::Newest);
+
+        db.set_podcast_sort(podcasts[0].id, SortMode::Oldest)
+            .unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!(podcasts[0].sort, SortMode::Oldest);
+    }
+
+    #[test]
+    fn podcast_skips_default_and_update() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!((podcasts[0].skip_intro, podcasts[0].skip_outro), (0, 0));
+
+        db.set_podcast_skips(podcasts[0].id, 45, 30).unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!((podcasts[0].skip_intro, podcasts[0].skip_outro), (45, 30));
+    }
+
+    #[test]
+    fn chapters_replaced_and_loaded() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        let ep_id = podcasts[0].episodes.map(|ep| ep.id, false)[0];
+        let chapter = |start, title: &str| Chapter {
+            start,
+            title: title.to_string(),
+        };
+
+        db.set_chapters(ep_id, &[chapter(0, "Old")]).unwrap();
+        db.set_chapters(ep_id, &[chapter(0, "Intro"), chapter(90, "Main")])
+            .unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        let chapters = podcasts[0]
+            .episodes
+            .map_single(ep_id, |ep| ep.chapters.clone())
+            .unwrap();
+        assert_eq!(chapters, vec![chapter(0, "Intro"), chapter(90, "Main")]);
+        let eps = db.get_episodes(podcasts[0].id).unwrap();
+        let ep = eps.iter().find(|ep| ep.id == ep_id).unwrap();
+        assert_eq!(ep.chapters.len(), 2);
+
+        db.remove_podcast(podcasts[0].id).unwrap();
+        assert!(db.get_chapters(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn chapters_checked_until_downloaded() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+        let pod_id = db.get_podcasts().unwrap()[0].id;
+        let checked = |db: &Database| db.get_episodes(pod_id).unwrap()[0].chapters_checked;
+        let ep_id = db.get_episodes(pod_id).unwrap()[0].id;
+        assert!(!checked(&db));
+
+        db.set_chapters(ep_id, &[]).unwrap();
+        assert!(checked(&db));
+
+        db.insert_file(ep_id, Path::new("/tmp/ep.mp3"), None)
+            .unwrap();
+        assert!(!checked(&db));
+    }
+
+    #[test]
+    fn time_saved_accumulates() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        let eps = db.get_episodes(podcasts[0].id).unwrap();
+        let ep_id = eps[0].id;
+        assert_eq!(eps[0].time_saved, Duration::ZERO);
+
+        db.add_time_saved(ep_id, Duration::from_millis(1500))
+            .unwrap();
+        db.add_time_saved(ep_id, Duration::from_millis(2250))
+            .unwrap();
+        let eps = db.get_episodes(podcasts[0].id).unwrap();
+        let ep = eps.iter().find(|ep| ep.id == ep_id).unwrap();
+        assert_eq!(ep.time_saved, Duration::from_millis(3750));
+    }
+
+    #[test]
+    fn last_played_saved() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+        let podcasts = db.get_podcasts().unwrap();
+        let eps = db.get_episodes(podcasts[0].id).unwrap();
+        let ep_id = eps[0].id;
+        assert_eq!(eps[0].last_played, None);
+
+        let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        db.set_last_played(ep_id, time).unwrap();
+        let eps = db.get_episodes(podcasts[0].id).unwrap();
+        let ep = eps.iter().find(|ep| ep.id == ep_id).unwrap();
+        assert_eq!(ep.last_played, Some(time));
+    }
+
+    #[test]
+    fn checkpoint_saved_and_cleared() {
+        let mut db = Database::connect_in_memory().unwrap();
+        db.insert_podcast(&sample_podcast()).unwrap();
+        let pods = db.get_podcasts().unwrap();
+        let ep_id = db.get_episodes(pods[0].id).unwrap()[0].id;
+        assert_eq!(db.get_checkpoint().unwrap(), None);
+
+        let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        db.set_checkpoint(ep_id, Duration::from_millis(95_250), time)
+            .unwrap();
+        assert_eq!(
+            db.get_checkpoint().unwrap(),
+            Some((ep_id, Duration::from_millis(95_250)))
+        );
+        let eps = db.get_episodes(pods[0].id).unwrap();
+        let ep = eps.iter().find(|ep| ep.id == ep_id).unwrap();
+        assert_eq!(ep.position, 95);
+        assert_eq!(ep.last_played, Some(time));
+
+        db.clear_checkpoint().unwrap();
+        assert_eq!(db.get_checkpoint().unwrap(), None);
+    }
+
+    #[test]
+    fn create_adds_missing_columns() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE podcasts (
+                id INTEGER PRIMARY KEY NOT NULL,
+                title TEXT NOT NULL,
+                url TEXT NOT NULL UNIQUE,
+                description TEXT,
+                author TEXT,
+                explicit INTEGER,
+                last_checked INTEGER
+            );",
+            params![],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO podcasts (title, url, last_checked) VALUES ('Old', 'url', 0);",
+            params![],
+        )
+        .unwrap();
+        let db = Database { conn: Some(conn) };
+        db.create().unwrap();
+        // running it again must not fail on the existing columns
+        db.create().unwrap();
+
+        let podcasts = db.get_podcasts().unwrap();
+        assert_eq!(podcasts[0].title, "Old");
+        assert!((podcasts[0].speed - 1.0).abs() < f32::EPSILON);
+        assert_eq!(podcasts[0].volume_offset, 0);
+        assert_eq!(podcasts[0].skip_intro, 0);
+    }
+
     #[test]
     fn insert_and_remove_file() {
         let mut db = Database::connect_in_memory().unwrap();
@@ -916,12 +1464,19 @@ mod tests {
         let eps = db.get_episodes(podcasts[0].id).unwrap();
         let ep_id = eps[0].id;
 
-        db.insert_file(ep_id, Path::new("/tmp/episode1.mp3"))
+        db.insert_file(ep_id, Path::new("/tmp/episode1.mp3"), Some(-18.5))
             .unwrap();
 
         let eps = db.get_episodes(podcasts[0].id).unwrap();
         let ep = eps.iter().find(|e| e.id == ep_id).unwrap();
         assert_eq!(ep.path.as_deref(), Some(Path::new("/tmp/episode1.mp3")));
+        assert_eq!(ep.loudness, Some(-18.5));
+        let podcasts = db.get_podcasts().unwrap();
+        let loudness = podcasts[0]
+            .episodes
+            .map_single(ep_id, |ep| ep.loudness)
+            .unwrap();
+        assert_eq!(loudness, Some(-18.5));
 
         db.remove_file(ep_id).unwrap();
 
@@ -1,20 +1,36 @@
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
 use sanitize_filename::{Options, sanitize_with_options};
+use tokio::io::AsyncWriteExt;
 use tokio::sync::Semaphore;
 
-use crate::types::Message;
-use crate::utils::audio_duration_file;
+use crate::loudness::integrated_loudness_file;
+use crate::types::{Menuable, Message};
+use crate::utils::{StringUtils, audio_duration_file, format_duration, format_size, url_extension};
+
+/// Shortest time between two progress reports of a download
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
 
 /// Enum used for communicating download results back to the main controller.
 #[derive(Debug)]
 pub enum DownloadMsg {
+    /// The response for an episode arrived, with the size of the file when
+    /// the server tells it
+    Started(i64, Option<u64>),
+    /// Bytes of an episode received so far
+    Progress(i64, u64),
     Complete(EpData),
     Error(EpData, DownloadError),
+    /// A download was stopped by the user, its partial file removed
+    Cancelled(EpData),
+    /// An episode was streamed completely, with its id, the file it was
+    /// saved to and the mime type of the stream
+    Streamed(i64, PathBuf, Option<String>),
 }
 
 #[derive(Debug)]
@@ -34,23 +50,177 @@ pub struct EpData {
     pub pubdate: Option<DateTime<Utc>>,
     pub file_path: Option<PathBuf>,
     pub duration: Option<u64>,
+    /// Integrated loudness in LUFS, measured when normalization is enabled
+    pub loudness: Option<f32>,
+}
+
+/// Where a download stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadStatus {
+    Queued,
+    Active,
+    Failed,
+    Cancelled,
+    Complete,
+}
+
+/// A download listed in the downloads panel, with its progress
+#[derive(Debug)]
+pub struct Download {
+    pub ep_id: i64,
+    pub pod_id: i64,
+    pub title: String,
+    pub status: DownloadStatus,
+    /// Bytes received so far
+    pub done: u64,
+    /// Size of the file, when the server tells it
+    pub total: Option<u64>,
+    /// When the response started to arrive
+    started: Option<Instant>,
+    /// Set to ask the download task to stop
+    cancel: Arc<AtomicBool>,
+}
+
+impl Download {
+    pub fn new(ep_data: &EpData) -> Self {
+        Self {
+            ep_id: ep_data.id,
+            pod_id: ep_data.pod_id,
+            title: ep_data.title.clone(),
+            status: DownloadStatus::Queued,
+            done: 0,
+            total: None,
+            started: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether it is still waiting or running
+    pub const fn is_pending(&self) -> bool {
+        matches!(self.status, DownloadStatus::Queued | DownloadStatus::Active)
+    }
+
+    pub fn start(&mut self, total: Option<u64>) {
+        self.status = DownloadStatus::Active;
+        self.total = total;
+        self.done = 0;
+        self.started = Some(Instant::now());
+    }
+
+    /// Flag the download task checks to stop early
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.cancel)
+    }
+
+    /// Asks the download task to stop and remove what it wrote. A queued
+    /// download never starts, so it is cancelled right away.
+    pub fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        if self.status == DownloadStatus::Queued {
+            self.status = DownloadStatus::Cancelled;
+        }
+    }
+
+    /// Average speed since the response started, in bytes per second
+    #[allow(clippy::cast_precision_loss)]
+    pub fn speed(&self) -> Option<f64> {
+        let elapsed = self.started?.elapsed().as_secs_f64();
+        (elapsed > 0.0 && self.done > 0).then(|| self.done as f64 / elapsed)
+    }
+
+    /// Time left at the current speed, when the size is known
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn eta(&self) -> Option<u64> {
+        let left = self.total?.saturating_sub(self.done);
+        self.speed()
+            .map(|speed| (left as f64 / speed).round() as u64)
+    }
+
+    /// Progress, speed and time left, or how it ended
+    fn status_label(&self) -> String {
+        match self.status {
+            DownloadStatus::Queued => "queued".to_string(),
+            DownloadStatus::Active if self.cancel.load(Ordering::Relaxed) => {
+                "cancelling".to_string()
+            }
+            DownloadStatus::Active => {
+                let done = format_size(self.done);
+                let size = match self.total {
+                    Some(total) => format!("{done}/{}", format_size(total)),
+                    None => done,
+                };
+                let speed = self
+                    .speed()
+                    .map(|speed| {
+                        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                        let speed = speed as u64;
+                        format!(" {}/s", format_size(speed))
+                    })
+                    .unwrap_or_default();
+                let eta = self
+                    .eta()
+                    .map(|eta| format!(" {}", format_duration(Some(eta))))
+                    .unwrap_or_default();
+                format!("{size}{speed}{eta}")
+            }
+            DownloadStatus::Failed => "failed".to_string(),
+            DownloadStatus::Cancelled => "cancelled".to_string(),
+            DownloadStatus::Complete => format!("done {}", format_size(self.done)),
+        }
+    }
+}
+
+impl Menuable for Download {
+    /// Returns the database ID of the episode downloaded.
+    fn get_id(&self) -> i64 {
+        self.ep_id
+    }
+
+    /// Returns the title of the episode with the state of the download,
+    /// up to length characters.
+    fn get_title(&self, length: usize) -> String {
+        let meta = format!("[{}]", self.status_label());
+        let meta_len = meta.grapheme_len();
+        if length > meta_len + 5 {
+            let title = self.title.substr(0, length - meta_len - 3);
+            format!(
+                " {title} {meta:>width$} ",
+                width = length - title.grapheme_len() - 3
+            )
+        } else {
+            format!(" {} ", self.title.substr(0, length.saturating_sub(2)))
+        }
+    }
+
+    fn is_played(&self) -> bool {
+        !self.is_pending()
+    }
 }
 
 /// This is the function the main controller uses to indicate new
 /// files to download. It spawns async tasks for every episode to be
-/// downloaded. New jobs can be requested by the user while there are
-/// still ongoing jobs.
+/// downloaded, each with the flag that cancels it. New jobs can be
+/// requested by the user while there are still ongoing jobs.
 pub fn download_list(
-    episodes: Vec<EpData>, dest: &Path, max_retries: usize, semaphore: &Arc<Semaphore>,
-    tx_to_main: &Sender<Message>,
+    episodes: Vec<(EpData, Arc<AtomicBool>)>, dest: &Path, max_retries: usize,
+    measure_loudness: bool, semaphore: &Arc<Semaphore>, tx_to_main: &Sender<Message>,
 ) {
-    for ep in episodes {
+    for (ep, cancel) in episodes {
         let tx = tx_to_main.clone();
         let dest2 = dest.to_path_buf();
         let sem = Arc::clone(semaphore);
         tokio::spawn(async move {
             let _permit = sem.acquire().await;
-            let result = download_file(ep, dest2, max_retries).await;
+            // cancelled while queued, the main controller already knows
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let result =
+                download_file(ep, dest2, max_retries, measure_loudness, &cancel, &tx).await;
             if tx.send(Message::Dl(result)).is_err() {
                 log::error!("Failed to send download message: channel closed");
             }
@@ -58,9 +228,12 @@ pub fn download_list(
     }
 }
 
-/// Downloads a file to a local filepath, returning `DownloadMsg` variant
-/// indicating success or failure.
-async fn download_file(mut ep_data: EpData, dest: PathBuf, mut max_retries: usize) -> DownloadMsg {
+/// Downloads a file to a local filepath, reporting its progress along the
+/// way, and returns `DownloadMsg` variant indicating success or failure.
+async fn download_file(
+    mut ep_data: EpData, dest: PathBuf, mut max_retries: usize, measure_loudness: bool,
+    cancel: &AtomicBool, tx: &Sender<Message>,
+) -> DownloadMsg {
     let Ok(client) = reqwest::Client::builder()
         .connect_timeout(Duration::from_secs(10))
         .timeout(Duration::from_mins(2))
@@ -69,7 +242,10 @@ async fn download_file(mut ep_data: EpData, dest: PathBuf, mut max_retries: usiz
         return DownloadMsg::Error(ep_data, DownloadError::Response);
     };
 
-    let response = loop {
+    let mut response = loop {
+        if cancel.load(Ordering::Relaxed) {
+            return DownloadMsg::Cancelled(ep_data);
+        }
         if let Ok(resp) = client.get(&ep_data.url).send().await {
             break resp;
         }
@@ -88,6 +264,90 @@ async fn download_file(mut ep_data: EpData, dest: PathBuf, mut max_retries: usiz
     // assume .mp3 unless we figure out otherwise
     let ext = get_file_ext(header, &ep_data.url).unwrap_or("mp3");
 
+    let file_path = dest.join(file_name(&ep_data, ext));
+
+    let send = |msg| {
+        if tx.send(Message::Dl(msg)).is_err() {
+            log::error!("Failed to send download message: channel closed");
+        }
+    };
+    send(DownloadMsg::Started(ep_data.id, response.content_length()));
+
+    let Ok(mut file) = tokio::fs::File::create(&file_path).await else {
+        return DownloadMsg::Error(ep_data, DownloadError::FileCreate);
+    };
+    let mut done = 0;
+    let mut last_report = Instant::now();
+    let error = loop {
+        if cancel.load(Ordering::Relaxed) {
+            break None;
+        }
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if file.write_all(&chunk).await.is_err() {
+                    break Some(DownloadError::FileWrite);
+                }
+                done += chunk.len() as u64;
+                if last_report.elapsed() >= PROGRESS_INTERVAL {
+                    send(DownloadMsg::Progress(ep_data.id, done));
+                    last_report = Instant::now();
+                }
+            }
+            Ok(None) => match file.flush().await {
+                Ok(()) => {
+                    send(DownloadMsg::Progress(ep_data.id, done));
+                    drop(file);
+                    ep_data.file_path = Some(file_path.clone());
+                    measure_file(&mut ep_data, &file_path, measure_loudness).await;
+                    return DownloadMsg::Complete(ep_data);
+                }
+                Err(_) => break Some(DownloadError::FileWrite),
+            },
+            // the connection failed, not the disk
+            Err(_) => break Some(DownloadError::Response),
+        }
+    };
+
+    // do not leave a partial file behind
+    drop(file);
+    tokio::fs::remove_file(&file_path).await.ok();
+    match error {
+        Some(err) => DownloadMsg::Error(ep_data, err),
+        None => DownloadMsg::Cancelled(ep_data),
+    }
+}
+
+/// Moves the file of a completely streamed episode to the download
+/// directory, then reports it like a finished download.
+pub fn keep_streamed(
+    mut ep_data: EpData, streamed: PathBuf, mime_type: Option<&str>, dest: &Path,
+    measure_loudness: bool, tx_to_main: &Sender<Message>,
+) {
+    let ext = get_file_ext(mime_type, &ep_data.url).unwrap_or("mp3");
+    let file_path = dest.join(file_name(&ep_data, ext));
+    let tx = tx_to_main.clone();
+    tokio::spawn(async move {
+        let moved = match tokio::fs::rename(&streamed, &file_path).await {
+            Ok(()) => Ok(()),
+            // the download path could be on another file system
+            Err(_) => tokio::fs::copy(&streamed, &file_path).await.map(|_| ()),
+        };
+        tokio::fs::remove_file(&streamed).await.ok();
+        ep_data.file_path = Some(file_path.clone());
+        let msg = if moved.is_ok() {
+            measure_file(&mut ep_data, &file_path, measure_loudness).await;
+            DownloadMsg::Complete(ep_data)
+        } else {
+            DownloadMsg::Error(ep_data, DownloadError::FileCreate)
+        };
+        if tx.send(Message::Dl(msg)).is_err() {
+            log::error!("Failed to send download message: channel closed");
+        }
+    });
+}
+
+/// Name of the file an episode is saved to, from its title and date
+fn file_name(ep_data: &EpData, ext: &str) -> String {
     let mut file_name = sanitize_with_options(
         &ep_data.title,
         Options {
@@ -100,25 +360,31 @@ async fn download_file(mut ep_data: EpData, dest: PathBuf, mut max_retries: usiz
     if let Some(pubdate) = ep_data.pubdate {
         file_name = format!("{}_{}", file_name, pubdate.format("%Y%m%d_%H%M%S"));
     }
+    format!("{file_name}.{ext}")
+}
 
-    let mut file_path = dest;
-    file_path.push(format!("{file_name}.{ext}"));
-
-    let Ok(bytes) = response.bytes().await else {
-        return DownloadMsg::Error(ep_data, DownloadError::FileWrite);
-    };
-
-    ep_data.file_path = Some(file_path.clone());
-
-    if tokio::fs::write(&file_path, &bytes).await.is_ok() {
-        let path = file_path.clone();
-        ep_data.duration = tokio::task::spawn_blocking(move || audio_duration_file(path))
+/// Reads the duration of a saved episode, and its loudness if requested.
+async fn measure_file(ep_data: &mut EpData, file_path: &Path, measure_loudness: bool) {
+    let path = file_path.to_path_buf();
+    ep_data.duration = tokio::task::spawn_blocking(move || audio_duration_file(&path))
+        .await
+        .ok()
+        .and_then(Result::ok);
+    if measure_loudness {
+        let path = file_path.to_path_buf();
+        ep_data.loudness = tokio::task::spawn_blocking(move || integrated_loudness_file(&path))
             .await
             .ok()
-            .and_then(Result::ok);
-        DownloadMsg::Complete(ep_data)
-    } else {
-        DownloadMsg::Error(ep_data, DownloadError::FileCreate)
+            .and_then(|loudness| {
+                loudness
+                    .inspect_err(|err| log::warn!("Could not measure loudness: {err}"))
+                    .ok()
+            })
+            .map(|loudness| {
+                #[allow(clippy::cast_possible_truncation)]
+                let loudness = loudness as f32;
+                loudness
+            });
     }
 }
 
@@ -147,11 +413,54 @@ fn get_file_ext<'a>(mime_type: Option<&str>, url: &'a str) -> Option<&'a str> {
         Some("video/quicktime") => Some("mov"),
         Some("video/x-m4v") => Some("m4v"),
         // Otherwise, use the extension in the URL as a fallback
-        _ => {
-            // Look for what's after the last slash (/)
-            url.rsplit('/')
-                .next()
-                .and_then(|file_name| file_name.rsplit('.').next())
-        }
+        _ => url_extension(url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_download() -> Download {
+        Download::new(&EpData {
+            id: 7,
+            pod_id: 1,
+            title: "Episode".to_string(),
+            url: "https://example.com/ep.mp3".to_string(),
+            pubdate: None,
+            file_path: None,
+            duration: None,
+            loudness: None,
+        })
+    }
+
+    #[test]
+    fn cancelling_queued_download_stops_it() {
+        let mut download = make_download();
+        download.cancel();
+        assert_eq!(download.status, DownloadStatus::Cancelled);
+        assert!(download.cancel_flag().load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn cancelling_active_download_waits_for_task() {
+        let mut download = make_download();
+        download.start(Some(1000));
+        download.cancel();
+        assert!(download.is_pending());
+        assert!(download.get_title(40).contains("[cancelling]"));
+    }
+
+    #[test]
+    fn file_ext_ignores_query() {
+        assert_eq!(
+            get_file_ext(None, "https://example.com/ep.mp3?id=1"),
+            Some("mp3")
+        );
+        assert_eq!(get_file_ext(None, "https://example.com/episode?id=5"), None);
+        assert_eq!(
+            get_file_ext(Some("audio/mp4"), "https://example.com/episode?id=5"),
+            Some("mp4")
+        );
     }
 }
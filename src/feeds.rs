@@ -97,6 +97,13 @@ fn parse_feed_data(channel: Channel, url: &str) -> PodcastNoId {
     let description = Some(channel.description().to_string());
     let last_checked = Utc::now();
 
+    // the itunes artwork is usually the larger one
+    let image_url = channel
+        .itunes_ext()
+        .and_then(|itunes| itunes.image())
+        .or_else(|| channel.image().map(rss::Image::url))
+        .map(std::string::ToString::to_string);
+
     let mut author = None;
     let explicit = channel.itunes_ext().and_then(|itunes| {
         author = itunes.author().map(std::string::ToString::to_string);
@@ -124,6 +131,7 @@ fn parse_feed_data(channel: Channel, url: &str) -> PodcastNoId {
         description,
         author,
         explicit,
+        image_url,
         last_checked,
         episodes,
     }
@@ -151,6 +159,19 @@ fn parse_episode_data(item: &Item) -> EpisodeNoId {
         .pub_date()
         .and_then(|pd| DateTime::parse_from_rfc2822(pd).map(|dt| dt.to_utc()).ok());
 
+    let chapters_url = item
+        .extensions()
+        .get("podcast")
+        .and_then(|podcast| podcast.get("chapters"))
+        .and_then(|chapters| chapters.first())
+        .and_then(|chapters| chapters.attrs().get("url"))
+        .cloned();
+
+    let image_url = item
+        .itunes_ext()
+        .and_then(|itunes| itunes.image())
+        .map(std::string::ToString::to_string);
+
     let duration = if let Some(itunes) = item.itunes_ext()
         && let Some(itures_duration) = itunes.duration()
     {
@@ -166,6 +187,8 @@ fn parse_episode_data(item: &Item) -> EpisodeNoId {
         description,
         pubdate,
         duration,
+        chapters_url,
+        image_url,
     }
 }
 
@@ -346,5 +369,68 @@ mod tests {
         assert!(ep.description.is_empty());
         assert!(ep.pubdate.is_none());
         assert!(ep.duration.is_none());
+        assert!(ep.chapters_url.is_none());
+        assert!(ep.image_url.is_none());
+    }
+
+    #[test]
+    fn podcast_chapters_url() {
+        let feed = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <rss version="2.0" xmlns:podcast="https://podcastindex.org/namespace/1.0">
+            <channel>
+                <title>Chapters</title>
+                <item>
+                    <title>Episode</title>
+                    <podcast:chapters url="https://example.com/ep.json" type="application/json+chapters"/>
+                </item>
+            </channel>
+            </rss>"#;
+        let channel = Channel::read_from(feed.as_bytes()).unwrap();
+        let data = parse_feed_data(channel, "dummy");
+        assert_eq!(
+            data.episodes[0].chapters_url.as_deref(),
+            Some("https://example.com/ep.json")
+        );
+    }
+
+    #[test]
+    fn artwork_urls() {
+        let feed = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+            <channel>
+                <title>Artwork</title>
+                <image>
+                    <url>https://example.com/channel.png</url>
+                    <title>Artwork</title>
+                    <link>https://example.com</link>
+                </image>
+                <item>
+                    <title>With art</title>
+                    <itunes:image href="https://example.com/ep.jpg"/>
+                </item>
+                <item>
+                    <title>Without art</title>
+                </item>
+            </channel>
+            </rss>"#;
+        let channel = Channel::read_from(feed.as_bytes()).unwrap();
+        let data = parse_feed_data(channel, "dummy");
+        assert_eq!(
+            data.image_url.as_deref(),
+            Some("https://example.com/channel.png")
+        );
+        assert_eq!(
+            data.episodes[0].image_url.as_deref(),
+            Some("https://example.com/ep.jpg")
+        );
+        assert!(data.episodes[1].image_url.is_none());
+
+        let path = "./tests/test.xml";
+        let channel = Channel::read_from(open_file(path)).unwrap();
+        let data = parse_feed_data(channel, "dummy");
+        assert!(
+            data.image_url
+                .is_some_and(|url| url.starts_with("https://images.megaphone.fm/"))
+        );
     }
 }
@@ -38,19 +38,56 @@ pub enum UserAction {
 
     FilterPlayed,
     FilterDownloaded,
+    Sort,
 
     Enqueue,
+    Select,
+    SelectRange,
 
     Help,
     Quit,
 
     UnplayedList,
+    DownloadsList,
     Information,
     Back,
     Switch,
     PlayExternal,
 
     ResetPlayer,
+
+    SpeedUp,
+    SpeedDown,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    SleepTimer,
+    OutputDevice,
+    Equalizer,
+    Mono,
+    BalanceLeft,
+    BalanceRight,
+
+    SeekBackLong,
+    SeekForwardLong,
+    GoToTimestamp,
+    NextChapter,
+    PreviousChapter,
+    Search,
+    NextMatch,
+    PreviousMatch,
+    /// Opens a link of the details popup, asking for its number
+    OpenLink,
+    /// Copies a link of the details popup to the clipboard
+    CopyLink,
+    /// Jumps to the given tenth of the episode
+    JumpTo(u8),
+    /// Change the intro or outro skipped for the podcast in the details
+    /// popup
+    IntroShorter,
+    IntroLonger,
+    OutroShorter,
+    OutroLonger,
 }
 
 /// Wrapper around a hash map that keeps track of all keybindings. Multiple
@@ -109,14 +146,43 @@ impl Keybindings {
             (config.remove, UserAction::Remove),
             (config.filter_played, UserAction::FilterPlayed),
             (config.filter_downloaded, UserAction::FilterDownloaded),
+            (config.sort, UserAction::Sort),
             (config.enqueue, UserAction::Enqueue),
+            (config.select, UserAction::Select),
+            (config.select_range, UserAction::SelectRange),
             (config.help, UserAction::Help),
             (config.quit, UserAction::Quit),
             (config.unplayed_list, UserAction::UnplayedList),
+            (config.downloads_list, UserAction::DownloadsList),
             (config.back, UserAction::Back),
             (config.switch, UserAction::Switch),
             (config.play_external, UserAction::PlayExternal),
             (config.reset_player, UserAction::ResetPlayer),
+            (config.speed_up, UserAction::SpeedUp),
+            (config.speed_down, UserAction::SpeedDown),
+            (config.volume_up, UserAction::VolumeUp),
+            (config.volume_down, UserAction::VolumeDown),
+            (config.mute, UserAction::Mute),
+            (config.sleep_timer, UserAction::SleepTimer),
+            (config.output_device, UserAction::OutputDevice),
+            (config.equalizer, UserAction::Equalizer),
+            (config.mono, UserAction::Mono),
+            (config.balance_left, UserAction::BalanceLeft),
+            (config.balance_right, UserAction::BalanceRight),
+            (config.seek_back_long, UserAction::SeekBackLong),
+            (config.seek_forward_long, UserAction::SeekForwardLong),
+            (config.go_to_timestamp, UserAction::GoToTimestamp),
+            (config.next_chapter, UserAction::NextChapter),
+            (config.previous_chapter, UserAction::PreviousChapter),
+            (config.search, UserAction::Search),
+            (config.next_match, UserAction::NextMatch),
+            (config.previous_match, UserAction::PreviousMatch),
+            (config.open_link, UserAction::OpenLink),
+            (config.copy_link, UserAction::CopyLink),
+            (config.intro_shorter, UserAction::IntroShorter),
+            (config.intro_longer, UserAction::IntroLonger),
+            (config.outro_shorter, UserAction::OutroShorter),
+            (config.outro_longer, UserAction::OutroLonger),
         ];
 
         let mut keymap = Self::default();
@@ -126,6 +192,13 @@ impl Keybindings {
                 keymap.1.insert(action, config);
             }
         }
+        // one key for each tenth, in order
+        if let Some(keys) = config.jump_to {
+            for (tenth, key) in (0..10).zip(keys) {
+                keymap.insert_from_vec(vec![key.clone()], UserAction::JumpTo(tenth));
+                keymap.1.insert(UserAction::JumpTo(tenth), vec![key]);
+            }
+        }
         keymap
     }
 
@@ -180,16 +253,54 @@ impl Keybindings {
             (UserAction::Remove, vec!["r".to_string()]),
             (UserAction::FilterPlayed, vec!["1".to_string()]),
             (UserAction::FilterDownloaded, vec!["2".to_string()]),
+            (UserAction::Sort, vec!["3".to_string()]),
             (UserAction::Enqueue, vec!["e".to_string()]),
+            (UserAction::Select, vec!["v".to_string()]),
+            (UserAction::SelectRange, vec!["V".to_string()]),
             (UserAction::Help, vec!["?".to_string()]),
             (UserAction::Quit, vec!["q".to_string()]),
             (UserAction::UnplayedList, vec!["u".to_string()]),
+            (UserAction::DownloadsList, vec!["w".to_string()]),
             (UserAction::Information, vec!["i".to_string()]),
             (UserAction::Back, vec!["Esc".to_string()]),
             (UserAction::Switch, vec!["Tab".to_string()]),
             (UserAction::PlayExternal, vec!["P".to_string()]),
             (UserAction::ResetPlayer, vec!["R".to_string()]),
+            (UserAction::SpeedUp, vec![">".to_string()]),
+            (UserAction::SpeedDown, vec!["<".to_string()]),
+            (UserAction::VolumeUp, vec!["+".to_string()]),
+            (UserAction::VolumeDown, vec!["-".to_string()]),
+            (UserAction::Mute, vec!["#".to_string()]),
+            (UserAction::SleepTimer, vec!["z".to_string()]),
+            (UserAction::OutputDevice, vec!["o".to_string()]),
+            (UserAction::Equalizer, vec!["E".to_string()]),
+            (UserAction::Mono, vec!["O".to_string()]),
+            (UserAction::BalanceLeft, vec!["(".to_string()]),
+            (UserAction::BalanceRight, vec![")".to_string()]),
+            (
+                UserAction::SeekBackLong,
+                vec!["Shift+Left".to_string(), "H".to_string()],
+            ),
+            (
+                UserAction::SeekForwardLong,
+                vec!["Shift+Right".to_string(), "L".to_string()],
+            ),
+            (UserAction::GoToTimestamp, vec!["t".to_string()]),
+            (UserAction::NextChapter, vec![".".to_string()]),
+            (UserAction::PreviousChapter, vec![",".to_string()]),
+            (UserAction::Search, vec!["/".to_string()]),
+            (UserAction::NextMatch, vec!["n".to_string()]),
+            (UserAction::PreviousMatch, vec!["N".to_string()]),
+            (UserAction::OpenLink, vec!["f".to_string()]),
+            (UserAction::CopyLink, vec!["y".to_string()]),
+            (UserAction::IntroShorter, vec!["[".to_string()]),
+            (UserAction::IntroLonger, vec!["]".to_string()]),
+            (UserAction::OutroShorter, vec!["{".to_string()]),
+            (UserAction::OutroLonger, vec!["}".to_string()]),
         ]
+        .into_iter()
+        .chain((0..10).map(|tenth| (UserAction::JumpTo(tenth), vec![format!("Alt+{tenth}")])))
+        .collect()
     }
 }
 
@@ -286,15 +397,46 @@ mod tests {
             UserAction::Remove,
             UserAction::FilterPlayed,
             UserAction::FilterDownloaded,
+            UserAction::Sort,
             UserAction::Enqueue,
+            UserAction::Select,
+            UserAction::SelectRange,
             UserAction::Help,
             UserAction::Quit,
             UserAction::UnplayedList,
+            UserAction::DownloadsList,
             UserAction::Information,
             UserAction::Back,
             UserAction::Switch,
             UserAction::PlayExternal,
             UserAction::ResetPlayer,
+            UserAction::SpeedUp,
+            UserAction::SpeedDown,
+            UserAction::VolumeUp,
+            UserAction::VolumeDown,
+            UserAction::Mute,
+            UserAction::SleepTimer,
+            UserAction::OutputDevice,
+            UserAction::Equalizer,
+            UserAction::Mono,
+            UserAction::BalanceLeft,
+            UserAction::BalanceRight,
+            UserAction::SeekBackLong,
+            UserAction::SeekForwardLong,
+            UserAction::GoToTimestamp,
+            UserAction::NextChapter,
+            UserAction::PreviousChapter,
+            UserAction::Search,
+            UserAction::NextMatch,
+            UserAction::PreviousMatch,
+            UserAction::OpenLink,
+            UserAction::CopyLink,
+            UserAction::JumpTo(0),
+            UserAction::JumpTo(9),
+            UserAction::IntroShorter,
+            UserAction::IntroLonger,
+            UserAction::OutroShorter,
+            UserAction::OutroLonger,
         ];
         for action in actions {
             assert!(
@@ -437,4 +579,19 @@ mod tests {
         let input = key_event(KeyCode::Up, KeyModifiers::CONTROL);
         assert_eq!(kb.get_from_input(input), Some(&UserAction::MoveUp));
     }
+
+    #[test]
+    fn alt_digits_jump_to_tenths() {
+        let kb = Keybindings::default();
+        let input = key_event(KeyCode::Char('3'), KeyModifiers::ALT);
+        assert_eq!(kb.get_from_input(input), Some(&UserAction::JumpTo(3)));
+
+        let config = KeybindingsFromToml {
+            jump_to: Some(vec!["F1".to_string(), "F2".to_string()]),
+            ..Default::default()
+        };
+        let kb = Keybindings::from_config(config);
+        let input = key_event(KeyCode::F(2), KeyModifiers::NONE);
+        assert_eq!(kb.get_from_input(input), Some(&UserAction::JumpTo(1)));
+    }
 }
@@ -0,0 +1,263 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use symphonia::core::audio::SampleBuffer;
+
+use crate::utils::AudioTrack;
+
+/// Blocks quieter than this are ignored, in LUFS
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks this far below the ungated loudness are ignored, in LU
+const RELATIVE_GATE: f64 = -10.0;
+/// Number of 100 ms steps in one 400 ms measurement block
+const STEPS_PER_BLOCK: usize = 4;
+
+/// Second order IIR filter in direct form I.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    const fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0].mul_add(
+            x,
+            self.b[1].mul_add(
+                self.x[0],
+                self.b[2].mul_add(
+                    self.x[1],
+                    (-self.a[0]).mul_add(self.y[0], -self.a[1] * self.y[1]),
+                ),
+            ),
+        );
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// Filters of the K-weighting curve from ITU-R BS.1770, a high shelf
+/// modelling the head followed by a high pass.
+#[allow(clippy::suboptimal_flops)]
+fn k_weighting(rate: u32) -> [Biquad; 2] {
+    let rate = f64::from(rate);
+
+    let (f0, gain, q) = (
+        1_681.974_450_955_533,
+        3.999_843_853_973_347,
+        0.707_175_236_955_419_6,
+    );
+    let k = (PI * f0 / rate).tan();
+    let vh = 10_f64.powf(gain / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.135_470_876_024_44, 0.500_327_037_323_877_3);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+fn energy_to_loudness(energy: f64) -> f64 {
+    10.0_f64.mul_add(energy.log10(), -0.691)
+}
+
+fn loudness_to_energy(loudness: f64) -> f64 {
+    10_f64.powf((loudness + 0.691) / 10.0)
+}
+
+/// Measures loudness following EBU R128: K-weighted energy of 400 ms
+/// blocks overlapping by 75%, then gated to leave silence out.
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    filters: Vec<[Biquad; 2]>,
+    channel: usize,
+    step_frames: usize,
+    frames: usize,
+    energy: f64,
+    /// Energy of the last steps, to build the overlapping blocks
+    steps: Vec<f64>,
+    /// Mean energy of every block above the absolute gate
+    blocks: Vec<f64>,
+    gated_energy: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: usize, rate: u32) -> Self {
+        Self {
+            filters: vec![k_weighting(rate); channels.max(1)],
+            channel: 0,
+            step_frames: (rate as usize / 10).max(1),
+            frames: 0,
+            energy: 0.0,
+            steps: Vec::with_capacity(STEPS_PER_BLOCK),
+            blocks: Vec::new(),
+            gated_energy: 0.0,
+        }
+    }
+
+    /// Adds the next interleaved sample. Returns true when it completes a
+    /// measurement block.
+    pub fn push(&mut self, sample: f32) -> bool {
+        let [high_shelf, high_pass] = &mut self.filters[self.channel];
+        let filtered = high_pass.process(high_shelf.process(f64::from(sample)));
+        // all channels are weighted equally, which is exact for mono and
+        // stereo
+        self.energy = filtered.mul_add(filtered, self.energy);
+        self.channel += 1;
+        if self.channel < self.filters.len() {
+            return false;
+        }
+        self.channel = 0;
+        self.frames += 1;
+        if self.frames < self.step_frames {
+            return false;
+        }
+
+        if self.steps.len() == STEPS_PER_BLOCK {
+            self.steps.remove(0);
+        }
+        self.steps.push(self.energy);
+        self.energy = 0.0;
+        self.frames = 0;
+        if self.steps.len() < STEPS_PER_BLOCK {
+            return false;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let block = self.steps.iter().sum::<f64>() / (STEPS_PER_BLOCK * self.step_frames) as f64;
+        if block > loudness_to_energy(ABSOLUTE_GATE) {
+            self.blocks.push(block);
+            self.gated_energy += block;
+        }
+        true
+    }
+
+    /// Loudness of everything measured so far, only applying the absolute
+    /// gate. Cheap enough to call after every block.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn running(&self) -> Option<f64> {
+        (!self.blocks.is_empty())
+            .then(|| energy_to_loudness(self.gated_energy / self.blocks.len() as f64))
+    }
+
+    /// Integrated loudness in LUFS, None if everything was silent.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn integrated(&self) -> Option<f64> {
+        let threshold = loudness_to_energy(self.running()? + RELATIVE_GATE);
+        let (sum, count) = self
+            .blocks
+            .iter()
+            .filter(|&&block| block > threshold)
+            .fold((0.0, 0), |(sum, count), block| (sum + block, count + 1));
+        (count > 0).then(|| energy_to_loudness(sum / f64::from(count)))
+    }
+}
+
+/// Decodes a whole audio file and returns its integrated loudness in LUFS.
+pub fn integrated_loudness_file(path: &Path) -> Result<f64> {
+    let track = AudioTrack::open(
+        Box::new(File::open(path)?),
+        path.extension().and_then(|ext| ext.to_str()),
+    )?;
+    let mut meter: Option<LoudnessMeter> = None;
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+    track.decode(|audio| {
+        let spec = *audio.spec();
+        let samples = audio.capacity() * spec.channels.count();
+        let buffer = match &mut buffer {
+            Some(buffer) if buffer.capacity() >= samples => buffer,
+            buffer => buffer.insert(SampleBuffer::new(audio.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(audio);
+        let meter =
+            meter.get_or_insert_with(|| LoudnessMeter::new(spec.channels.count(), spec.rate));
+        for &sample in buffer.samples() {
+            meter.push(sample);
+        }
+    })?;
+    meter
+        .as_ref()
+        .and_then(LoudnessMeter::integrated)
+        .ok_or_else(|| anyhow!("No audio to measure"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(clippy::cast_precision_loss)]
+    fn measure(channels: usize, amplitude: f32, seconds: usize) -> Option<f64> {
+        let rate = 48_000;
+        let mut meter = LoudnessMeter::new(channels, rate);
+        for n in 0..rate as usize * seconds {
+            let sample =
+                amplitude * (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / rate as f32).sin();
+            for _ in 0..channels {
+                meter.push(sample);
+            }
+        }
+        meter.integrated()
+    }
+
+    #[test]
+    fn full_scale_sine_on_one_channel() {
+        // reference value given by EBU Tech 3341
+        let loudness = measure(1, 1.0, 5).unwrap();
+        assert!((loudness + 3.01).abs() < 0.1, "{loudness}");
+    }
+
+    #[test]
+    fn quieter_sine_on_two_channels() {
+        let loudness = measure(2, 0.1, 5).unwrap();
+        assert!((loudness + 20.0).abs() < 0.1, "{loudness}");
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        assert!(measure(2, 0.0, 2).is_none());
+    }
+
+    #[test]
+    fn quiet_parts_are_gated() {
+        let rate = 48_000;
+        let mut meter = LoudnessMeter::new(1, rate);
+        for n in 0..rate as usize * 10 {
+            // 5 seconds of a loud sine followed by 5 very quiet ones
+            let amplitude = if n < rate as usize * 5 { 0.5 } else { 0.001 };
+            #[allow(clippy::cast_precision_loss)]
+            let sample =
+                amplitude * (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / rate as f32).sin();
+            meter.push(sample);
+        }
+        let loudness = meter.integrated().unwrap();
+        let loud_only = measure(1, 0.5, 5).unwrap();
+        assert!((loudness - loud_only).abs() < 0.2, "{loudness} {loud_only}");
+    }
+}
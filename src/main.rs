@@ -14,16 +14,20 @@ use gag::Gag;
 use utils::parse_create_dir;
 
 mod app;
+mod artwork;
+mod chapters;
 mod config;
 mod db;
 mod downloads;
 mod feeds;
 mod gpodder;
 mod keymap;
+mod loudness;
 mod media_control;
 mod opml;
 mod play_file;
 mod player;
+mod show_notes;
 mod types;
 mod ui;
 mod utils;
@@ -34,8 +38,8 @@ use crate::db::Database;
 use crate::feeds::{FeedMsg, PodcastFeed};
 use crate::gpodder::{GpodderController, GpodderRequest};
 use crate::media_control::init_controls;
-use crate::player::{PlaybackStatus, Player, PlayerMessage};
-use crate::types::{LockVec, Message, SyncProgress};
+use crate::player::{Player, PlayerEvent, PlayerMessage};
+use crate::types::{FilterPanel, LockVec, Message, SortMode, SyncProgress};
 use crate::ui::UiState;
 use crate::utils::{evaluate_in_shell, get_unplayed_episodes};
 use tokio::sync::Semaphore;
@@ -220,30 +224,37 @@ async fn start_app(config: Arc<Config>, db_path: &Path, lock_file: File) -> Resu
     }
 
     let (tx_to_player, rx_from_ui) = tokio::sync::mpsc::channel(32);
-    let elapsed = Arc::new(RwLock::new(0));
-    let playing = Arc::new(RwLock::new(PlaybackStatus::Ready));
+    let (tx_player_events, rx_player_events) = tokio::sync::broadcast::channel(64);
+    let rx_control_events = tx_player_events.subscribe();
+    let rx_app_events = tx_player_events.subscribe();
+    let time_saved = Arc::new(RwLock::new(Duration::ZERO));
     let sync_progress = Arc::new(RwLock::new(SyncProgress::default()));
     // spawn_blocking + block_on because rodio types aren't Send on macOS
     blocking_tasks.push({
-        let playing_clone = playing.clone();
-        let elapsed_clone = elapsed.clone();
+        let time_saved_clone = time_saved.clone();
+        let config = config.clone();
+        let tx_to_main = tx_to_main.clone();
         tokio::task::spawn_blocking(move || {
             tokio::runtime::Handle::current().block_on(Player::spawn_async(
                 rx_from_ui,
-                elapsed_clone,
-                playing_clone,
+                tx_player_events,
+                time_saved_clone,
+                config,
+                tx_to_main,
             ));
         })
     });
 
     let (tx_to_control, rx_from_control) = mpsc::channel();
     let current_episode = Arc::new(RwLock::new(None));
+    let current_art = Arc::new(RwLock::new(None));
     let (tx_to_controls, rx_controls_from_main) = tokio::sync::oneshot::channel::<()>();
 
     match init_controls(
         tx_to_control,
         current_episode.clone(),
-        playing.clone(),
+        current_art.clone(),
+        rx_control_events,
         rx_controls_from_main,
     ) {
         Ok(task) => tasks.push(task),
@@ -266,24 +277,28 @@ async fn start_app(config: Arc<Config>, db_path: &Path, lock_file: File) -> Resu
     });
 
     let unplayed_items = LockVec::new_arc(get_unplayed_episodes(&podcast_list));
-    unplayed_items.sort();
-    unplayed_items.reverse();
+    unplayed_items.sort_by_mode(SortMode::default_for(FilterPanel::Unplayed));
+    let download_items = LockVec::new(Vec::new());
 
     blocking_tasks.push(UiState::spawn_blocking(
         config.clone(),
         podcast_list.clone(),
         queue_items.clone(),
         unplayed_items.clone(),
+        download_items.clone(),
         rx_from_main,
         tx_to_main.clone(),
         tx_to_player.clone(),
         rx_from_control,
         current_episode,
-        elapsed,
-        playing,
+        rx_player_events,
+        time_saved,
         sync_progress.clone(),
+        current_art,
     ));
 
+    tasks.push(forward_player_events(rx_app_events, tx_to_main.clone()));
+
     let mut app = App::new(
         config,
         db_inst,
@@ -294,6 +309,7 @@ async fn start_app(config: Arc<Config>, db_path: &Path, lock_file: File) -> Resu
         podcast_list,
         queue_items,
         unplayed_items,
+        download_items,
         sync_progress,
     );
 
@@ -358,6 +374,26 @@ async fn start_app(config: Arc<Config>, db_path: &Path, lock_file: File) -> Resu
     Ok(())
 }
 
+/// Passes the player events on to the main controller, until the player
+/// quits.
+fn forward_player_events(
+    mut rx_from_player: tokio::sync::broadcast::Receiver<PlayerEvent>,
+    tx_to_main: mpsc::Sender<Message>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn(async move {
+        loop {
+            let event = match rx_from_player.recv().await {
+                Ok(event) => event,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            };
+            if tx_to_main.send(Message::Player(event)).is_err() {
+                break;
+            }
+        }
+    })
+}
+
 /// Gets the path to the config file if one is specified in the command-
 /// line arguments, or else returns the default config path for the
 /// user's operating system.
@@ -1,9 +1,11 @@
 use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
+use tokio::sync::broadcast::{Receiver, error::TryRecvError};
 
 use crate::{
     config::TICK_RATE,
-    player::PlaybackStatus,
+    player::PlayerEvent,
     types::{Episode, ShareableRwLock},
 };
 
@@ -12,10 +14,14 @@ pub enum ControlMessage {
 }
 
 fn update_control_metadata(
-    title: &str, controls: &mut MediaControls,
+    title: &str, art: Option<&Path>, controls: &mut MediaControls,
 ) -> Result<(), souvlaki::Error> {
+    let cover_url = art
+        .and_then(|path| url::Url::from_file_path(path).ok())
+        .map(String::from);
     controls.set_metadata(MediaMetadata {
         title: Some(title),
+        cover_url: cover_url.as_deref(),
         ..Default::default()
     })
 }
@@ -23,7 +29,8 @@ fn update_control_metadata(
 pub fn init_controls(
     tx_to_ui: Sender<ControlMessage>,
     current_episode: ShareableRwLock<Option<ShareableRwLock<Episode>>>,
-    playing: ShareableRwLock<PlaybackStatus>, mut rx_from_main: tokio::sync::oneshot::Receiver<()>,
+    current_art: ShareableRwLock<Option<PathBuf>>, mut rx_from_player: Receiver<PlayerEvent>,
+    mut rx_from_main: tokio::sync::oneshot::Receiver<()>,
 ) -> anyhow::Result<tokio::task::JoinHandle<()>> {
     let task = tokio::task::spawn({
         let config = PlatformConfig {
@@ -33,7 +40,7 @@ pub fn init_controls(
         };
         let mut controls = MediaControls::new(config)?;
         let mut last_episode_id = -1_i64;
-        let mut last_status = PlaybackStatus::Ready;
+        let mut last_art = None;
 
         controls.attach(move |event: MediaControlEvent| {
             if event == MediaControlEvent::Toggle {
@@ -48,10 +55,14 @@ pub fn init_controls(
 
         async move {
             loop {
-                if last_status != *playing.read().expect("RwLock read should not fail") {
-                    last_status = *playing.read().expect("RwLock read should not fail");
-                    match last_status {
-                        PlaybackStatus::Playing => {
+                loop {
+                    let event = match rx_from_player.try_recv() {
+                        Ok(event) => event,
+                        Err(TryRecvError::Lagged(_)) => continue,
+                        Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                    };
+                    match event {
+                        PlayerEvent::Started | PlayerEvent::Resumed => {
                             controls
                                 .set_playback(MediaPlayback::Playing { progress: None })
                                 .inspect_err(|err| {
@@ -61,7 +72,7 @@ pub fn init_controls(
                                 })
                                 .ok();
                         }
-                        PlaybackStatus::Paused => {
+                        PlayerEvent::Paused => {
                             controls
                                 .set_playback(MediaPlayback::Paused { progress: None })
                                 .inspect_err(|err| {
@@ -71,7 +82,7 @@ pub fn init_controls(
                                 })
                                 .ok();
                         }
-                        PlaybackStatus::Finished | PlaybackStatus::Ready => {
+                        PlayerEvent::Finished | PlayerEvent::Unsupported => {
                             controls
                                 .set_playback(MediaPlayback::Stopped)
                                 .inspect_err(|err| {
@@ -81,23 +92,35 @@ pub fn init_controls(
                                 })
                                 .ok();
                         }
+                        PlayerEvent::Position(_)
+                        | PlayerEvent::Checkpoint(..)
+                        | PlayerEvent::Buffering(_)
+                        | PlayerEvent::Error(_) => {}
                     }
                 }
 
-                if let Some(ep) = current_episode
+                let art = current_art
+                    .read()
+                    .expect("RwLock read should not fail")
+                    .clone();
+                let playing = current_episode
                     .read()
                     .expect("RwLock read should not fail")
                     .as_ref()
+                    .map(|ep| {
+                        let ep = ep.read().expect("RwLock read should not fail");
+                        (ep.id, ep.title.clone())
+                    });
+                if let Some((ep_id, title)) = playing
+                    && (ep_id != last_episode_id || art != last_art)
                 {
-                    let ep = ep.read().expect("RwLock read should not fail");
-                    if ep.id != last_episode_id {
-                        update_control_metadata(&ep.title, &mut controls)
-                            .inspect_err(|err| {
-                                log::error!("update_control_metadata failed: {err}");
-                            })
-                            .unwrap_or_default();
-                        last_episode_id = ep.id;
-                    }
+                    update_control_metadata(&title, art.as_deref(), &mut controls)
+                        .inspect_err(|err| {
+                            log::error!("update_control_metadata failed: {err}");
+                        })
+                        .unwrap_or_default();
+                    last_episode_id = ep_id;
+                    last_art = art;
                 }
                 if rx_from_main.try_recv().is_ok() {
                     break;
@@ -0,0 +1,178 @@
+use std::{sync::Arc, time::Duration};
+
+use rodio::{ChannelCount, Sample, SampleRate, Source, source::SeekError};
+
+use super::controls::Controls;
+
+/// Mixes the channels down to mono and shifts the balance between left and
+/// right, as set in the controls, for listening with a single earbud.
+pub struct ChannelMix<S> {
+    input: S,
+    controls: Arc<Controls>,
+    channels: usize,
+    /// Frame being played, already mixed
+    frame: Vec<Sample>,
+    /// Position of the next sample in the frame
+    index: usize,
+    /// Channel of the next sample passed through unchanged
+    channel: usize,
+}
+
+impl<S: Source> ChannelMix<S> {
+    pub fn new(input: S, controls: Arc<Controls>) -> Self {
+        let channels = usize::from(input.channels().get());
+        Self {
+            input,
+            controls,
+            channels,
+            frame: Vec::with_capacity(channels),
+            index: 0,
+            channel: 0,
+        }
+    }
+}
+
+/// Mixes one frame in place, the first two channels are left and right.
+/// The balance goes from -100, only left, to 100, only right.
+fn mix(frame: &mut [Sample], mono: bool, balance: i8) {
+    if mono {
+        #[allow(clippy::cast_precision_loss)]
+        let average = frame.iter().sum::<Sample>() / frame.len() as Sample;
+        frame.fill(average);
+    }
+    if let [left, right, ..] = frame {
+        let balance = f32::from(balance) / 100.0;
+        *left *= (1.0 - balance).min(1.0);
+        *right *= (1.0 + balance).min(1.0);
+    }
+}
+
+impl<S: Source> Iterator for ChannelMix<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if let Some(&sample) = self.frame.get(self.index) {
+            self.index += 1;
+            return Some(sample);
+        }
+        // only switched at the start of a frame, so the channels stay in
+        // place
+        if self.channel == 0 && self.channels > 1 {
+            let mono = self.controls.mono();
+            let balance = self.controls.balance();
+            if mono || balance != 0 {
+                self.frame.clear();
+                self.frame.extend(self.input.by_ref().take(self.channels));
+                // an incomplete frame at the end is left as is
+                if self.frame.len() == self.channels {
+                    mix(&mut self.frame, mono, balance);
+                }
+                self.index = 1;
+                return self.frame.first().copied();
+            }
+        }
+        let sample = self.input.next()?;
+        self.channel = (self.channel + 1) % self.channels;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.frame.len().saturating_sub(self.index);
+        let (lower, upper) = self.input.size_hint();
+        (
+            lower.saturating_add(buffered),
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+}
+
+impl<S: Source> Source for ChannelMix<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.frame.clear();
+        self.index = 0;
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZero;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    /// Stereo source with a different constant on each channel
+    fn stereo(left: Sample, right: Sample, frames: usize) -> SamplesBuffer {
+        let samples = [left, right].repeat(frames);
+        SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(16000).unwrap(),
+            samples,
+        )
+    }
+
+    #[test]
+    fn leaves_audio_untouched_by_default() {
+        let controls = Arc::new(Controls::default());
+        let output: Vec<Sample> = ChannelMix::new(stereo(0.4, -0.2, 4), controls).collect();
+        assert_eq!(output, [0.4, -0.2].repeat(4));
+    }
+
+    #[test]
+    fn mono_averages_channels() {
+        let controls = Arc::new(Controls::default());
+        controls.set_mono(true);
+        let output: Vec<Sample> = ChannelMix::new(stereo(0.4, 0.0, 4), controls).collect();
+        assert_eq!(output, [0.2, 0.2].repeat(4));
+    }
+
+    #[test]
+    fn balance_lowers_other_side() {
+        let controls = Arc::new(Controls::default());
+        controls.set_balance(-100);
+        let output: Vec<Sample> = ChannelMix::new(stereo(0.5, 0.5, 2), controls.clone()).collect();
+        assert_eq!(output, [0.5, 0.0].repeat(2));
+        controls.set_balance(50);
+        let output: Vec<Sample> = ChannelMix::new(stereo(0.5, 0.5, 2), controls).collect();
+        assert_eq!(output, [0.25, 0.5].repeat(2));
+    }
+
+    #[test]
+    fn mono_applies_before_balance() {
+        let controls = Arc::new(Controls::default());
+        controls.set_mono(true);
+        controls.set_balance(-100);
+        let output: Vec<Sample> = ChannelMix::new(stereo(0.6, 0.0, 2), controls).collect();
+        assert_eq!(output, [0.3, 0.0].repeat(2));
+    }
+
+    #[test]
+    fn switches_while_playing() {
+        let controls = Arc::new(Controls::default());
+        let mut mix = ChannelMix::new(stereo(0.4, 0.0, 4), controls.clone());
+        // toggled in the middle of a frame, takes effect at the next one
+        assert_eq!(mix.next(), Some(0.4));
+        controls.set_mono(true);
+        let output: Vec<Sample> = mix.collect();
+        assert_eq!(output, [0.0, 0.2, 0.2, 0.2, 0.2, 0.2, 0.2]);
+    }
+}
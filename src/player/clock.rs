@@ -0,0 +1,124 @@
+use std::{sync::Arc, time::Duration};
+
+use rodio::{ChannelCount, Sample, SampleRate, Source, source::SeekError};
+
+use super::controls::Controls;
+
+/// Number of samples between two position updates
+const PUBLISH_INTERVAL: u64 = 1024;
+
+/// Tracks the position in episode time by counting the samples taken from
+/// the decoder. It has to sit right above the decoder, so adapters that
+/// change the amount of audio, like time stretching, do not affect it.
+pub struct EpisodeClock<S> {
+    input: S,
+    controls: Arc<Controls>,
+    start: Duration,
+    samples: u64,
+}
+
+impl<S: Source> EpisodeClock<S> {
+    pub fn new(input: S, controls: Arc<Controls>) -> Self {
+        controls.set_position(Duration::ZERO);
+        Self {
+            input,
+            controls,
+            start: Duration::ZERO,
+            samples: 0,
+        }
+    }
+
+    fn current_position(&self) -> Duration {
+        let rate =
+            u64::from(self.input.sample_rate().get()) * u64::from(self.input.channels().get());
+        self.start + Duration::from_micros(self.samples.saturating_mul(1_000_000) / rate)
+    }
+}
+
+impl<S: Source> Iterator for EpisodeClock<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+        self.samples += 1;
+        if self.samples.is_multiple_of(PUBLISH_INTERVAL) {
+            self.controls.set_position(self.current_position());
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for EpisodeClock<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.start = pos;
+        self.samples = 0;
+        self.controls.set_position(pos);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZero;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    #[test]
+    fn position_follows_consumed_samples() {
+        let controls = Arc::new(Controls::default());
+        let source = SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(8000).unwrap(),
+            vec![0.0; 2 * 8000 * 3],
+        );
+        let mut clock = EpisodeClock::new(source, controls.clone());
+        for _ in 0..2 * 8000 * 2 {
+            clock.next();
+        }
+        let position = controls.position();
+        assert!(position <= Duration::from_secs(2));
+        assert!(position > Duration::from_millis(1900));
+    }
+
+    #[test]
+    fn seek_moves_position() {
+        let controls = Arc::new(Controls::default());
+        let source = SamplesBuffer::new(
+            NonZero::new(1).unwrap(),
+            NonZero::new(8000).unwrap(),
+            vec![0.0; 8000 * 10],
+        );
+        let mut clock = EpisodeClock::new(source, controls.clone());
+        clock.try_seek(Duration::from_secs(5)).unwrap();
+        assert_eq!(controls.position(), Duration::from_secs(5));
+        for _ in 0..8000 {
+            clock.next();
+        }
+        let position = controls.position();
+        assert!(position <= Duration::from_secs(6));
+        assert!(position > Duration::from_millis(5800));
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicI8, AtomicU8, AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::config::{MAX_BALANCE, MAX_SPEED, MIN_SPEED};
+
+use super::EqPreset;
+
+/// Playback state shared between the player task and the source adapters,
+/// which run on the audio thread and cannot receive messages.
+#[derive(Debug)]
+pub struct Controls {
+    /// Bits of the `f32` playback speed
+    speed: AtomicU32,
+    /// Position in episode time, in milliseconds
+    position: AtomicU64,
+    /// Silence skipped and not yet reported, in microseconds
+    skipped: AtomicU64,
+    /// Index of the equalizer preset
+    equalizer: AtomicU8,
+    mono: AtomicBool,
+    /// Balance in percent, negative towards the left
+    balance: AtomicI8,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            speed: AtomicU32::new(1.0_f32.to_bits()),
+            position: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            equalizer: AtomicU8::new(EqPreset::Flat as u8),
+            mono: AtomicBool::new(false),
+            balance: AtomicI8::new(0),
+        }
+    }
+}
+
+impl Controls {
+    pub fn speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn equalizer(&self) -> EqPreset {
+        EqPreset::from_index(self.equalizer.load(Ordering::Relaxed))
+    }
+
+    pub fn set_equalizer(&self, preset: EqPreset) {
+        self.equalizer.store(preset as u8, Ordering::Relaxed);
+    }
+
+    pub fn mono(&self) -> bool {
+        self.mono.load(Ordering::Relaxed)
+    }
+
+    pub fn set_mono(&self, mono: bool) {
+        self.mono.store(mono, Ordering::Relaxed);
+    }
+
+    pub fn balance(&self) -> i8 {
+        self.balance.load(Ordering::Relaxed)
+    }
+
+    pub fn set_balance(&self, balance: i8) {
+        let balance = balance.clamp(-MAX_BALANCE, MAX_BALANCE);
+        self.balance.store(balance, Ordering::Relaxed);
+    }
+
+    pub fn position(&self) -> Duration {
+        Duration::from_millis(self.position.load(Ordering::Relaxed))
+    }
+
+    pub fn set_position(&self, position: Duration) {
+        let millis = u64::try_from(position.as_millis()).unwrap_or(u64::MAX);
+        self.position.store(millis, Ordering::Relaxed);
+    }
+
+    pub fn add_skipped(&self, skipped: Duration) {
+        let micros = u64::try_from(skipped.as_micros()).unwrap_or(u64::MAX);
+        self.skipped.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Returns the silence skipped since the last call
+    pub fn take_skipped(&self) -> Duration {
+        Duration::from_micros(self.skipped.swap(0, Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Result;
+use rodio::{
+    DeviceSinkBuilder, DeviceSinkError, MixerDeviceSink,
+    cpal::{
+        self, StreamError,
+        traits::{DeviceTrait, HostTrait},
+    },
+};
+
+/// Names of the output devices that are currently available.
+pub fn output_device_names() -> Vec<String> {
+    cpal::default_host()
+        .output_devices()
+        .map(|devices| {
+            devices
+                .filter_map(|device| device.description().ok())
+                .map(|description| description.name().to_string())
+                .collect()
+        })
+        .inspect_err(|err| log::warn!("Failed to list output devices: {err}"))
+        .unwrap_or_default()
+}
+
+/// Opens a sink on the output device with the given name, or on the
+/// default device if None or if no device has that name. `lost` is set
+/// when the device disappears while playing.
+pub fn open_sink(name: Option<&str>, lost: &Arc<AtomicBool>) -> Result<MixerDeviceSink> {
+    let device = name.and_then(|name| {
+        let device = cpal::default_host().output_devices().ok()?.find(|device| {
+            device
+                .description()
+                .is_ok_and(|description| description.name() == name)
+        });
+        if device.is_none() {
+            log::warn!("Output device {name} not found, using the default one");
+        }
+        device
+    });
+    let sink = device
+        .map_or_else(
+            DeviceSinkBuilder::from_default_device,
+            DeviceSinkBuilder::from_device,
+        )
+        .and_then(|builder| open_watched(builder, lost))
+        .or_else(|err| {
+            // tries the default device, then the others except the ones
+            // that play nowhere
+            let host = cpal::default_host();
+            let others = host
+                .output_devices()
+                .into_iter()
+                .flatten()
+                .filter(|device| {
+                    device.description().is_ok_and(|description| {
+                        description.driver().is_some_and(|driver| driver != "null")
+                    })
+                });
+            host.default_output_device()
+                .into_iter()
+                .chain(others)
+                .find_map(|device| {
+                    DeviceSinkBuilder::from_device(device)
+                        .and_then(|builder| open_watched(builder, lost))
+                        .ok()
+                })
+                .ok_or(err)
+        })?;
+    Ok(sink)
+}
+
+/// Opens a sink with any configuration the device supports, that sets
+/// `lost` when the device disappears.
+fn open_watched(
+    builder: DeviceSinkBuilder, lost: &Arc<AtomicBool>,
+) -> Result<MixerDeviceSink, DeviceSinkError> {
+    let lost = lost.clone();
+    builder
+        .with_error_callback(move |err| match err {
+            StreamError::DeviceNotAvailable => lost.store(true, Ordering::Relaxed),
+            err => log::warn!("Audio stream error: {err}"),
+        })
+        .open_sink_or_fallback()
+}
@@ -0,0 +1,354 @@
+use std::{f32::consts::PI, sync::Arc, time::Duration};
+
+use rodio::{ChannelCount, Sample, SampleRate, Source, source::SeekError};
+
+use super::controls::Controls;
+
+/// Equalizer settings for voices that are hard to understand
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EqPreset {
+    #[default]
+    Flat,
+    /// Less rumble and more presence, for poor microphones and laptop
+    /// speakers
+    VoiceBoost,
+    ReduceBass,
+}
+
+impl EqPreset {
+    const ALL: [Self; 3] = [Self::Flat, Self::VoiceBoost, Self::ReduceBass];
+
+    /// Name used in the config file and the database
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Flat => "flat",
+            Self::VoiceBoost => "voice_boost",
+            Self::ReduceBass => "reduce_bass",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| preset.name() == name)
+    }
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Flat => "Flat",
+            Self::VoiceBoost => "Voice boost",
+            Self::ReduceBass => "Reduce bass",
+        }
+    }
+
+    /// The preset that follows when cycling through them
+    pub const fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn from_index(index: u8) -> Self {
+        Self::ALL
+            .get(usize::from(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Gain before the filters, to leave room for the boosted bands, and
+    /// the gain of each band, in dB
+    const fn gains(self) -> (f32, [f32; BANDS.len()]) {
+        match self {
+            Self::Flat => (0.0, [0.0; BANDS.len()]),
+            Self::VoiceBoost => (-3.0, [-6.0, 2.0, 4.0, -1.0]),
+            Self::ReduceBass => (0.0, [-9.0, 0.0, 0.0, 0.0]),
+        }
+    }
+}
+
+enum Shape {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+/// Shape, frequency in Hz and quality of each band
+const BANDS: [(Shape, f32, f32); 4] = [
+    (Shape::LowShelf, 150.0, 0.707),
+    (Shape::Peak, 1000.0, 1.0),
+    (Shape::Peak, 3000.0, 1.0),
+    (Shape::HighShelf, 8000.0, 0.707),
+];
+
+/// Coefficients of a biquad filter, normalized by a0
+#[derive(Debug, Clone, Copy, Default)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    /// Filter from the audio EQ cookbook by Robert Bristow-Johnson
+    fn new(shape: &Shape, frequency: f32, q: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10_f32.powf(gain_db / 40.0);
+        // keeps the frequency below Nyquist for low sample rates
+        let w0 = 2.0 * PI * frequency.min(sample_rate * 0.45) / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let (b0, b1, b2, a0, a1, a2) = match shape {
+            Shape::Peak => (
+                alpha.mul_add(a, 1.0),
+                -2.0 * cos,
+                (-alpha).mul_add(a, 1.0),
+                alpha / a + 1.0,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            Shape::LowShelf => {
+                let root = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a - 1.0).mul_add(-cos, a + 1.0) + root),
+                    2.0 * a * (a + 1.0).mul_add(-cos, a - 1.0),
+                    a * ((a - 1.0).mul_add(-cos, a + 1.0) - root),
+                    (a - 1.0).mul_add(cos, a + 1.0) + root,
+                    -2.0 * (a + 1.0).mul_add(cos, a - 1.0),
+                    (a - 1.0).mul_add(cos, a + 1.0) - root,
+                )
+            }
+            Shape::HighShelf => {
+                let root = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a - 1.0).mul_add(cos, a + 1.0) + root),
+                    -2.0 * a * (a + 1.0).mul_add(cos, a - 1.0),
+                    a * ((a - 1.0).mul_add(cos, a + 1.0) - root),
+                    (a - 1.0).mul_add(-cos, a + 1.0) + root,
+                    2.0 * (a + 1.0).mul_add(-cos, a - 1.0),
+                    (a - 1.0).mul_add(-cos, a + 1.0) - root,
+                )
+            }
+        };
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Previous inputs and outputs of a filter, for one channel
+#[derive(Debug, Clone, Copy, Default)]
+struct History {
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl History {
+    fn process(&mut self, filter: &Biquad, x: f32) -> f32 {
+        let y = filter.a2.mul_add(
+            -self.y2,
+            filter.a1.mul_add(
+                -self.y1,
+                filter
+                    .b2
+                    .mul_add(self.x2, filter.b1.mul_add(self.x1, filter.b0 * x)),
+            ),
+        );
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Applies the equalizer preset selected in the controls. Flat leaves the
+/// audio untouched.
+pub struct Equalizer<S> {
+    input: S,
+    controls: Arc<Controls>,
+    preset: EqPreset,
+    gain: Sample,
+    filters: [Biquad; BANDS.len()],
+    /// Filter state of every band, for each channel
+    history: Vec<[History; BANDS.len()]>,
+    /// Channel of the next sample
+    channel: usize,
+}
+
+impl<S: Source> Equalizer<S> {
+    pub fn new(input: S, controls: Arc<Controls>) -> Self {
+        let channels = usize::from(input.channels().get());
+        let mut equalizer = Self {
+            input,
+            controls,
+            preset: EqPreset::Flat,
+            gain: 1.0,
+            filters: [Biquad::default(); BANDS.len()],
+            history: vec![[History::default(); BANDS.len()]; channels],
+            channel: 0,
+        };
+        equalizer.set_preset(equalizer.controls.equalizer());
+        equalizer
+    }
+
+    fn set_preset(&mut self, preset: EqPreset) {
+        self.preset = preset;
+        #[allow(clippy::cast_precision_loss)]
+        let sample_rate = self.input.sample_rate().get() as f32;
+        let (gain, band_gains) = preset.gains();
+        self.gain = 10_f32.powf(gain / 20.0);
+        for ((filter, (shape, frequency, q)), band_gain) in
+            self.filters.iter_mut().zip(&BANDS).zip(band_gains)
+        {
+            *filter = Biquad::new(shape, *frequency, *q, band_gain, sample_rate);
+        }
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        for history in &mut self.history {
+            *history = [History::default(); BANDS.len()];
+        }
+        self.channel = 0;
+    }
+}
+
+impl<S: Source> Iterator for Equalizer<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+        // only switched at the start of a frame, so the channels stay
+        // aligned with their history
+        if self.channel == 0 {
+            let preset = self.controls.equalizer();
+            if preset != self.preset {
+                self.set_preset(preset);
+            }
+        }
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.history.len();
+        if self.preset == EqPreset::Flat {
+            return Some(sample);
+        }
+        let history = &mut self.history[channel];
+        let output = self
+            .filters
+            .iter()
+            .zip(history.iter_mut())
+            .fold(sample * self.gain, |x, (filter, history)| {
+                history.process(filter, x)
+            });
+        Some(output)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Equalizer<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.reset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZero;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    const RATE: u16 = 16000;
+
+    /// Mono sine wave at the given frequency, one second long
+    fn sine(frequency: f32) -> SamplesBuffer {
+        let samples = (0..RATE)
+            .map(|i| (2.0 * PI * frequency * f32::from(i) / f32::from(RATE)).sin() * 0.5)
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(
+            NonZero::new(1).unwrap(),
+            NonZero::new(u32::from(RATE)).unwrap(),
+            samples,
+        )
+    }
+
+    /// Peak level once the filters settled
+    fn peak(source: impl Iterator<Item = Sample>) -> f32 {
+        source
+            .skip(usize::from(RATE / 4))
+            .fold(0.0, |peak: f32, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn preset_names_round_trip() {
+        for preset in EqPreset::ALL {
+            assert_eq!(EqPreset::from_name(preset.name()), Some(preset));
+            assert_eq!(EqPreset::from_index(preset as u8), preset);
+        }
+        assert_eq!(EqPreset::from_name("loud"), None);
+        assert_eq!(EqPreset::ReduceBass.next(), EqPreset::Flat);
+    }
+
+    #[test]
+    fn flat_leaves_audio_untouched() {
+        let controls = Arc::new(Controls::default());
+        let output: Vec<Sample> = Equalizer::new(sine(440.0), controls).collect();
+        let input: Vec<Sample> = sine(440.0).collect();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn reduce_bass_cuts_low_frequencies() {
+        let controls = Arc::new(Controls::default());
+        controls.set_equalizer(EqPreset::ReduceBass);
+        let low = peak(Equalizer::new(sine(50.0), controls.clone()));
+        let high = peak(Equalizer::new(sine(2000.0), controls));
+        assert!(low < 0.25, "low peak {low}");
+        assert!((high - 0.5).abs() < 0.05, "high peak {high}");
+    }
+
+    #[test]
+    fn voice_boost_raises_presence() {
+        let controls = Arc::new(Controls::default());
+        controls.set_equalizer(EqPreset::VoiceBoost);
+        let presence = peak(Equalizer::new(sine(3000.0), controls.clone()));
+        let low = peak(Equalizer::new(sine(50.0), controls));
+        assert!(presence > 0.5, "presence peak {presence}");
+        assert!(low < presence / 2.0, "low peak {low}");
+    }
+
+    #[test]
+    fn preset_switches_while_playing() {
+        let controls = Arc::new(Controls::default());
+        let mut equalizer = Equalizer::new(sine(50.0), controls.clone());
+        let before = peak(equalizer.by_ref().take(usize::from(RATE / 2)));
+        controls.set_equalizer(EqPreset::ReduceBass);
+        let after = peak(equalizer);
+        assert!(after < before / 2.0, "before {before} after {after}");
+    }
+}
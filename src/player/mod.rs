@@ -0,0 +1,773 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use rodio::{MixerDeviceSink, Player as RodioPlayer, Source, decoder::DecoderError};
+use stream_download::source::SourceStream;
+use stream_download::{
+    Settings, StreamDownload,
+    http::{HttpStream, reqwest::Client},
+    storage::temp::TempStorageProvider,
+};
+use tokio::{
+    sync::{broadcast, mpsc::Receiver},
+    task::JoinHandle,
+};
+
+use crate::{
+    config::{CHECKPOINT_INTERVAL, Config, FADE_OUT_INTERVAL, FADING_TIME},
+    types::Message,
+    utils::{resolve_redirection_async, smart_rewind, url_extension},
+};
+
+use self::{
+    channels::ChannelMix,
+    clock::EpisodeClock,
+    controls::Controls,
+    equalizer::Equalizer,
+    normalize::Normalize,
+    silence::SkipSilence,
+    streamed::StreamKeeper,
+    stretch::TimeStretch,
+    upcoming::{Loaded, Upcoming},
+};
+
+pub use self::device::output_device_names;
+pub use self::equalizer::EqPreset;
+
+mod channels;
+mod clock;
+mod controls;
+mod device;
+mod equalizer;
+mod normalize;
+mod silence;
+mod streamed;
+mod stretch;
+mod upcoming;
+
+pub enum PlayerMessage {
+    PlayPause,
+    /// Episode id, path, position, duration in seconds and measured
+    /// loudness of the file
+    PlayFile(i64, PathBuf, Duration, u64, Option<f32>),
+    PlayUrl(i64, String, Duration, u64),
+    /// Opens the episode that follows in the queue ahead of time, so it
+    /// starts right when the current one ends. Same fields as `PlayFile`,
+    /// then the settings of its podcast.
+    PrepareFile(i64, PathBuf, Duration, u64, Option<f32>, PodcastSettings),
+    PrepareUrl(i64, String, Duration, u64, PodcastSettings),
+    /// Drops the prepared episode, unless it already started
+    CancelPrepared,
+    Seek(Duration, bool),
+    /// Absolute position in the episode
+    SeekTo(Duration),
+    /// Playback speed, keeping the pitch
+    SetSpeed(f32),
+    /// Volume as a gain factor, 1.0 leaves the audio unchanged
+    SetVolume(f32),
+    SetEqualizer(EqPreset),
+    /// Mixes all channels down to mono
+    Mono(bool),
+    /// Balance in percent, negative towards the left
+    Balance(i8),
+    Mute(bool),
+    /// Seconds at the end of the episode that are treated as played
+    SkipOutro(u64),
+    /// Lowers the volume gradually over the given time, then pauses.
+    /// None cancels a fade in progress.
+    FadeOut(Option<Duration>),
+    /// Moves playback to the output device with the given name, None for
+    /// the default one, keeping the position
+    SetDevice(Option<String>),
+    Quit,
+    /// Workaround for sound not working after resume
+    ResetSink,
+}
+
+/// Settings of the podcast of a prepared episode, so it starts with them
+/// rather than with those of the episode before
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PodcastSettings {
+    pub speed: f32,
+    pub equalizer: EqPreset,
+    /// Seconds at the end of the episode that are treated as played
+    pub skip_outro: u64,
+}
+
+/// Changes in the playback state, sent to everyone subscribed to the player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerEvent {
+    /// An episode started playing
+    Started,
+    Resumed,
+    Paused,
+    /// Position in the current episode, in seconds
+    Position(u64),
+    /// Id of the current episode and its exact position, to be saved while
+    /// playing
+    Checkpoint(i64, Duration),
+    /// The current episode ended
+    Finished,
+    /// True while waiting for a stream to start
+    Buffering(bool),
+    /// Playback failed, with the reason
+    Error(String),
+    /// The format of the episode is not supported, nothing is playing
+    Unsupported,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum PlaybackStatus {
+    Ready,
+    Playing,
+    Paused,
+    Finished,
+}
+
+/// Episode loaded in the sink, so it can be reopened on another device,
+/// with the id of the episode
+#[derive(Clone, PartialEq)]
+enum Media {
+    File(i64, PathBuf, Option<f32>),
+    Url(i64, String),
+}
+
+type Decoded = Box<dyn Source + Send>;
+
+/// Decodes the data, with the file extension as a hint for the format.
+fn decoder<R>(data: R, hint: Option<&str>, byte_len: Option<u64>) -> Result<Decoded>
+where
+    R: std::io::Read + std::io::Seek + Send + Sync + 'static,
+{
+    let mut builder = rodio::Decoder::builder()
+        .with_seekable(true)
+        .with_data(data);
+    if let Some(hint) = hint {
+        builder = builder.with_hint(hint);
+    }
+    if let Some(byte_len) = byte_len {
+        builder = builder.with_byte_len(byte_len);
+    }
+    let source = builder.build().map_err(|err| match err {
+        DecoderError::UnrecognizedFormat => anyhow::Error::new(Unsupported),
+        err => err.into(),
+    })?;
+    Ok(Box::new(source))
+}
+
+/// The format of the episode cannot be decoded by the internal player
+#[derive(Debug)]
+struct Unsupported;
+
+impl std::fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsupported audio format")
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+impl Media {
+    async fn decode(&self, keeper: Option<&StreamKeeper>) -> Result<Decoded> {
+        match self {
+            Self::File(_, path, _) => {
+                let file = std::fs::File::open(path)?;
+                let byte_len = file.metadata()?.len();
+                let hint = path.extension().and_then(|ext| ext.to_str());
+                decoder(file, hint, Some(byte_len))
+            }
+            Self::Url(id, url) => {
+                let url = resolve_redirection_async(url)
+                    .await
+                    .unwrap_or_else(|_| url.clone());
+                let stream = HttpStream::<Client>::create(url.parse()?).await?;
+                let content_type = stream
+                    .content_type()
+                    .as_ref()
+                    .map(|mime| format!("{}/{}", mime.r#type, mime.subtype));
+                let (storage, settings) = keeper.map_or_else(
+                    || (TempStorageProvider::new(), Settings::default()),
+                    |keeper| keeper.storage(*id, content_type),
+                );
+                let reader = StreamDownload::from_stream(stream, storage, settings).await?;
+                decoder(reader, url_extension(&url), None)
+            }
+        }
+    }
+
+    const fn loudness(&self) -> Option<f32> {
+        match self {
+            Self::File(_, _, loudness) => *loudness,
+            Self::Url(..) => None,
+        }
+    }
+
+    const fn episode_id(&self) -> i64 {
+        match self {
+            Self::File(id, ..) | Self::Url(id, _) => *id,
+        }
+    }
+}
+
+pub struct Player {
+    stream_handle: MixerDeviceSink, // else the sink stops working
+    sink: RodioPlayer,
+    elapsed: u64,
+    duration: u64,
+    skip_outro: u64,
+    status: PlaybackStatus,
+    events: broadcast::Sender<PlayerEvent>,
+    /// Silence skipped in the current episode and not yet saved
+    time_saved: Arc<RwLock<Duration>>,
+    controls: Arc<Controls>,
+    volume: f32,
+    muted: bool,
+    /// Loudness to normalize episodes to, None if disabled
+    target_loudness: Option<f32>,
+    skip_silence: bool,
+    /// Start and length of the fade out in progress
+    fade_out: Option<(Instant, Duration)>,
+    /// Name of the output device, None for the default one
+    device: Option<String>,
+    /// Set by the stream when the output device disappears
+    device_lost: Arc<AtomicBool>,
+    current: Option<Media>,
+    /// Length of the crossfade between queued episodes, zero for gapless
+    crossfade: Duration,
+    /// Next episode being opened in the background
+    loading: Option<JoinHandle<Result<Loaded>>>,
+    upcoming: Option<Upcoming>,
+    /// Episode that started after the previous one ended, until the UI
+    /// asks to play it
+    advanced: Option<Media>,
+    /// Most the position goes back when resuming, zero if disabled
+    smart_rewind: Duration,
+    /// When playback was paused, for the smart rewind
+    paused_at: Option<Instant>,
+    /// When the position was last reported to be saved
+    checkpoint_at: Instant,
+    /// Keeps fully streamed episodes, if enabled
+    keeper: Option<StreamKeeper>,
+}
+
+impl Player {
+    fn new(
+        events: broadcast::Sender<PlayerEvent>, time_saved: Arc<RwLock<Duration>>, config: &Config,
+        tx_to_main: Sender<Message>,
+    ) -> Result<Self> {
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let stream_handle = device::open_sink(config.output_device.as_deref(), &device_lost)?;
+        let sink = RodioPlayer::connect_new(stream_handle.mixer());
+        let controls = Arc::new(Controls::default());
+        controls.set_mono(config.mono);
+        controls.set_balance(config.balance);
+        Ok(Self {
+            stream_handle,
+            sink,
+            elapsed: 0,
+            duration: 0,
+            skip_outro: 0,
+            status: PlaybackStatus::Ready,
+            events,
+            time_saved,
+            controls,
+            volume: 1.0,
+            muted: false,
+            target_loudness: config.normalize_loudness.then_some(config.target_loudness),
+            skip_silence: config.skip_silence,
+            fade_out: None,
+            device: config.output_device.clone(),
+            device_lost,
+            current: None,
+            crossfade: Duration::from_secs(config.crossfade),
+            loading: None,
+            upcoming: None,
+            advanced: None,
+            smart_rewind: config.smart_rewind,
+            paused_at: None,
+            checkpoint_at: Instant::now(),
+            keeper: config
+                .keep_streamed
+                .then(|| StreamKeeper::new(&config.download_path, tx_to_main)),
+        })
+    }
+
+    fn reset(&mut self) {
+        self.clear_upcoming();
+        match device::open_sink(self.device.as_deref(), &self.device_lost) {
+            Ok(stream_handle) => {
+                let sink = RodioPlayer::connect_new(stream_handle.mixer());
+                self.stream_handle = stream_handle;
+                self.sink = sink;
+            }
+            Err(err) => log::error!("Failed to reset audio sink: {err}"),
+        }
+        self.set_position(0);
+        self.finish();
+    }
+
+    #[allow(clippy::too_many_lines)]
+    pub async fn spawn_async(
+        mut rx_from_ui: Receiver<PlayerMessage>, events: broadcast::Sender<PlayerEvent>,
+        time_saved: Arc<RwLock<Duration>>, config: Arc<Config>, tx_to_main: Sender<Message>,
+    ) {
+        let mut player = match Self::new(events, time_saved, &config, tx_to_main) {
+            Ok(player) => player,
+            Err(err) => {
+                log::error!("No audio device available: {err}");
+                Self::drain_messages(&mut rx_from_ui).await;
+                return;
+            }
+        };
+        let mut elapsed_interval = tokio::time::interval(Duration::from_secs(1));
+        elapsed_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut fade_interval = tokio::time::interval(FADE_OUT_INTERVAL);
+        fade_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        loop {
+            tokio::select! {
+                msg = rx_from_ui.recv() => {
+                    let Some(message) = msg else { break };
+                    match message {
+                        PlayerMessage::PlayPause => {
+                            if !player.sink.empty() {
+                                player.play_pause().await;
+                            }
+                        }
+                        PlayerMessage::PlayFile(id, path, position, duration, loudness) => {
+                            let media = Media::File(id, path, loudness);
+                            player.play_episode(media, position, duration).await;
+                        }
+                        PlayerMessage::PlayUrl(id, url, position, duration) => {
+                            let media = Media::Url(id, url);
+                            player.play_episode(media, position, duration).await;
+                        }
+                        PlayerMessage::PrepareFile(
+                            id,
+                            path,
+                            position,
+                            duration,
+                            loudness,
+                            settings,
+                        ) => {
+                            let media = Media::File(id, path, loudness);
+                            player.prepare(media, position, duration, settings);
+                        }
+                        PlayerMessage::PrepareUrl(id, url, position, duration, settings) => {
+                            player.prepare(Media::Url(id, url), position, duration, settings);
+                        }
+                        PlayerMessage::CancelPrepared => player.cancel_upcoming(),
+                        PlayerMessage::Seek(shift, direction) => {
+                            if !player.sink.empty() {
+                                player.seek(shift, direction).await;
+                            }
+                        }
+                        PlayerMessage::SeekTo(position) => {
+                            if !player.sink.empty() {
+                                player.seek_to(position).await;
+                            }
+                        }
+                        PlayerMessage::SetSpeed(speed) => player.controls.set_speed(speed),
+                        PlayerMessage::SetEqualizer(preset) => {
+                            player.controls.set_equalizer(preset);
+                        }
+                        PlayerMessage::Mono(mono) => player.controls.set_mono(mono),
+                        PlayerMessage::Balance(balance) => player.controls.set_balance(balance),
+                        PlayerMessage::SetVolume(volume) => {
+                            player.volume = volume;
+                            player.sink.set_volume(player.volume());
+                        }
+                        PlayerMessage::Mute(muted) => {
+                            player.muted = muted;
+                            player.sink.set_volume(player.volume());
+                        }
+                        PlayerMessage::SkipOutro(outro) => player.skip_outro = outro,
+                        PlayerMessage::FadeOut(length) => {
+                            player.fade_out = length.map(|length| (Instant::now(), length));
+                            player.sink.set_volume(player.volume());
+                        }
+                        PlayerMessage::SetDevice(device) => player.switch_device(device).await,
+                        PlayerMessage::Quit => {
+                            player.clear_upcoming();
+                            player.sink.stop();
+                            break;
+                        }
+                        PlayerMessage::ResetSink => player.reset(),
+                    }
+                }
+                _ = elapsed_interval.tick() => player.tick().await,
+                _ = fade_interval.tick(), if player.fade_out.is_some() || player.upcoming.is_some() => {
+                    player.fade_step();
+                    player.check_upcoming();
+                }
+                loaded = async { player.loading.as_mut().expect("checked by the guard").await },
+                    if player.loading.is_some() =>
+                {
+                    player.loading = None;
+                    match loaded {
+                        Ok(Ok(loaded)) => player.prepared(loaded),
+                        Ok(Err(err)) => log::warn!("Failed to prepare next episode: {err}"),
+                        Err(err) => log::error!("Preparing next episode failed: {err}"),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Follows the output device and the position, once per second
+    async fn tick(&mut self) {
+        if self.device_lost.swap(false, Ordering::Relaxed) {
+            log::warn!("Output device disappeared, switching to the default one");
+            self.switch_device(None).await;
+        }
+        if self.status == PlaybackStatus::Playing {
+            self.set_elapsed();
+        }
+        if self.status == PlaybackStatus::Playing
+            && self.checkpoint_at.elapsed() >= CHECKPOINT_INTERVAL
+        {
+            self.checkpoint();
+        }
+    }
+
+    fn checkpoint(&mut self) {
+        self.checkpoint_at = Instant::now();
+        if let Some(media) = &self.current {
+            self.emit(PlayerEvent::Checkpoint(
+                media.episode_id(),
+                self.controls.position(),
+            ));
+        }
+    }
+
+    async fn play_episode(&mut self, media: Media, position: Duration, duration: u64) {
+        self.duration = duration;
+        self.status = PlaybackStatus::Playing;
+        if self.advanced.take().as_ref() == Some(&media) {
+            // already playing since the previous episode ended
+            self.emit(PlayerEvent::Started);
+            self.set_elapsed();
+            return;
+        }
+        self.elapsed = position.as_secs();
+        self.paused_at = None;
+        match self.play(media, position).await {
+            Ok(()) => self.emit(PlayerEvent::Started),
+            Err(err) if err.is::<Unsupported>() => {
+                log::warn!("Cannot play episode: {err}");
+                // nothing left to finish, the UI offers the external player
+                self.sink.stop();
+                self.status = PlaybackStatus::Ready;
+                self.emit(PlayerEvent::Unsupported);
+            }
+            Err(err) => {
+                log::error!("Error playing episode: {err}");
+                self.emit(PlayerEvent::Error(err.to_string()));
+            }
+        }
+    }
+
+    async fn play(&mut self, media: Media, position: Duration) -> Result<()> {
+        self.clear_upcoming();
+        let streamed = matches!(media, Media::Url(..));
+        if streamed {
+            self.emit(PlayerEvent::Buffering(true));
+        }
+        let source = media.decode(self.keeper.as_ref()).await;
+        if streamed {
+            self.emit(PlayerEvent::Buffering(false));
+        }
+        let source = source?;
+        let loudness = media.loudness();
+        self.current = Some(media);
+        self.start(source, loudness, position).await;
+        Ok(())
+    }
+
+    /// Replaces the sink contents with the source, from the given
+    /// position. Stays paused if playback was paused.
+    async fn start(&self, source: Decoded, loudness: Option<f32>, position: Duration) {
+        if !self.sink.empty() {
+            self.sink.stop();
+        }
+        self.sink.set_volume(0.0);
+        self.sink
+            .append(self.wrap_source(source, &self.controls, loudness));
+        if !position.is_zero()
+            && let Err(err) = self.sink.try_seek(position)
+        {
+            log::warn!("Failed to seek: {err}");
+        }
+        if self.status == PlaybackStatus::Paused {
+            self.sink.pause();
+            self.sink.set_volume(self.volume());
+            return;
+        }
+        self.sink.play();
+        tokio::time::sleep(std::time::Duration::from_millis(FADING_TIME)).await;
+        self.sink.set_volume(self.volume());
+    }
+
+    /// Opens a sink on another output device and continues the current
+    /// episode there from the same position.
+    async fn switch_device(&mut self, device: Option<String>) {
+        let loaded = !self.sink.empty();
+        if loaded {
+            self.set_elapsed();
+        }
+        self.clear_upcoming();
+        let stream_handle = match device::open_sink(device.as_deref(), &self.device_lost) {
+            Ok(stream_handle) => stream_handle,
+            Err(err) => {
+                log::error!("Failed to open output device: {err}");
+                return;
+            }
+        };
+        self.device = device;
+        self.sink.stop();
+        self.sink = RodioPlayer::connect_new(stream_handle.mixer());
+        self.stream_handle = stream_handle;
+        // set_elapsed could have finished the episode
+        if !loaded || self.status == PlaybackStatus::Finished {
+            return;
+        }
+        if let Some(media) = self.current.clone()
+            && let Err(err) = self.play(media, Duration::from_secs(self.elapsed)).await
+        {
+            log::error!("Error resuming on new device: {err}");
+            self.emit(PlayerEvent::Error(err.to_string()));
+        }
+    }
+
+    /// Adds the processing stages to a decoded source. The clock goes
+    /// first, so that the position stays in episode time even when
+    /// silence is skipped or the speed changed.
+    fn wrap_source(
+        &self, source: Decoded, controls: &Arc<Controls>, loudness: Option<f32>,
+    ) -> impl Source + Send + 'static {
+        let source = EpisodeClock::new(source, controls.clone());
+        // anything skipped before belongs to the previous episode
+        controls.take_skipped();
+        let source = SkipSilence::new(source, controls.clone(), self.skip_silence);
+        let source = Equalizer::new(source, controls.clone());
+        let source = ChannelMix::new(source, controls.clone());
+        let source = Normalize::new(source, self.target_loudness, loudness);
+        TimeStretch::new(source, controls.clone())
+    }
+
+    /// Lowers the volume along the fade out, pausing once it completes.
+    /// The volume is restored after pausing, so resuming plays normally.
+    fn fade_step(&mut self) {
+        let Some((start, length)) = self.fade_out else {
+            return;
+        };
+        if self.sink.empty() {
+            self.fade_out = None;
+            return;
+        }
+        let progress = start.elapsed().as_secs_f32() / length.as_secs_f32().max(f32::EPSILON);
+        if progress < 1.0 {
+            // squared, so the volume drops slower at the beginning where the
+            // change is most noticeable
+            self.sink
+                .set_volume(self.volume() * (1.0 - progress).powi(2));
+            return;
+        }
+        self.fade_out = None;
+        self.set_elapsed();
+        self.sink.pause();
+        self.paused_at = Some(Instant::now());
+        self.status = PlaybackStatus::Paused;
+        self.emit(PlayerEvent::Paused);
+        self.sink.set_volume(self.volume());
+    }
+
+    const fn volume(&self) -> f32 {
+        if self.muted { 0.0 } else { self.volume }
+    }
+
+    async fn play_pause(&mut self) {
+        let fading_in = self.upcoming.as_ref().and_then(Upcoming::fading_in);
+        if self.sink.is_paused() {
+            self.sink.play();
+            if let Some(sink) = fading_in {
+                sink.play();
+            }
+            self.status = PlaybackStatus::Playing;
+            self.emit(PlayerEvent::Resumed);
+            let rewind = self.paused_at.take().map_or(Duration::ZERO, |paused_at| {
+                smart_rewind(paused_at.elapsed(), self.smart_rewind)
+            });
+            if !rewind.is_zero() {
+                self.seek_to(self.controls.position().saturating_sub(rewind))
+                    .await;
+            }
+        } else {
+            self.sink.pause();
+            if let Some(sink) = fading_in {
+                sink.pause();
+            }
+            self.paused_at = Some(Instant::now());
+            self.status = PlaybackStatus::Paused;
+            self.emit(PlayerEvent::Paused);
+            self.checkpoint();
+        }
+    }
+
+    async fn seek(&mut self, shift: Duration, direction: bool) {
+        let pos = self.controls.position();
+        if direction {
+            self.seek_to(pos + shift).await;
+        } else {
+            self.seek_to(pos.saturating_sub(shift)).await;
+        }
+    }
+
+    async fn seek_to(&mut self, pos: Duration) {
+        if self
+            .upcoming
+            .as_ref()
+            .and_then(Upcoming::fading_in)
+            .is_some()
+        {
+            // the crossfade would no longer match the position
+            self.clear_upcoming();
+        }
+        // the duration is 0 when unknown
+        let pos = if self.duration > 0 {
+            pos.min(Duration::from_secs(self.duration))
+        } else {
+            pos
+        };
+        self.sink.pause();
+        self.sink.set_volume(0.0);
+        self.sink
+            .try_seek(pos)
+            .inspect_err(|err| log::warn!("Failed to seek: {err}"))
+            .unwrap_or_default();
+        self.sink.play();
+        tokio::time::sleep(std::time::Duration::from_millis(FADING_TIME)).await;
+        self.sink.set_volume(self.volume());
+        self.set_elapsed();
+    }
+
+    async fn drain_messages(rx_from_ui: &mut Receiver<PlayerMessage>) {
+        while let Some(msg) = rx_from_ui.recv().await {
+            if matches!(msg, PlayerMessage::Quit) {
+                break;
+            }
+        }
+    }
+
+    fn set_elapsed(&mut self) {
+        *self
+            .time_saved
+            .write()
+            .expect("RwLock write should not fail") += self.controls.take_skipped();
+        let elapsed = self.controls.position().as_secs();
+        if self.upcoming.as_ref().is_some_and(Upcoming::started) {
+            // the transition to the next episode handles the end
+            self.set_position(elapsed);
+            return;
+        }
+        if self.skip_outro > 0
+            && self.duration > self.skip_outro
+            && elapsed >= self.duration - self.skip_outro
+        {
+            // finished early, so the episode is marked as played and the
+            // queue moves on as usual
+            self.sink.stop();
+            self.set_position(self.duration);
+            self.finish();
+            return;
+        }
+        if self.sink.empty() {
+            // Snap elapsed to duration on natural finish (1s tolerance for
+            // rounding between sink position and RSS/symphonia duration)
+            if self.duration > 0 && self.duration <= elapsed + 1 {
+                self.set_position(self.duration);
+            }
+            self.finish();
+            return;
+        }
+        self.set_position(elapsed);
+    }
+
+    fn set_position(&mut self, position: u64) {
+        self.elapsed = position;
+        self.emit(PlayerEvent::Position(position));
+    }
+
+    fn finish(&mut self) {
+        self.status = PlaybackStatus::Finished;
+        self.emit(PlayerEvent::Finished);
+    }
+
+    fn emit(&self, event: PlayerEvent) {
+        // only fails when nobody is subscribed
+        self.events.send(event).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn kept_stream_is_known_by_episode() {
+        use wiremock::matchers::path;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        use crate::{downloads::DownloadMsg, utils::tests::wav_bytes};
+
+        // feeds usually link to a tracker, that redirects to the file
+        let server = MockServer::start().await;
+        Mock::given(path("/track/ep"))
+            .respond_with(
+                ResponseTemplate::new(302).insert_header("Location", "/cdn/ep.wav?token=1"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(path("/cdn/ep.wav"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(wav_bytes(1), "audio/wav"))
+            .mount(&server)
+            .await;
+
+        let dir = std::env::temp_dir().join(format!("hullcaster-stream-{}", std::process::id()));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let keeper = StreamKeeper::new(&dir, tx);
+        let media = Media::Url(7, format!("{}/track/ep", server.uri()));
+        let _decoded = media.decode(Some(&keeper)).await.unwrap();
+        let msg = tokio::task::spawn_blocking(move || rx.recv_timeout(Duration::from_secs(5)))
+            .await
+            .unwrap()
+            .unwrap();
+        let kept = match msg {
+            Message::Dl(DownloadMsg::Streamed(ep_id, path, mime_type)) => {
+                Some((ep_id, path, mime_type))
+            }
+            _ => None,
+        };
+        let (ep_id, path, mime_type) = kept.expect("the stream should be kept");
+        assert_eq!(ep_id, 7);
+        assert_eq!(mime_type.as_deref(), Some("audio/wav"));
+        assert_eq!(std::fs::read(path).unwrap(), wav_bytes(1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_format_is_unsupported() {
+        let err = decoder(Cursor::new(vec![0_u8; 4096]), None, None).err();
+        assert!(err.is_some_and(|err| err.is::<Unsupported>()));
+    }
+}
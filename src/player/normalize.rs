@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use rodio::{ChannelCount, Sample, SampleRate, Source, source::SeekError};
+
+use crate::loudness::LoudnessMeter;
+
+/// Highest level the limiter lets through
+const CEILING: Sample = 0.98;
+/// How fast the limiter recovers after reducing the gain, per sample
+const RELEASE: Sample = 0.000_05;
+/// Bounds of the normalization gain, in dB
+const MAX_BOOST: f64 = 12.0;
+const MAX_CUT: f64 = -20.0;
+/// How fast the gain follows the running estimate, per sample
+const ADAPT_RATE: Sample = 0.000_02;
+/// Blocks measured before trusting the running estimate, 100 ms each
+const WARMUP_BLOCKS: usize = 30;
+
+fn gain_for(target: f32, loudness: f64) -> Sample {
+    let db = (f64::from(target) - loudness).clamp(MAX_CUT, MAX_BOOST);
+    #[allow(clippy::cast_possible_truncation)]
+    let gain = 10_f64.powf(db / 20.0) as Sample;
+    gain
+}
+
+/// Brings episodes to a common loudness. Files measured after download get
+/// a fixed gain, anything else follows a running estimate of its loudness.
+/// A limiter keeps the boosted audio from clipping.
+pub struct Normalize<S> {
+    input: S,
+    target: Option<f32>,
+    /// Only used without a measured loudness
+    meter: Option<LoudnessMeter>,
+    blocks: usize,
+    gain: Sample,
+    target_gain: Sample,
+    reduction: Sample,
+}
+
+impl<S: Source> Normalize<S> {
+    /// `target` is the loudness to reach in LUFS, None to play the audio
+    /// untouched. `loudness` is the measured loudness of the input, if
+    /// known.
+    pub fn new(input: S, target: Option<f32>, loudness: Option<f32>) -> Self {
+        let gain = match (target, loudness) {
+            (Some(target), Some(loudness)) => gain_for(target, f64::from(loudness)),
+            _ => 1.0,
+        };
+        let meter = (target.is_some() && loudness.is_none()).then(|| {
+            LoudnessMeter::new(
+                usize::from(input.channels().get()),
+                input.sample_rate().get(),
+            )
+        });
+        Self {
+            input,
+            target,
+            meter,
+            blocks: 0,
+            gain,
+            target_gain: gain,
+            reduction: 1.0,
+        }
+    }
+}
+
+impl<S: Source> Iterator for Normalize<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+        let Some(target) = self.target else {
+            return Some(sample);
+        };
+        if let Some(meter) = &mut self.meter {
+            if meter.push(sample) {
+                self.blocks += 1;
+                if self.blocks >= WARMUP_BLOCKS
+                    && let Some(loudness) = meter.running()
+                {
+                    self.target_gain = gain_for(target, loudness);
+                }
+            }
+            self.gain = (self.target_gain - self.gain).mul_add(ADAPT_RATE, self.gain);
+        }
+
+        let output = sample * self.gain;
+        if output.abs() * self.reduction > CEILING {
+            self.reduction = CEILING / output.abs();
+        } else {
+            self.reduction = (1.0 - self.reduction).mul_add(RELEASE, self.reduction);
+        }
+        Some(output * self.reduction)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Normalize<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        // the estimate so far is still valid for the rest of the episode
+        self.input.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZero;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    #[allow(clippy::cast_precision_loss)]
+    fn sine(amplitude: f32, seconds: usize) -> SamplesBuffer {
+        let rate = 48_000;
+        let samples: Vec<Sample> = (0..rate * seconds)
+            .map(|n| amplitude * (std::f32::consts::TAU * 1000.0 * n as f32 / rate as f32).sin())
+            .collect();
+        SamplesBuffer::new(
+            NonZero::new(1).unwrap(),
+            NonZero::new(48_000).unwrap(),
+            samples,
+        )
+    }
+
+    fn peak(samples: &[Sample]) -> Sample {
+        samples.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn disabled_is_passthrough() {
+        let output: Vec<Sample> = Normalize::new(sine(0.5, 1), None, Some(-30.0)).collect();
+        let input: Vec<Sample> = sine(0.5, 1).collect();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn measured_loudness_sets_gain() {
+        // a sine at 0.1 measures about -23 LUFS, 7 dB below the target
+        let output: Vec<Sample> = Normalize::new(sine(0.1, 1), Some(-16.0), Some(-23.0)).collect();
+        let expected = 0.1 * 10_f32.powf(7.0 / 20.0);
+        assert!((peak(&output) - expected).abs() < 0.005);
+    }
+
+    #[test]
+    fn limiter_prevents_clipping() {
+        let output: Vec<Sample> = Normalize::new(sine(0.9, 1), Some(-5.0), Some(-20.0)).collect();
+        assert!(peak(&output) <= CEILING + f32::EPSILON);
+    }
+
+    #[test]
+    fn running_estimate_moves_towards_target() {
+        let output: Vec<Sample> = Normalize::new(sine(0.08, 30), Some(-16.0), None).collect();
+        let mut meter = LoudnessMeter::new(1, 48_000);
+        for &sample in &output[output.len() - 48_000 * 5..] {
+            meter.push(sample);
+        }
+        let loudness = meter.integrated().unwrap();
+        assert!((loudness + 16.0).abs() < 1.0, "{loudness}");
+    }
+}
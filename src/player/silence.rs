@@ -0,0 +1,192 @@
+use std::{sync::Arc, time::Duration};
+
+use rodio::{ChannelCount, Sample, SampleRate, Source, source::SeekError};
+
+use super::controls::Controls;
+
+/// Length of the blocks the input is judged in
+const BLOCK_LENGTH: Duration = Duration::from_millis(10);
+/// Blocks with a lower RMS level are silent, -45 dBFS
+const SILENCE_THRESHOLD: Sample = 0.005_6;
+/// Silence kept at the start of every pause, so speech still breathes
+const KEPT_SILENCE: Duration = Duration::from_millis(300);
+#[allow(clippy::cast_possible_truncation)]
+const KEPT_BLOCKS: usize = (KEPT_SILENCE.as_millis() / BLOCK_LENGTH.as_millis()) as usize;
+
+/// Shortens pauses: once the input has been silent for `KEPT_SILENCE`, the
+/// rest of the silence is dropped. Cuts only happen between two silent
+/// blocks, so they do not click. The time dropped is added to the
+/// controls. Has to sit after the clock, so the position keeps counting
+/// the skipped audio.
+pub struct SkipSilence<S> {
+    input: S,
+    controls: Arc<Controls>,
+    enabled: bool,
+    block: Vec<Sample>,
+    /// Next sample of `block` to output
+    index: usize,
+    /// Consecutive silent blocks played so far
+    silent_blocks: usize,
+}
+
+impl<S: Source> SkipSilence<S> {
+    pub const fn new(input: S, controls: Arc<Controls>, enabled: bool) -> Self {
+        Self {
+            input,
+            controls,
+            enabled,
+            block: Vec::new(),
+            index: 0,
+            silent_blocks: 0,
+        }
+    }
+
+    fn block_frames(&self) -> usize {
+        let rate = self.input.sample_rate().get() as usize;
+        (rate * BLOCK_LENGTH.as_millis() as usize / 1000).max(1)
+    }
+
+    /// Reads the next block into `block`, false if the input ended
+    fn read_block(&mut self) -> bool {
+        let samples = self.block_frames() * usize::from(self.input.channels().get());
+        self.block.clear();
+        self.index = 0;
+        self.block.extend(self.input.by_ref().take(samples));
+        !self.block.is_empty()
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn is_silent(&self) -> bool {
+        let energy = self
+            .block
+            .iter()
+            .map(|sample| sample * sample)
+            .sum::<Sample>();
+        (energy / self.block.len() as Sample).sqrt() < SILENCE_THRESHOLD
+    }
+}
+
+impl<S: Source> Iterator for SkipSilence<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if !self.enabled {
+            return self.input.next();
+        }
+        while self.index >= self.block.len() {
+            if !self.read_block() {
+                return None;
+            }
+            if !self.is_silent() {
+                self.silent_blocks = 0;
+            } else if self.silent_blocks < KEPT_BLOCKS {
+                self.silent_blocks += 1;
+            } else {
+                let frames = self.block.len() / usize::from(self.input.channels().get());
+                let rate = self.input.sample_rate().get();
+                #[allow(clippy::cast_possible_truncation)]
+                self.controls.add_skipped(Duration::from_micros(
+                    frames as u64 * 1_000_000 / u64::from(rate),
+                ));
+                self.block.clear();
+            }
+        }
+        let sample = self.block[self.index];
+        self.index += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for SkipSilence<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        if self.enabled {
+            None
+        } else {
+            self.input.current_span_len()
+        }
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.block.clear();
+        self.index = 0;
+        self.silent_blocks = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZero;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    /// One second of tone, `silence` seconds of silence, one more of tone
+    #[allow(clippy::cast_precision_loss)]
+    fn with_pause(silence: usize) -> SamplesBuffer {
+        let rate = 8000;
+        let samples: Vec<Sample> = (0..rate * (2 + silence))
+            .map(|n| {
+                if n < rate || n >= rate * (1 + silence) {
+                    0.5 * (std::f32::consts::TAU * 220.0 * n as f32 / rate as f32).sin()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        SamplesBuffer::new(
+            NonZero::new(1).unwrap(),
+            NonZero::new(8000).unwrap(),
+            samples,
+        )
+    }
+
+    #[test]
+    fn disabled_is_passthrough() {
+        let controls = Arc::new(Controls::default());
+        let output: Vec<Sample> =
+            SkipSilence::new(with_pause(2), controls.clone(), false).collect();
+        let input: Vec<Sample> = with_pause(2).collect();
+        assert_eq!(output, input);
+        assert_eq!(controls.take_skipped(), Duration::ZERO);
+    }
+
+    #[test]
+    fn long_pause_is_shortened() {
+        let controls = Arc::new(Controls::default());
+        let output = SkipSilence::new(with_pause(3), controls.clone(), true).count();
+        // two seconds of tone plus the silence that is kept
+        assert!(output.abs_diff(8000 * 2 + 2400) <= 80, "{output}");
+        let skipped = controls.take_skipped();
+        assert!(skipped.abs_diff(Duration::from_millis(2700)) <= Duration::from_millis(10));
+        assert_eq!(controls.take_skipped(), Duration::ZERO);
+    }
+
+    #[test]
+    fn short_pause_is_kept() {
+        let controls = Arc::new(Controls::default());
+        let source = SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(8000).unwrap(),
+            vec![0.0; 2 * 8000 / 5],
+        );
+        assert_eq!(
+            SkipSilence::new(source, controls, true).count(),
+            2 * 8000 / 5
+        );
+    }
+}
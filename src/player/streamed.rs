@@ -0,0 +1,86 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc::Sender},
+};
+
+use stream_download::{
+    Settings,
+    http::{HttpStream, reqwest::Client},
+    storage::temp::{TempStorageProvider, tempfile},
+};
+
+use crate::{downloads::DownloadMsg, types::Message};
+
+/// Directory under the download path that holds episodes while they are
+/// streamed
+const STREAM_DIR: &str = ".streaming";
+
+/// Saves streamed episodes, so they do not have to be downloaded again.
+/// Each stream is written to a temporary file, which is removed when the
+/// stream is dropped unless the whole episode was received.
+#[derive(Clone)]
+pub struct StreamKeeper {
+    dir: PathBuf,
+    tx_to_main: Sender<Message>,
+}
+
+impl StreamKeeper {
+    /// Removes what is left from streams that were interrupted, the
+    /// temporary files are only removed on a clean exit.
+    pub fn new(download_path: &Path, tx_to_main: Sender<Message>) -> Self {
+        let dir = download_path.join(STREAM_DIR);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)
+                .inspect_err(|err| log::warn!("Failed to remove partial streams: {err}"))
+                .ok();
+        }
+        std::fs::create_dir_all(&dir)
+            .inspect_err(|err| log::warn!("Failed to create {}: {err}", dir.display()))
+            .ok();
+        Self { dir, tx_to_main }
+    }
+
+    /// Storage for streaming an episode, and settings that report the file
+    /// to the app once the stream completed. The episode is known by its
+    /// id, since the url streamed from can be a redirection of its own.
+    pub fn storage(
+        &self, ep_id: i64, content_type: Option<String>,
+    ) -> (TempStorageProvider, Settings<HttpStream<Client>>) {
+        let path = Arc::new(Mutex::new(None));
+        let provider = {
+            let dir = self.dir.clone();
+            let path = path.clone();
+            TempStorageProvider::with_tempfile_builder(move || {
+                let file = tempfile::Builder::new().suffix(".part").tempfile_in(&dir)?;
+                *path.lock().expect("Mutex lock should not fail") = Some(file.path().to_path_buf());
+                Ok(file)
+            })
+        };
+        let tx_to_main = self.tx_to_main.clone();
+        let settings = Settings::default().on_progress(move |_, state, _| {
+            if !matches!(state.phase, stream_download::StreamPhase::Complete) {
+                return;
+            }
+            let Some(path) = path.lock().expect("Mutex lock should not fail").take() else {
+                return;
+            };
+            // linked, since the temporary file is removed with the stream
+            let complete = path.with_extension("complete");
+            if let Err(err) = std::fs::hard_link(&path, &complete)
+                .or_else(|_| std::fs::copy(&path, &complete).map(|_| ()))
+            {
+                log::warn!("Failed to keep streamed episode: {err}");
+                return;
+            }
+            tx_to_main
+                .send(Message::Dl(DownloadMsg::Streamed(
+                    ep_id,
+                    complete,
+                    content_type.clone(),
+                )))
+                .inspect_err(|err| log::error!("Failed to send Message::Dl: {err}"))
+                .ok();
+        });
+        (provider, settings)
+    }
+}
@@ -0,0 +1,364 @@
+use std::{collections::VecDeque, f32::consts::TAU, sync::Arc, time::Duration};
+
+use rodio::{ChannelCount, Sample, SampleRate, Source, source::SeekError};
+
+use super::controls::Controls;
+
+/// Length of the overlapping segments the output is built from
+const SEGMENT_LENGTH: Duration = Duration::from_millis(30);
+/// How far a segment may be shifted from its nominal position to line up
+/// with the previous one
+const SEARCH_TOLERANCE: Duration = Duration::from_millis(8);
+/// Step used when comparing segments, trades accuracy for CPU time
+const SEARCH_STRIDE: usize = 2;
+/// Speeds closer to 1.0 than this are played untouched
+const SPEED_EPSILON: f32 = 0.01;
+
+/// Changes the playback speed while keeping the pitch, using waveform
+/// similarity overlap-add (WSOLA): the output is made of half-overlapping
+/// Hann windowed segments of the input, each one picked close to its
+/// nominal position where it best continues the previous segment.
+pub struct TimeStretch<S> {
+    input: S,
+    controls: Arc<Controls>,
+    channels: usize,
+    /// Segment length in frames, always even
+    segment: usize,
+    tolerance: usize,
+    window: Vec<Sample>,
+    /// Interleaved input not yet discarded
+    buffer: Vec<Sample>,
+    /// Nominal start of the next segment, in frames into `buffer`
+    analysis: f64,
+    /// Where the input continues the last segment used, in frames into
+    /// `buffer`
+    continuation: Option<usize>,
+    /// Second half of the last segment, already windowed
+    tail: Vec<Sample>,
+    output: VecDeque<Sample>,
+    stretching: bool,
+    exhausted: bool,
+}
+
+impl<S: Source> TimeStretch<S> {
+    pub fn new(input: S, controls: Arc<Controls>) -> Self {
+        let mut stretch = Self {
+            input,
+            controls,
+            channels: 1,
+            segment: 2,
+            tolerance: 0,
+            window: Vec::new(),
+            buffer: Vec::new(),
+            analysis: 0.0,
+            continuation: None,
+            tail: Vec::new(),
+            output: VecDeque::new(),
+            stretching: false,
+            exhausted: false,
+        };
+        stretch.configure();
+        stretch
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    fn configure(&mut self) {
+        let rate = self.input.sample_rate().get() as usize;
+        self.channels = usize::from(self.input.channels().get());
+        self.segment = (rate * SEGMENT_LENGTH.as_millis() as usize / 1000).max(2) & !1;
+        self.tolerance = rate * SEARCH_TOLERANCE.as_millis() as usize / 1000;
+        let length = self.segment as f32;
+        self.window = (0..self.segment)
+            .map(|n| 0.5f32.mul_add(-(TAU * n as f32 / length).cos(), 0.5))
+            .collect();
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.tail.clear();
+        self.analysis = 0.0;
+        self.continuation = None;
+    }
+
+    const fn frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    /// Reads input until the buffer holds `frames` frames, false if the
+    /// input ends first
+    fn fill(&mut self, frames: usize) -> bool {
+        while self.frames() < frames {
+            match self.input.next() {
+                Some(sample) => self.buffer.push(sample),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    fn mono(&self, frame: usize) -> Sample {
+        self.buffer[frame * self.channels..(frame + 1) * self.channels]
+            .iter()
+            .sum()
+    }
+
+    /// Finds the segment start around `nominal` most similar to the input
+    /// continuing the previous segment
+    fn best_match(&self, continuation: usize, nominal: usize) -> usize {
+        let half = self.segment / 2;
+        let mut best = (nominal, Sample::MIN);
+        for candidate in (nominal.saturating_sub(self.tolerance)..=nominal + self.tolerance)
+            .step_by(SEARCH_STRIDE)
+        {
+            let (mut correlation, mut energy) = (0.0, 0.0);
+            for i in (0..half).step_by(SEARCH_STRIDE) {
+                let sample = self.mono(candidate + i);
+                correlation = sample.mul_add(self.mono(continuation + i), correlation);
+                energy = sample.mul_add(sample, energy);
+            }
+            let score = correlation / energy.sqrt().max(Sample::EPSILON);
+            if score > best.1 {
+                best = (candidate, score);
+            }
+        }
+        best.0
+    }
+
+    /// Produces half a segment of output, false if the input ended
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn step(&mut self, speed: f32) -> bool {
+        let half = self.segment / 2;
+        let nominal = self.analysis.round() as usize;
+        if !self.fill(nominal + self.tolerance + self.segment) {
+            return false;
+        }
+        let start = match self.continuation {
+            // nothing to overlap with, so the rising half is used as is
+            None => {
+                self.output.extend(
+                    &self.buffer[nominal * self.channels..(nominal + half) * self.channels],
+                );
+                nominal
+            }
+            Some(continuation) => {
+                let start = self.best_match(continuation, nominal);
+                let offset = start * self.channels;
+                for (i, tail) in self.tail.iter().enumerate() {
+                    let window = self.window[i / self.channels];
+                    self.output
+                        .push_back(tail + window * self.buffer[offset + i]);
+                }
+                start
+            }
+        };
+        self.tail.clear();
+        for i in half * self.channels..self.segment * self.channels {
+            let window = self.window[i / self.channels];
+            self.tail
+                .push(window * self.buffer[start * self.channels + i]);
+        }
+        self.continuation = Some(start + half);
+        self.analysis = (half as f64).mul_add(f64::from(speed), self.analysis);
+        self.discard();
+        true
+    }
+
+    /// Drops the input no later segment can reach
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn discard(&mut self) {
+        let Some(continuation) = self.continuation else {
+            return;
+        };
+        let used = continuation.min((self.analysis as usize).saturating_sub(self.tolerance));
+        if used >= self.segment {
+            self.buffer.drain(..used * self.channels);
+            self.continuation = Some(continuation - used);
+            self.analysis -= used as f64;
+        }
+    }
+
+    /// Goes back to passthrough. The input following the last segment is
+    /// output untouched, which also completes its windowed tail, as two
+    /// half-overlapping Hann windows add up to one.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn stop_stretching(&mut self) {
+        let from = self
+            .continuation
+            .unwrap_or_else(|| self.analysis.round() as usize)
+            * self.channels;
+        if from < self.buffer.len() {
+            self.output.extend(&self.buffer[from..]);
+        }
+        self.reset();
+        self.stretching = false;
+    }
+}
+
+impl<S: Source> Iterator for TimeStretch<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        loop {
+            if let Some(sample) = self.output.pop_front() {
+                return Some(sample);
+            }
+            if self.exhausted {
+                return None;
+            }
+            let speed = self.controls.speed();
+            if (speed - 1.0).abs() < SPEED_EPSILON {
+                if !self.stretching {
+                    return self.input.next();
+                }
+                self.stop_stretching();
+            } else {
+                if !self.stretching {
+                    self.configure();
+                    self.stretching = true;
+                }
+                if !self.step(speed) {
+                    self.stop_stretching();
+                    self.exhausted = true;
+                }
+            }
+        }
+    }
+}
+
+impl<S: Source> Source for TimeStretch<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.output.clear();
+        self.reset();
+        self.stretching = false;
+        self.exhausted = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZero;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    #[allow(clippy::cast_precision_loss)]
+    fn sine(channels: u16, seconds: usize) -> SamplesBuffer {
+        let rate = 8000;
+        let samples: Vec<Sample> = (0..rate * seconds)
+            .flat_map(|n| {
+                let value = (TAU * 220.0 * n as f32 / rate as f32).sin() * 0.5;
+                std::iter::repeat_n(value, usize::from(channels))
+            })
+            .collect();
+        SamplesBuffer::new(
+            NonZero::new(channels).unwrap(),
+            NonZero::new(8000).unwrap(),
+            samples,
+        )
+    }
+
+    fn stretched_len(speed: f32, channels: u16) -> usize {
+        let controls = Arc::new(Controls::default());
+        controls.set_speed(speed);
+        TimeStretch::new(sine(channels, 4), controls).count()
+    }
+
+    #[test]
+    fn normal_speed_is_passthrough() {
+        let controls = Arc::new(Controls::default());
+        let output: Vec<Sample> = TimeStretch::new(sine(2, 1), controls).collect();
+        let input: Vec<Sample> = sine(2, 1).collect();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn output_length_scales_with_speed() {
+        let input = 8000 * 4 * 2;
+        for speed in [0.5, 1.5, 2.0, 3.0] {
+            let output = stretched_len(speed, 2);
+            let expected = input as f32 / speed;
+            let error = (output as f32 - expected).abs() / expected;
+            assert!(error < 0.02, "speed {speed}: {output} samples");
+            assert_eq!(output % 2, 0);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn pitch_is_preserved() {
+        let crossings = |samples: &[Sample]| {
+            let count = samples
+                .windows(2)
+                .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+                .count();
+            count as f32 / samples.len() as f32
+        };
+        let input: Vec<Sample> = sine(1, 4).collect();
+        for speed in [0.7, 1.8] {
+            let controls = Arc::new(Controls::default());
+            controls.set_speed(speed);
+            let output: Vec<Sample> = TimeStretch::new(sine(1, 4), controls).collect();
+            let ratio = crossings(&output) / crossings(&input);
+            assert!((ratio - 1.0).abs() < 0.02, "speed {speed}: ratio {ratio}");
+        }
+    }
+
+    #[test]
+    fn output_stays_in_range() {
+        let controls = Arc::new(Controls::default());
+        controls.set_speed(1.7);
+        assert!(TimeStretch::new(sine(1, 2), controls).all(|s| s.abs() <= 0.51));
+    }
+
+    #[test]
+    fn speed_change_midway_keeps_all_audio() {
+        let controls = Arc::new(Controls::default());
+        controls.set_speed(2.0);
+        let mut stretch = TimeStretch::new(sine(1, 4), controls.clone());
+        let first = stretch.by_ref().take(8000).count();
+        controls.set_speed(1.0);
+        let rest = stretch.count();
+        // one second at double speed, then the remaining two seconds
+        assert_eq!(first, 8000);
+        assert!(rest.abs_diff(2 * 8000) < 400, "{rest}");
+    }
+
+    #[test]
+    fn seek_resets_state() {
+        let controls = Arc::new(Controls::default());
+        controls.set_speed(2.0);
+        let mut stretch = TimeStretch::new(sine(1, 4), controls);
+        stretch.by_ref().take(4000).for_each(drop);
+        stretch.try_seek(Duration::from_secs(3)).unwrap();
+        let rest = stretch.count();
+        assert!(rest.abs_diff(4000) < 400, "{rest}");
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rodio::{Player as RodioPlayer, Source};
+
+use super::{Decoded, Media, PlaybackStatus, Player, PodcastSettings, controls::Controls};
+
+/// The next episode is appended to the sink this long before the current
+/// one ends, it has to cover the interval between two checks
+const APPEND_MARGIN: Duration = Duration::from_secs(2);
+
+/// Episode opened in the background, still at the start
+pub struct Loaded {
+    media: Media,
+    position: Duration,
+    duration: u64,
+    settings: PodcastSettings,
+    source: Decoded,
+}
+
+/// Episode that follows the current one in the queue, with its own
+/// controls since both play at the same time during a crossfade
+pub struct Upcoming {
+    media: Media,
+    duration: u64,
+    skip_outro: u64,
+    controls: Arc<Controls>,
+    state: NextState,
+}
+
+enum NextState {
+    /// Waiting for the end of the current episode
+    Ready(Decoded),
+    /// Appended to the sink after the current episode
+    Queued,
+    /// Playing in its own sink while the current episode fades out
+    Fading(RodioPlayer, Instant),
+}
+
+impl Upcoming {
+    /// True once the episode was handed to a sink
+    pub const fn started(&self) -> bool {
+        !matches!(self.state, NextState::Ready(_))
+    }
+
+    /// The sink the episode fades in on, during a crossfade
+    pub const fn fading_in(&self) -> Option<&RodioPlayer> {
+        match &self.state {
+            NextState::Fading(sink, _) => Some(sink),
+            _ => None,
+        }
+    }
+}
+
+impl Player {
+    /// Starts opening the episode in the background, replacing the one
+    /// prepared before.
+    pub(super) fn prepare(
+        &mut self, media: Media, position: Duration, duration: u64, settings: PodcastSettings,
+    ) {
+        if self.upcoming.as_ref().is_some_and(Upcoming::started) {
+            log::warn!("Next episode already started, ignoring the new one");
+            return;
+        }
+        self.clear_upcoming();
+        let keeper = self.keeper.clone();
+        self.loading = Some(tokio::spawn(async move {
+            let source = media.decode(keeper.as_ref()).await?;
+            Ok(Loaded {
+                media,
+                position,
+                duration,
+                settings,
+                source,
+            })
+        }));
+    }
+
+    /// Readies the opened episode with the settings of its podcast, mono
+    /// and balance are the same for all episodes.
+    pub(super) fn prepared(&mut self, loaded: Loaded) {
+        let controls = Arc::new(Controls::default());
+        controls.set_speed(loaded.settings.speed);
+        controls.set_equalizer(loaded.settings.equalizer);
+        controls.set_mono(self.controls.mono());
+        controls.set_balance(self.controls.balance());
+        let mut source = self.wrap_source(loaded.source, &controls, loaded.media.loudness());
+        if !loaded.position.is_zero() {
+            source
+                .try_seek(loaded.position)
+                .inspect_err(|err| log::warn!("Failed to seek: {err}"))
+                .ok();
+        }
+        self.upcoming = Some(Upcoming {
+            media: loaded.media,
+            duration: loaded.duration,
+            skip_outro: loaded.settings.skip_outro,
+            controls,
+            state: NextState::Ready(Box::new(source)),
+        });
+    }
+
+    /// Drops the prepared episode, unless it is already playing
+    pub(super) fn cancel_upcoming(&mut self) {
+        if !self.upcoming.as_ref().is_some_and(Upcoming::started) {
+            self.clear_upcoming();
+        }
+    }
+
+    /// Drops the prepared episode. Callers replace the sink contents, so
+    /// an episode appended to it does not outlive the call.
+    pub(super) fn clear_upcoming(&mut self) {
+        if let Some(loading) = self.loading.take() {
+            loading.abort();
+        }
+        if let Some(sink) = self.upcoming.take().as_ref().and_then(Upcoming::fading_in) {
+            sink.stop();
+            self.sink.set_volume(self.volume());
+        }
+    }
+
+    /// Hands the prepared episode to the output once the current one gets
+    /// close to its end, and continues with it when the current one ended.
+    pub(super) fn check_upcoming(&mut self) {
+        let Some(mut upcoming) = self.upcoming.take() else {
+            return;
+        };
+        let remaining = self.remaining();
+        upcoming.state = match upcoming.state {
+            // without a duration the end cannot be anticipated
+            NextState::Ready(source) if self.is_playing() && self.duration > 0 => {
+                if !self.crossfade.is_zero() && remaining <= self.crossfade {
+                    let sink = RodioPlayer::connect_new(self.stream_handle.mixer());
+                    sink.set_volume(0.0);
+                    sink.append(source);
+                    NextState::Fading(sink, Instant::now())
+                } else if remaining <= APPEND_MARGIN {
+                    self.sink.append(source);
+                    NextState::Queued
+                } else {
+                    NextState::Ready(source)
+                }
+            }
+            NextState::Queued => {
+                if self.sink.len() > 1 && self.skip_outro > 0 && remaining.is_zero() {
+                    self.sink.skip_one();
+                }
+                if self.sink.len() > 1 {
+                    NextState::Queued
+                } else {
+                    return self.advance(
+                        upcoming.media,
+                        upcoming.duration,
+                        upcoming.skip_outro,
+                        upcoming.controls,
+                    );
+                }
+            }
+            NextState::Fading(sink, start) => {
+                let progress = start.elapsed().as_secs_f32() / self.crossfade.as_secs_f32();
+                if progress >= 1.0 || self.sink.empty() {
+                    self.sink.stop();
+                    self.sink = sink;
+                    self.sink.set_volume(self.volume());
+                    return self.advance(
+                        upcoming.media,
+                        upcoming.duration,
+                        upcoming.skip_outro,
+                        upcoming.controls,
+                    );
+                }
+                self.sink.set_volume(self.volume() * (1.0 - progress));
+                sink.set_volume(self.volume() * progress);
+                NextState::Fading(sink, start)
+            }
+            state @ NextState::Ready(_) => state,
+        };
+        self.upcoming = Some(upcoming);
+    }
+
+    /// Continues with the next episode. The current one is reported as
+    /// finished, so the UI moves on with the queue as usual and then asks
+    /// to play the episode that is already playing.
+    fn advance(&mut self, media: Media, duration: u64, skip_outro: u64, controls: Arc<Controls>) {
+        *self
+            .time_saved
+            .write()
+            .expect("RwLock write should not fail") += self.controls.take_skipped();
+        self.set_position(self.duration);
+        self.controls = controls;
+        self.duration = duration;
+        self.skip_outro = skip_outro;
+        self.current = Some(media.clone());
+        self.advanced = Some(media);
+        self.finish();
+    }
+
+    /// Time until the current episode ends, at the current speed
+    fn remaining(&self) -> Duration {
+        let end = if self.skip_outro > 0 && self.duration > self.skip_outro {
+            self.duration - self.skip_outro
+        } else {
+            self.duration
+        };
+        Duration::from_secs(end)
+            .saturating_sub(self.controls.position())
+            .div_f32(self.controls.speed())
+    }
+
+    fn is_playing(&self) -> bool {
+        self.status == PlaybackStatus::Playing
+    }
+}
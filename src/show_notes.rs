@@ -0,0 +1,409 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::utils::{clean_html, parse_timestamp};
+
+static RE_COMMENTS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->").expect("Regex error"));
+
+static RE_TAGS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/?)([a-zA-Z][a-zA-Z0-9]*)([^<>]*)>").expect("Regex error"));
+
+static RE_BLOCK_TAGS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<(p|br|li|div)\b").expect("Regex error"));
+
+static RE_HREF: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)href\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).expect("Regex error")
+});
+
+/// Bare urls and timestamps like "12:34" or "1:02:03" in the text
+static RE_REFERENCES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(https?://[^\s<>"]*[^\s<>".,;:!?)\]])|\b((?:\d{1,2}:)?\d{1,2}:\d{2})\b"#)
+        .expect("Regex error")
+});
+
+/// Link targets that may be opened: web pages and mail addresses
+static RE_LINK_SCHEMES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^(?:https?://|mailto:)\S").expect("Regex error"));
+
+/// Where a reference of the show notes leads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Link {
+    Url(String),
+    /// Seconds from the start of the episode
+    Timestamp(u64),
+}
+
+/// Piece of a line of show notes, with its emphasis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    /// Whether it is the number of a link, like "[2]"
+    pub reference: bool,
+}
+
+/// Show notes converted from HTML, with the links they refer to by number
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShowNotes {
+    pub lines: Vec<Vec<Span>>,
+    /// Link `[n]` is at index n - 1
+    pub links: Vec<Link>,
+}
+
+impl ShowNotes {
+    /// Converts the HTML of a description, keeping paragraphs, lists,
+    /// emphasis and links. Plain text descriptions keep their line breaks.
+    pub fn parse(html: &str) -> Self {
+        let html = RE_COMMENTS.replace_all(html, "");
+        let mut builder = Builder {
+            keep_newlines: !RE_BLOCK_TAGS.is_match(&html),
+            ..Builder::default()
+        };
+        if !RE_TAGS.is_match(&html) {
+            builder.push_text(&clean_html(&html));
+            return builder.finish();
+        }
+
+        let mut last = 0;
+        for tag in RE_TAGS.captures_iter(&html) {
+            let whole = tag.get(0).expect("Regex match has a whole group");
+            builder.push_text(&decode(&html[last..whole.start()]));
+            last = whole.end();
+            let closing = !tag[1].is_empty();
+            builder.push_tag(&tag[2].to_lowercase(), closing, &tag[3]);
+        }
+        builder.push_text(&decode(&html[last..]));
+        builder.finish()
+    }
+
+    /// Link referred to as `[number]`
+    pub fn link(&self, number: usize) -> Option<&Link> {
+        self.links.get(number.checked_sub(1)?)
+    }
+}
+
+fn decode(text: &str) -> String {
+    escaper::decode_html(text).unwrap_or_else(|_| text.to_string())
+}
+
+/// Writes the lines of the show notes as the HTML is read
+#[derive(Debug, Default)]
+struct Builder {
+    notes: ShowNotes,
+    line: Vec<Span>,
+    bold: usize,
+    italic: usize,
+    /// Lists being written, with the number of their next item, None for
+    /// bullet lists
+    lists: Vec<Option<usize>>,
+    /// Target and text of the link being written
+    link: Option<(String, String)>,
+    /// Whether line breaks in the text start new lines, as in plain text
+    keep_newlines: bool,
+}
+
+impl Builder {
+    fn push_tag(&mut self, name: &str, closing: bool, attributes: &str) {
+        match name {
+            "br" => self.break_line(),
+            "p" | "div" | "blockquote" => self.paragraph(),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.paragraph();
+                self.bold = if closing {
+                    self.bold.saturating_sub(1)
+                } else {
+                    self.bold + 1
+                };
+            }
+            "b" | "strong" => {
+                self.bold = if closing {
+                    self.bold.saturating_sub(1)
+                } else {
+                    self.bold + 1
+                };
+            }
+            "i" | "em" => {
+                self.italic = if closing {
+                    self.italic.saturating_sub(1)
+                } else {
+                    self.italic + 1
+                };
+            }
+            "ul" | "ol" => {
+                self.end_line();
+                if closing {
+                    self.lists.pop();
+                } else {
+                    self.lists.push((name == "ol").then_some(1));
+                }
+            }
+            "li" => {
+                self.end_line();
+                if !closing {
+                    let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                    let marker = match self.lists.last_mut() {
+                        Some(Some(number)) => {
+                            *number += 1;
+                            format!("{indent}{}. ", *number - 1)
+                        }
+                        _ => format!("{indent}• "),
+                    };
+                    self.push_span(marker, false);
+                }
+            }
+            "a" if closing => self.end_link(),
+            "a" => {
+                self.link = RE_HREF.captures(attributes).and_then(|href| {
+                    let href = href
+                        .get(1)
+                        .or_else(|| href.get(2))
+                        .or_else(|| href.get(3))?;
+                    Some((decode(href.as_str().trim()), String::new()))
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes text, numbering the bare urls and timestamps it mentions
+    fn push_text(&mut self, text: &str) {
+        if self.keep_newlines {
+            for (i, part) in text.lines().enumerate() {
+                if i > 0 {
+                    self.break_line();
+                }
+                self.push_words(part);
+            }
+            if text.ends_with('\n') {
+                self.break_line();
+            }
+        } else {
+            self.push_words(text);
+        }
+    }
+
+    /// Writes text on the current line, with its whitespace collapsed
+    fn push_words(&mut self, text: &str) {
+        let mut words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if words.is_empty() {
+            if !text.is_empty() && !self.at_line_start() {
+                self.push_span(" ".to_string(), false);
+            }
+            return;
+        }
+        if text.starts_with(char::is_whitespace) && !self.at_line_start() {
+            words.insert(0, ' ');
+        }
+        if text.ends_with(char::is_whitespace) {
+            words.push(' ');
+        }
+        if let Some((_, link_text)) = &mut self.link {
+            link_text.push_str(&words);
+            return self.push_span(words, false);
+        }
+
+        let mut last = 0;
+        for reference in RE_REFERENCES.captures_iter(&words) {
+            let whole = reference.get(0).expect("Regex match has a whole group");
+            let link = match (reference.get(1), reference.get(2)) {
+                (Some(url), _) => Some(Link::Url(url.as_str().to_string())),
+                (None, Some(time)) => parse_timestamp(time.as_str()).map(Link::Timestamp),
+                (None, None) => None,
+            };
+            let Some(link) = link else {
+                continue;
+            };
+            self.push_span(words[last..whole.end()].to_string(), false);
+            self.push_reference(link);
+            last = whole.end();
+        }
+        self.push_span(words[last..].to_string(), false);
+    }
+
+    /// Numbers the link just written, a timestamp if that is its text
+    fn end_link(&mut self) {
+        let Some((href, text)) = self.link.take() else {
+            return;
+        };
+        let link = parse_timestamp(text.trim())
+            .filter(|_| text.contains(':'))
+            .map_or(Link::Url(href), Link::Timestamp);
+        if matches!(&link, Link::Url(url) if !RE_LINK_SCHEMES.is_match(url)) {
+            return;
+        }
+        self.push_reference(link);
+    }
+
+    /// Writes `[n]` for a link, reusing the number of an identical one
+    fn push_reference(&mut self, link: Link) {
+        let number = if let Some(index) = self.notes.links.iter().position(|known| *known == link) {
+            index + 1
+        } else {
+            self.notes.links.push(link);
+            self.notes.links.len()
+        };
+        self.push_span(format!("[{number}]"), true);
+    }
+
+    fn push_span(&mut self, text: String, reference: bool) {
+        if text.is_empty() {
+            return;
+        }
+        let (bold, italic) = (self.bold > 0, self.italic > 0);
+        match self.line.last_mut() {
+            Some(last)
+                if last.bold == bold && last.italic == italic && last.reference == reference =>
+            {
+                last.text.push_str(&text);
+            }
+            _ => self.line.push(Span {
+                text,
+                bold,
+                italic,
+                reference,
+            }),
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.line
+            .last()
+            .is_none_or(|span| span.text.ends_with(char::is_whitespace))
+    }
+
+    /// Starts a new line, even if the current one is empty
+    fn break_line(&mut self) {
+        let mut line = std::mem::take(&mut self.line);
+        if let Some(last) = line.last_mut() {
+            let trimmed = last.text.trim_end().len();
+            last.text.truncate(trimmed);
+        }
+        line.retain(|span| !span.text.is_empty());
+        self.notes.lines.push(line);
+    }
+
+    /// Starts a new line, unless the current one is empty
+    fn end_line(&mut self) {
+        if self.line.iter().any(|span| !span.text.trim().is_empty()) {
+            self.break_line();
+        } else {
+            self.line.clear();
+        }
+    }
+
+    /// Leaves a blank line before what comes next
+    fn paragraph(&mut self) {
+        self.end_line();
+        if self.notes.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.notes.lines.push(Vec::new());
+        }
+    }
+
+    fn finish(mut self) -> ShowNotes {
+        self.end_line();
+        // at most one blank line in a row, none around the notes
+        self.notes
+            .lines
+            .dedup_by(|line, previous| line.is_empty() && previous.is_empty());
+        while self.notes.lines.last().is_some_and(Vec::is_empty) {
+            self.notes.lines.pop();
+        }
+        if self.notes.lines.first().is_some_and(Vec::is_empty) {
+            self.notes.lines.remove(0);
+        }
+        self.notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(notes: &ShowNotes) -> Vec<String> {
+        notes
+            .lines
+            .iter()
+            .map(|line| line.iter().map(|span| span.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn paragraphs_and_lists() {
+        let notes = ShowNotes::parse(
+            "<p>Intro</p><ul><li>one</li><li>two</li></ul><ol><li>first</li><li>second</li></ol>",
+        );
+        assert_eq!(
+            text(&notes),
+            ["Intro", "", "• one", "• two", "1. first", "2. second"]
+        );
+    }
+
+    #[test]
+    fn emphasis() {
+        let notes = ShowNotes::parse("<p>a <b>bold</b> and <em>italic</em> word</p>");
+        let line = &notes.lines[0];
+        assert_eq!(line[0].text, "a ");
+        assert!(line[1].bold && line[1].text == "bold");
+        assert!(line[3].italic && line[3].text == "italic");
+        assert!(!line[4].bold && !line[4].italic);
+    }
+
+    #[test]
+    fn links_are_numbered_once() {
+        let notes = ShowNotes::parse(
+            "<p>See <a href=\"https://a.example\">this</a>, \
+             <a href='https://b.example'>that</a> and <a href=\"https://a.example\">again</a></p>",
+        );
+        assert_eq!(text(&notes), ["See this[1], that[2] and again[1]"]);
+        assert_eq!(
+            notes.link(2),
+            Some(&Link::Url("https://b.example".to_string()))
+        );
+        assert!(notes.link(0).is_none());
+        assert!(notes.link(3).is_none());
+    }
+
+    #[test]
+    fn timestamps_and_bare_urls() {
+        let notes = ShowNotes::parse("00:00 Intro\n12:34 Guest, see https://c.example.\n");
+        assert_eq!(
+            text(&notes),
+            [
+                "00:00[1] Intro",
+                "12:34[2] Guest, see https://c.example[3]."
+            ]
+        );
+        assert_eq!(notes.links[1], Link::Timestamp(754));
+        assert_eq!(notes.links[2], Link::Url("https://c.example".to_string()));
+    }
+
+    #[test]
+    fn timestamp_links() {
+        let notes = ShowNotes::parse("<p><a href=\"#t=1:02:03\">1:02:03</a> Outro</p>");
+        assert_eq!(text(&notes), ["1:02:03[1] Outro"]);
+        assert_eq!(notes.links, [Link::Timestamp(3723)]);
+    }
+
+    #[test]
+    fn only_web_and_mail_links() {
+        let notes = ShowNotes::parse(
+            "<p><a href=\"file:///etc/passwd\">a</a> <a href=\"--help\">b</a> \
+             <a href=\"javascript:alert(1)\">c</a> <a href=\"#top\">d</a> \
+             <a href=\"mailto:host@example.com\">e</a></p>",
+        );
+        assert_eq!(text(&notes), ["a b c d e[1]"]);
+        assert_eq!(
+            notes.links,
+            [Link::Url("mailto:host@example.com".to_string())]
+        );
+    }
+
+    #[test]
+    fn html_whitespace_collapses() {
+        let notes = ShowNotes::parse("<p>one\n   two</p>\n\n<p>three &amp; four</p><br><br><br>");
+        assert_eq!(text(&notes), ["one two", "", "three & four"]);
+    }
+}
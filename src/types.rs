@@ -1,13 +1,16 @@
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard};
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 
+use crate::chapters::Chapter;
 use crate::downloads::DownloadMsg;
 use crate::feeds::FeedMsg;
 use crate::gpodder::GpodderMsg;
+use crate::player::{EqPreset, PlayerEvent};
 use crate::ui::UiMsg;
 use crate::utils::{StringUtils, format_duration};
 
@@ -21,7 +24,18 @@ pub struct Podcast {
     pub description: Option<String>,
     pub author: Option<String>,
     pub explicit: Option<bool>,
+    /// Artwork of the feed
+    pub image_url: Option<String>,
     pub last_checked: DateTime<Utc>,
+    pub speed: f32,
+    pub volume_offset: i32,
+    /// Seconds skipped at the start and the end of every episode
+    pub skip_intro: u64,
+    pub skip_outro: u64,
+    /// Equalizer preset, None for the one from the config
+    pub equalizer: Option<EqPreset>,
+    /// Order of the episodes
+    pub sort: SortMode,
     pub episodes: LockVec<Episode>,
 }
 
@@ -33,6 +47,15 @@ impl Podcast {
             .iter()
             .sum()
     }
+
+    /// Publication date of the newest episode
+    fn latest_pubdate(&self) -> Option<DateTime<Utc>> {
+        self.episodes
+            .map(|ep| ep.pubdate, false)
+            .into_iter()
+            .flatten()
+            .max()
+    }
 }
 
 impl PartialEq for Podcast {
@@ -58,7 +81,7 @@ impl Ord for Podcast {
 /// is metadata, but if the episode has been downloaded to the local
 /// machine, the filepath will be included here as well. `played`
 /// indicates whether the podcast has been marked as played or unplayed.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub struct Episode {
     pub id: i64,
     pub pod_id: i64,
@@ -70,9 +93,30 @@ pub struct Episode {
     pub duration: Option<u64>,
     pub position: u64,
     pub path: Option<PathBuf>,
+    /// Integrated loudness of the downloaded file, in LUFS
+    pub loudness: Option<f32>,
+    /// Time saved by skipping silence while playing it
+    pub time_saved: Duration,
+    /// Podcasting 2.0 chapters file linked from the feed
+    pub chapters_url: Option<String>,
+    /// Artwork of the episode, when it has its own
+    pub image_url: Option<String>,
+    /// Sorted by start, empty until they are loaded
+    pub chapters: Vec<Chapter>,
+    /// The chapters were looked for, so none are to be found when empty
+    pub chapters_checked: bool,
+    /// When the position was last saved while listening
+    pub last_played: Option<DateTime<Utc>>,
     pub played: bool,
 }
 
+impl PartialEq for Episode {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+impl Eq for Episode {}
+
 impl Ord for Episode {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.pubdate.cmp(&other.pubdate)
@@ -95,6 +139,7 @@ pub struct PodcastNoId {
     pub description: Option<String>,
     pub author: Option<String>,
     pub explicit: Option<bool>,
+    pub image_url: Option<String>,
     pub last_checked: DateTime<Utc>,
     pub episodes: Vec<EpisodeNoId>,
 }
@@ -109,6 +154,8 @@ pub struct EpisodeNoId {
     pub description: String,
     pub pubdate: Option<DateTime<Utc>>,
     pub duration: Option<u64>,
+    pub chapters_url: Option<String>,
+    pub image_url: Option<String>,
 }
 
 /// Struct holding data about an individual podcast episode, specifically
@@ -199,6 +246,13 @@ impl Menuable for Episode {
     }
 }
 
+impl Episode {
+    /// Whether listening to it started but did not finish
+    pub const fn is_partly_played(&self) -> bool {
+        self.position > 0 && !self.played
+    }
+}
+
 impl Menuable for NewEpisode {
     /// Returns the database ID for the episode.
     fn get_id(&self) -> i64 {
@@ -319,9 +373,13 @@ impl<T: Menuable> LockVec<T> {
         borrowed.get(&id).cloned()
     }
 
-    pub fn get_index(&self, id: i64) -> Option<usize> {
+    pub fn get_index(&self, id: i64, filtered: bool) -> Option<usize> {
         if self.contains_key(id) {
-            let borrowed = self.borrow_order();
+            let borrowed = if filtered {
+                self.borrow_filtered_order()
+            } else {
+                self.borrow_order()
+            };
             for (i, el) in borrowed.iter().enumerate() {
                 if *el == id {
                     return Some(i);
@@ -443,14 +501,12 @@ impl<T: Menuable> LockVec<T> {
     /// Maps a closure to a single element in the `LockVec`, specified by
     /// `index` (position order). If there is no element at that index,
     /// this returns None.
-    pub fn map_single_by_index<B, F>(&self, index: usize, f: F) -> Option<B>
+    pub fn map_single_by_index<B, F>(&self, index: usize, f: F, filtered: bool) -> Option<B>
     where
         F: FnOnce(&T) -> B,
     {
-        let order = self.borrow_order();
-        order
-            .get(index)
-            .map_or_else(|| None, |id| self.map_single(*id, f))
+        self.get_id_by_index(index, filtered)
+            .and_then(|id| self.map_single(id, f))
     }
 
     /// Maps a closure to every element in the `LockVec`, in the same way
@@ -489,8 +545,33 @@ impl<T: Menuable> LockVec<T> {
         return self.borrow_order().is_empty();
     }
 
-    pub fn get_id_by_index(&self, index: usize) -> Option<i64> {
-        let borrowed = self.borrow_order();
+    /// Orders the items by the given key, then by id. The items filtered
+    /// out stay out of the filtered order.
+    pub fn sort_by_key<K: Ord>(&self, mut key: impl FnMut(&T) -> K) {
+        let (map, mut order, mut filtered_order) = self.borrow();
+        let mut keyed: Vec<(K, i64)> = map
+            .iter()
+            .map(|(id, item)| {
+                let item = item.read().expect("RwLock read should not fail");
+                (key(&item), *id)
+            })
+            .collect();
+        keyed.sort();
+        let shown: HashSet<i64> = filtered_order.iter().copied().collect();
+        *order = keyed.into_iter().map(|(_, id)| id).collect();
+        *filtered_order = order
+            .iter()
+            .copied()
+            .filter(|id| shown.contains(id))
+            .collect();
+    }
+
+    pub fn get_id_by_index(&self, index: usize, filtered: bool) -> Option<i64> {
+        let borrowed = if filtered {
+            self.borrow_filtered_order()
+        } else {
+            self.borrow_order()
+        };
         borrowed.get(index).copied()
     }
 }
@@ -519,39 +600,32 @@ impl LockVec<Podcast> {
     }
 }
 
-impl LockVec<Episode> {
-    pub fn sort(&self) {
-        let mut epvec = self
-            .borrow_map()
-            .iter()
-            .map(|(id, ep)| {
-                let value = ep.read().expect("RwLock read should not fail").pubdate;
-                value.map_or((DateTime::UNIX_EPOCH, *id), |t| (t, *id))
-            })
-            .collect::<Vec<(DateTime<Utc>, i64)>>();
-        epvec.sort();
-
-        let sforder = self
-            .borrow_filtered_order()
-            .clone()
-            .into_iter()
-            .collect::<HashSet<i64>>();
-        let mut norder = Vec::new();
-        let mut new_forder = Vec::new();
-        for (_, i) in epvec {
-            if sforder.contains(&i) {
-                norder.push(i);
-                new_forder.push(i);
+impl LockVec<Podcast> {
+    pub fn sort_by_mode(&self, mode: SortMode) {
+        match mode {
+            SortMode::Unplayed => {
+                self.sort_by_key(|pod| (Reverse(pod.num_unplayed()), pod.title.clone()));
             }
+            SortMode::Updated => {
+                self.sort_by_key(|pod| (Reverse(pod.latest_pubdate()), pod.title.clone()));
+            }
+            _ => self.sort_by_key(|pod| pod.title.clone()),
         }
-        *self.borrow_order() = norder;
-        let mut forder = self.borrow_filtered_order();
-        *forder = new_forder;
     }
+}
 
-    pub fn reverse(&self) {
-        self.borrow_order().reverse();
-        self.borrow_filtered_order().reverse();
+impl LockVec<Episode> {
+    pub fn sort_by_mode(&self, mode: SortMode) {
+        match mode {
+            SortMode::Oldest => self.sort_by_key(|ep| ep.pubdate),
+            SortMode::Title => self.sort_by_key(|ep| ep.title.to_lowercase()),
+            // episodes without a duration go last
+            SortMode::Duration => self.sort_by_key(|ep| (ep.duration.is_none(), ep.duration)),
+            SortMode::PartlyPlayed => {
+                self.sort_by_key(|ep| (!ep.is_partly_played(), Reverse(ep.pubdate)));
+            }
+            _ => self.sort_by_key(|ep| Reverse(ep.pubdate)),
+        }
     }
 }
 
@@ -571,6 +645,66 @@ pub enum FilterType {
     Downloaded,
 }
 
+impl FilterStatus {
+    /// Name used in the database
+    const fn name(self) -> &'static str {
+        match self {
+            Self::PositiveCases => "positive",
+            Self::NegativeCases => "negative",
+            Self::All => "all",
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "positive" => Self::PositiveCases,
+            "negative" => Self::NegativeCases,
+            _ => Self::All,
+        }
+    }
+
+    /// Whether an item with or without the property is shown
+    const fn allows(self, positive: bool) -> bool {
+        match self {
+            Self::PositiveCases => positive,
+            Self::NegativeCases => !positive,
+            Self::All => true,
+        }
+    }
+}
+
+/// Lists that can be filtered and sorted, each keeps its own filters and
+/// order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterPanel {
+    Podcasts,
+    Episodes,
+    Unplayed,
+}
+
+impl FilterPanel {
+    pub const ALL: [Self; 3] = [Self::Podcasts, Self::Episodes, Self::Unplayed];
+
+    /// Key of the params entry where the filters of the list are saved
+    pub const fn param_key(self) -> &'static str {
+        match self {
+            Self::Podcasts => "filters_podcasts",
+            Self::Episodes => "filters_episodes",
+            Self::Unplayed => "filters_unplayed",
+        }
+    }
+
+    /// Key of the params entry where the order of the list is saved, the
+    /// episodes keep theirs with each podcast
+    pub const fn sort_param_key(self) -> Option<&'static str> {
+        match self {
+            Self::Podcasts => Some("sort_podcasts"),
+            Self::Episodes => None,
+            Self::Unplayed => Some("sort_unplayed"),
+        }
+    }
+}
+
 /// Struct holding information about all active filters.
 #[derive(Debug, Clone, Copy)]
 pub struct Filters {
@@ -587,6 +721,178 @@ impl Default for Filters {
     }
 }
 
+impl Filters {
+    /// Moves the filter to its next state, returns a description of it.
+    pub const fn toggle(&mut self, filter_type: FilterType) -> &'static str {
+        // We need to handle these separately because the order that makes
+        // the most sense to me is different: played goes from all -> neg
+        // -> pos. Downloaded goes from all -> pos -> neg; this is purely
+        // based on the idea that people are most likely to want to
+        // specifically find unplayed episodes, or downloaded episodes
+        match filter_type {
+            FilterType::Played => {
+                let (status, message) = match self.played {
+                    FilterStatus::All => (FilterStatus::NegativeCases, "Unplayed only"),
+                    FilterStatus::NegativeCases => (FilterStatus::PositiveCases, "Played only"),
+                    FilterStatus::PositiveCases => (FilterStatus::All, "Played and unplayed"),
+                };
+                self.played = status;
+                message
+            }
+            FilterType::Downloaded => {
+                let (status, message) = match self.downloaded {
+                    FilterStatus::All => (FilterStatus::PositiveCases, "Downloaded only"),
+                    FilterStatus::PositiveCases => {
+                        (FilterStatus::NegativeCases, "Undownloaded only")
+                    }
+                    FilterStatus::NegativeCases => {
+                        (FilterStatus::All, "Downloaded and undownloaded")
+                    }
+                };
+                self.downloaded = status;
+                message
+            }
+        }
+    }
+
+    pub const fn allows(self, played: bool, downloaded: bool) -> bool {
+        self.played.allows(played) && self.downloaded.allows(downloaded)
+    }
+
+    /// Short description of the active filters, None if everything is
+    /// shown
+    pub fn label(self) -> Option<String> {
+        let played = match self.played {
+            FilterStatus::PositiveCases => Some("played"),
+            FilterStatus::NegativeCases => Some("unplayed"),
+            FilterStatus::All => None,
+        };
+        let downloaded = match self.downloaded {
+            FilterStatus::PositiveCases => Some("downloaded"),
+            FilterStatus::NegativeCases => Some("not downloaded"),
+            FilterStatus::All => None,
+        };
+        let active: Vec<&str> = played.into_iter().chain(downloaded).collect();
+        (!active.is_empty()).then(|| active.join(", "))
+    }
+
+    /// Value saved in the database
+    pub fn to_param(self) -> String {
+        format!("{} {}", self.played.name(), self.downloaded.name())
+    }
+
+    pub fn from_param(value: &str) -> Self {
+        let mut names = value.split_whitespace();
+        Self {
+            played: FilterStatus::from_name(names.next().unwrap_or_default()),
+            downloaded: FilterStatus::from_name(names.next().unwrap_or_default()),
+        }
+    }
+}
+
+/// Filters of each list
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PanelFilters {
+    pub podcasts: Filters,
+    pub episodes: Filters,
+    pub unplayed: Filters,
+}
+
+impl PanelFilters {
+    pub const fn get_mut(&mut self, panel: FilterPanel) -> &mut Filters {
+        match panel {
+            FilterPanel::Podcasts => &mut self.podcasts,
+            FilterPanel::Episodes => &mut self.episodes,
+            FilterPanel::Unplayed => &mut self.unplayed,
+        }
+    }
+}
+
+/// Orders of the lists, cycled with the sort key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortMode {
+    #[default]
+    Newest,
+    Oldest,
+    Title,
+    /// Shortest first
+    Duration,
+    /// Episodes started but not finished first
+    PartlyPlayed,
+    /// Podcasts with the most unplayed episodes first
+    Unplayed,
+    /// Podcasts with the newest episodes first
+    Updated,
+}
+
+impl SortMode {
+    const ALL: [Self; 7] = [
+        Self::Newest,
+        Self::Oldest,
+        Self::Title,
+        Self::Duration,
+        Self::PartlyPlayed,
+        Self::Unplayed,
+        Self::Updated,
+    ];
+
+    /// Name used in the database
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Newest => "newest",
+            Self::Oldest => "oldest",
+            Self::Title => "title",
+            Self::Duration => "duration",
+            Self::PartlyPlayed => "partly_played",
+            Self::Unplayed => "unplayed",
+            Self::Updated => "updated",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Newest => "newest first",
+            Self::Oldest => "oldest first",
+            Self::Title => "by title",
+            Self::Duration => "shortest first",
+            Self::PartlyPlayed => "partly played first",
+            Self::Unplayed => "most unplayed first",
+            Self::Updated => "recently updated first",
+        }
+    }
+
+    /// Orders available for a list, the first one is its default
+    pub const fn modes(panel: FilterPanel) -> &'static [Self] {
+        match panel {
+            FilterPanel::Podcasts => &[Self::Title, Self::Unplayed, Self::Updated],
+            FilterPanel::Episodes | FilterPanel::Unplayed => &[
+                Self::Newest,
+                Self::Oldest,
+                Self::Title,
+                Self::Duration,
+                Self::PartlyPlayed,
+            ],
+        }
+    }
+
+    pub const fn default_for(panel: FilterPanel) -> Self {
+        Self::modes(panel)[0]
+    }
+
+    /// The order that follows in the list when cycling through them
+    pub fn next(self, panel: FilterPanel) -> Self {
+        let modes = Self::modes(panel);
+        modes
+            .iter()
+            .position(|&mode| mode == self)
+            .map_or(modes[0], |index| modes[(index + 1) % modes.len()])
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct SyncProgress {
     pub total: usize,
@@ -612,6 +918,12 @@ pub enum Message {
     Feed(FeedMsg),
     Dl(DownloadMsg),
     Gpodder(GpodderMsg),
+    /// Chapters loaded for a podcast episode, None when they could not be
+    /// fetched and are to be looked for again
+    Chapters(i64, i64, Option<Vec<Chapter>>),
+    /// Url of artwork and whether it is cached now
+    Artwork(String, bool),
+    Player(PlayerEvent),
 }
 
 #[cfg(test)]
@@ -635,6 +947,13 @@ mod tests {
             } else {
                 None
             },
+            loudness: None,
+            time_saved: Duration::ZERO,
+            chapters_url: None,
+            image_url: None,
+            chapters: Vec::new(),
+            chapters_checked: false,
+            last_played: None,
             played,
         }
     }
@@ -746,9 +1065,12 @@ mod tests {
             make_episode(10, false, false),
             make_episode(20, false, false),
         ]);
-        assert_eq!(lv.get_index(10), Some(0));
-        assert_eq!(lv.get_index(20), Some(1));
-        assert_eq!(lv.get_index(99), None);
+        assert_eq!(lv.get_index(10, false), Some(0));
+        assert_eq!(lv.get_index(20, false), Some(1));
+        assert_eq!(lv.get_index(99, false), None);
+        *lv.borrow_filtered_order() = vec![20];
+        assert_eq!(lv.get_index(20, true), Some(0));
+        assert_eq!(lv.get_index(10, true), None);
     }
 
     #[test]
@@ -757,9 +1079,12 @@ mod tests {
             make_episode(10, false, false),
             make_episode(20, false, false),
         ]);
-        assert_eq!(lv.get_id_by_index(0), Some(10));
-        assert_eq!(lv.get_id_by_index(1), Some(20));
-        assert_eq!(lv.get_id_by_index(99), None);
+        assert_eq!(lv.get_id_by_index(0, false), Some(10));
+        assert_eq!(lv.get_id_by_index(1, false), Some(20));
+        assert_eq!(lv.get_id_by_index(99, false), None);
+        *lv.borrow_filtered_order() = vec![20];
+        assert_eq!(lv.get_id_by_index(0, true), Some(20));
+        assert_eq!(lv.get_id_by_index(1, true), None);
     }
 
     #[test]
@@ -777,6 +1102,84 @@ mod tests {
         assert!(matches!(f.downloaded, FilterStatus::All));
     }
 
+    #[test]
+    fn filters_toggle_and_label() {
+        let mut f = Filters::default();
+        assert_eq!(f.label(), None);
+        assert_eq!(f.toggle(FilterType::Played), "Unplayed only");
+        assert_eq!(f.toggle(FilterType::Downloaded), "Downloaded only");
+        assert_eq!(f.label().as_deref(), Some("unplayed, downloaded"));
+        assert!(f.allows(false, true));
+        assert!(!f.allows(true, true));
+        assert!(!f.allows(false, false));
+        assert_eq!(f.toggle(FilterType::Played), "Played only");
+        assert_eq!(f.toggle(FilterType::Played), "Played and unplayed");
+        assert!(f.allows(true, true));
+    }
+
+    #[test]
+    fn filters_param_round_trip() {
+        let mut f = Filters::default();
+        f.toggle(FilterType::Played);
+        let restored = Filters::from_param(&f.to_param());
+        assert!(matches!(restored.played, FilterStatus::NegativeCases));
+        assert!(matches!(restored.downloaded, FilterStatus::All));
+        assert!(matches!(Filters::from_param("").played, FilterStatus::All));
+    }
+
+    #[test]
+    fn sort_keeps_filtered_out_episodes() {
+        let lv = LockVec::new(vec![
+            make_episode(1, false, false),
+            make_episode(2, true, false),
+        ]);
+        *lv.borrow_filtered_order() = vec![1];
+        lv.sort_by_mode(SortMode::Oldest);
+        assert_eq!(lv.len(false), 2);
+        assert_eq!(lv.len(true), 1);
+    }
+
+    #[test]
+    fn sort_modes_cycle_per_list() {
+        for mode in SortMode::ALL {
+            assert_eq!(SortMode::from_name(mode.name()), Some(mode));
+        }
+        assert_eq!(SortMode::from_name("random"), None);
+        let panel = FilterPanel::Podcasts;
+        assert_eq!(SortMode::default_for(panel), SortMode::Title);
+        assert_eq!(SortMode::Updated.next(panel), SortMode::Title);
+        // an order of another list starts over
+        assert_eq!(SortMode::Oldest.next(panel), SortMode::Title);
+        let panel = FilterPanel::Episodes;
+        assert_eq!(SortMode::default_for(panel), SortMode::Newest);
+        assert_eq!(SortMode::Newest.next(panel), SortMode::Oldest);
+    }
+
+    #[test]
+    fn episodes_sort_by_mode() {
+        let mut episodes: Vec<Episode> = (1..=3).map(|id| make_episode(id, false, false)).collect();
+        for (ep, (date, duration)) in episodes.iter_mut().zip([(300, 20), (100, 30), (200, 10)]) {
+            ep.pubdate = DateTime::from_timestamp(date, 0);
+            ep.duration = Some(duration);
+        }
+        episodes[0].title = "b".to_string();
+        episodes[1].title = "C".to_string();
+        episodes[2].title = "a".to_string();
+        episodes[1].position = 5;
+        let lv = LockVec::new(episodes);
+        *lv.borrow_filtered_order() = vec![1, 2];
+        let order = |mode| {
+            lv.sort_by_mode(mode);
+            lv.borrow_order().clone()
+        };
+        assert_eq!(order(SortMode::Newest), [1, 3, 2]);
+        assert_eq!(order(SortMode::Oldest), [2, 3, 1]);
+        assert_eq!(order(SortMode::Title), [3, 1, 2]);
+        assert_eq!(order(SortMode::Duration), [3, 1, 2]);
+        assert_eq!(order(SortMode::PartlyPlayed), [2, 1, 3]);
+        assert_eq!(*lv.borrow_filtered_order(), [2, 1]);
+    }
+
     #[test]
     fn episode_ordering_by_pubdate() {
         let early = DateTime::from_timestamp(1000, 0).unwrap();
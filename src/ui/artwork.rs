@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use image::{Rgb, RgbImage, imageops::FilterType};
+use ratatui::{Frame, buffer::CellDiffOption, crossterm::terminal, prelude::Rect, style::Color};
+
+use crate::{
+    artwork::{self, ArtworkMode},
+    types::ShareableRwLock,
+};
+
+use super::UiState;
+
+/// Pixels of a cell when the terminal does not tell them
+const DEFAULT_CELL: (u16, u16) = (10, 20);
+
+/// Most rows the artwork takes in the details
+pub(super) const DETAILS_ART_ROWS: u16 = 12;
+
+/// Largest chunk of base64 data in one kitty graphics command
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Debug)]
+enum ArtState {
+    /// Being fetched by the main controller
+    Loading,
+    Ready(PathBuf, RgbImage),
+    Missing,
+}
+
+/// Artwork drawn over a part of the screen
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Placement {
+    url: String,
+    area: Rect,
+}
+
+/// Podcast artwork loaded from the cache, and what is needed to draw it
+/// with the graphics protocol of the terminal
+#[derive(Debug)]
+pub(super) struct Artwork {
+    mode: ArtworkMode,
+    images: HashMap<String, ArtState>,
+    /// Urls to ask the main controller for
+    requests: Vec<String>,
+    /// Art drawn in this frame
+    wanted: Vec<Placement>,
+    /// Art the terminal shows since the last frame
+    shown: Vec<Placement>,
+    /// The wanted art differs from the one shown
+    dirty: bool,
+    /// Size of the screen the art was shown on, a resize clears it
+    screen: Rect,
+    /// Width and height of a cell, in pixels
+    cell: (u16, u16),
+    /// Half blocks scaled to fit each area, kept while they are drawn
+    scaled: HashMap<Placement, RgbImage>,
+    /// Cached file of the current episode's art, for the media controls
+    current_art: ShareableRwLock<Option<PathBuf>>,
+}
+
+impl Artwork {
+    pub(super) fn new(mode: ArtworkMode, current_art: ShareableRwLock<Option<PathBuf>>) -> Self {
+        Self {
+            mode: mode.resolve(),
+            images: HashMap::new(),
+            requests: Vec::new(),
+            wanted: Vec::new(),
+            shown: Vec::new(),
+            dirty: false,
+            screen: Rect::default(),
+            cell: cell_size(),
+            scaled: HashMap::new(),
+            current_art,
+        }
+    }
+
+    /// Whether the terminal draws the art on top of the text, so it has
+    /// to be hidden under popups
+    pub(super) const fn is_overlay(&self) -> bool {
+        matches!(
+            self.mode,
+            ArtworkMode::Kitty | ArtworkMode::Sixel | ArtworkMode::Iterm
+        )
+    }
+
+    /// Artwork at a url, loaded from the cache or else requested
+    fn state(&mut self, url: &str) -> &ArtState {
+        self.images.entry(url.to_string()).or_insert_with(|| {
+            let Some(path) = artwork::cached_path(url).filter(|path| path.exists()) else {
+                self.requests.push(url.to_string());
+                return ArtState::Loading;
+            };
+            match artwork::load(&path) {
+                Ok(image) => ArtState::Ready(path, image),
+                Err(err) => {
+                    log::warn!("Could not open artwork {}: {err}", path.display());
+                    ArtState::Missing
+                }
+            }
+        })
+    }
+
+    /// Columns and rows the artwork at a url takes, at most `rows` high
+    /// and `max_cols` wide. None until it is loaded.
+    pub(super) fn fit(&mut self, url: &str, rows: u16, max_cols: u16) -> Option<(u16, u16)> {
+        if self.mode == ArtworkMode::Off {
+            return None;
+        }
+        let cell = self.cell;
+        match self.state(url) {
+            ArtState::Ready(_, image) => fit(image.dimensions(), cell, rows, max_cols),
+            ArtState::Loading | ArtState::Missing => None,
+        }
+    }
+
+    /// Draws the artwork at a url, already loaded, over an area fitted to
+    /// it.
+    pub(super) fn render(&mut self, frame: &mut Frame, area: Rect, url: &str) {
+        let placement = Placement {
+            url: url.to_string(),
+            area,
+        };
+        if self.mode == ArtworkMode::HalfBlocks {
+            let Some(ArtState::Ready(_, image)) = self.images.get(url) else {
+                return;
+            };
+            let scaled = self.scaled.entry(placement.clone()).or_insert_with(|| {
+                image::imageops::resize(
+                    image,
+                    u32::from(area.width),
+                    u32::from(area.height) * 2,
+                    FilterType::Triangle,
+                )
+            });
+            draw_half_blocks(frame, area, scaled);
+        } else {
+            // the terminal draws these cells, ratatui leaves them alone
+            for y in area.top()..area.bottom() {
+                for x in area.left()..area.right() {
+                    if let Some(cell) = frame.buffer_mut().cell_mut((x, y)) {
+                        cell.reset();
+                        cell.set_diff_option(CellDiffOption::Skip);
+                    }
+                }
+            }
+        }
+        self.wanted.push(placement);
+    }
+
+    /// Ends drawing a frame. Text is drawn again where art was removed,
+    /// since ratatui does not know the terminal drew over it.
+    pub(super) fn finish(&mut self, frame: &mut Frame) {
+        let screen = frame.area();
+        self.dirty = self.is_overlay() && (self.wanted != self.shown || self.screen != screen);
+        if !self.dirty {
+            return;
+        }
+        if self.screen != screen {
+            self.screen = screen;
+            self.cell = cell_size();
+        }
+        for old in &self.shown {
+            for y in old.area.top()..old.area.bottom() {
+                for x in old.area.left()..old.area.right() {
+                    if self
+                        .wanted
+                        .iter()
+                        .any(|new| new.area.contains((x, y).into()))
+                    {
+                        continue;
+                    }
+                    if let Some(cell) = frame.buffer_mut().cell_mut((x, y)) {
+                        cell.set_diff_option(CellDiffOption::AlwaysUpdate);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes the art of the frame just drawn to the terminal, if it
+    /// changed.
+    pub(super) fn flush(&mut self) -> io::Result<()> {
+        let wanted = std::mem::take(&mut self.wanted);
+        self.scaled
+            .retain(|placement, _| wanted.contains(placement));
+        if !self.dirty {
+            self.shown = wanted;
+            return Ok(());
+        }
+        let mut out = String::from("\x1b7");
+        if self.mode == ArtworkMode::Kitty {
+            // deletes every image, so the ones kept are placed again
+            out.push_str("\x1b_Ga=d,d=A,q=2\x1b\\");
+        }
+        for placement in &wanted {
+            let Some(ArtState::Ready(path, image)) = self.images.get(&placement.url) else {
+                continue;
+            };
+            let area = placement.area;
+            let _ = write!(out, "\x1b[{};{}H", area.y + 1, area.x + 1);
+            match self.mode {
+                ArtworkMode::Kitty => kitty(&mut out, &std::fs::read(path)?, area),
+                ArtworkMode::Iterm => iterm(&mut out, &std::fs::read(path)?, area),
+                ArtworkMode::Sixel => {
+                    let scaled = image::imageops::resize(
+                        image,
+                        u32::from(area.width) * u32::from(self.cell.0),
+                        u32::from(area.height) * u32::from(self.cell.1),
+                        FilterType::Triangle,
+                    );
+                    out.push_str(&sixel(&scaled));
+                }
+                ArtworkMode::Auto | ArtworkMode::HalfBlocks | ArtworkMode::Off => {}
+            }
+        }
+        out.push_str("\x1b8");
+        self.shown = wanted;
+        let mut stdout = io::stdout();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()
+    }
+
+    /// Urls of the artwork to fetch, since the last time they were asked
+    /// for
+    pub(super) fn take_requests(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.requests)
+    }
+
+    /// Loads artwork once it is cached, or stops waiting for it.
+    pub(super) fn fetched(&mut self, url: &str, fetched: bool) {
+        if fetched {
+            self.images.remove(url);
+        } else {
+            self.images.insert(url.to_string(), ArtState::Missing);
+        }
+    }
+
+    /// Shares the cached file of the current episode's art, once it is
+    /// loaded.
+    fn share_current(&mut self, url: Option<&str>) {
+        let path = url.and_then(|url| match self.state(url) {
+            ArtState::Ready(path, _) => Some(path.clone()),
+            ArtState::Loading | ArtState::Missing => None,
+        });
+        *self
+            .current_art
+            .write()
+            .expect("RwLock write should not fail") = path;
+    }
+}
+
+impl UiState {
+    /// Url of the artwork of the episode playing, or else of its
+    /// podcast. The media controls get it too, once it is cached.
+    pub(super) fn current_art_url(&mut self) -> Option<String> {
+        let (pod_id, image_url) = self
+            .current_episode
+            .read()
+            .expect("RwLock read should not fail")
+            .as_ref()
+            .map(|ep| {
+                let ep = ep.read().expect("RwLock read should not fail");
+                (ep.pod_id, ep.image_url.clone())
+            })?;
+        let url = image_url.or_else(|| {
+            self.podcasts
+                .items
+                .map_single(pod_id, |pod| pod.image_url.clone())
+                .flatten()
+        });
+        self.artwork.share_current(url.as_deref());
+        url
+    }
+}
+
+/// Pixels of a cell, from the size of the terminal window
+fn cell_size() -> (u16, u16) {
+    terminal::window_size()
+        .ok()
+        .filter(|size| size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0)
+        .map_or(DEFAULT_CELL, |size| {
+            (size.width / size.columns, size.height / size.rows)
+        })
+}
+
+/// Columns and rows taken by an image of `size` pixels, as large as fits
+/// `rows` and `max_cols` while keeping its aspect
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::suboptimal_flops
+)]
+fn fit(size: (u32, u32), cell: (u16, u16), rows: u16, max_cols: u16) -> Option<(u16, u16)> {
+    if size.1 == 0 || cell.0 == 0 {
+        return None;
+    }
+    // columns per row, with the shape of the cells
+    let ratio = f64::from(size.0) / f64::from(size.1) * f64::from(cell.1) / f64::from(cell.0);
+    let mut cols = (f64::from(rows) * ratio).round();
+    let rows = if cols > f64::from(max_cols) {
+        cols = f64::from(max_cols);
+        (cols / ratio).round()
+    } else {
+        f64::from(rows)
+    };
+    let (cols, rows) = (cols as u16, rows as u16);
+    (cols > 0 && rows > 0).then_some((cols, rows))
+}
+
+/// Draws an image scaled to twice the rows of the area, with the upper
+/// half block in the color of the top pixel over the color of the
+/// bottom one.
+fn draw_half_blocks(frame: &mut Frame, area: Rect, image: &RgbImage) {
+    let color = |x: u16, y: u32| {
+        let Rgb([r, g, b]) = *image.get_pixel(u32::from(x), y);
+        Color::Rgb(r, g, b)
+    };
+    for row in 0..area.height {
+        for col in 0..area.width {
+            if let Some(cell) = frame.buffer_mut().cell_mut((area.x + col, area.y + row)) {
+                cell.set_symbol("▀")
+                    .set_fg(color(col, u32::from(row) * 2))
+                    .set_bg(color(col, u32::from(row) * 2 + 1));
+            }
+        }
+    }
+}
+
+/// Kitty graphics command showing a png over an area, sent in chunks
+fn kitty(out: &mut String, png: &[u8], area: Rect) {
+    let data = STANDARD.encode(png);
+    let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Gf=100,a=T,q=2,C=1,c={},r={},m={more};",
+                area.width, area.height
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={more};");
+        }
+        out.push_str(&String::from_utf8_lossy(chunk));
+        out.push_str("\x1b\\");
+    }
+}
+
+/// iTerm inline image showing a file over an area
+fn iterm(out: &mut String, file: &[u8], area: Rect) {
+    let _ = write!(
+        out,
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+        file.len(),
+        area.width,
+        area.height,
+        STANDARD.encode(file)
+    );
+}
+
+/// Index of a color in a 6x6x6 cube
+fn cube_index(Rgb([r, g, b]): Rgb<u8>) -> usize {
+    let level = |value: u8| (usize::from(value) * 5 + 127) / 255;
+    level(r) * 36 + level(g) * 6 + level(b)
+}
+
+/// Appends a run of the same sixel, repeated if it is long enough
+fn push_run(out: &mut String, sixel: char, count: usize) {
+    if count > 3 {
+        let _ = write!(out, "!{count}{sixel}");
+    } else {
+        out.extend(std::iter::repeat_n(sixel, count));
+    }
+}
+
+/// Encodes an image as sixels, with its colors reduced to a 6x6x6 cube
+fn sixel(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    let indices: Vec<usize> = image.pixels().map(|pixel| cube_index(*pixel)).collect();
+    let mut used = [false; 216];
+    for &index in &indices {
+        used[index] = true;
+    }
+
+    let mut out = format!("\x1bPq\"1;1;{width};{height}");
+    for (index, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+        let _ = write!(
+            out,
+            "#{index};2;{};{};{}",
+            index / 36 * 20,
+            index / 6 % 6 * 20,
+            index % 6 * 20
+        );
+    }
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut colors: Vec<usize> = (band..band + rows)
+            .flat_map(|y| &indices[y * width..(y + 1) * width])
+            .copied()
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+        for (n, &color) in colors.iter().enumerate() {
+            if n > 0 {
+                out.push('$');
+            }
+            let _ = write!(out, "#{color}");
+            let mut run = ('?', 0);
+            for x in 0..width {
+                let bits = (0..rows)
+                    .filter(|dy| indices[(band + dy) * width + x] == color)
+                    .fold(0, |bits, dy| bits | 1 << dy);
+                let sixel = char::from(63 + bits);
+                if sixel == run.0 {
+                    run.1 += 1;
+                } else {
+                    push_run(&mut out, run.0, run.1);
+                    run = (sixel, 1);
+                }
+            }
+            // nothing left to draw in an empty run at the end
+            if run.0 != '?' {
+                push_run(&mut out, run.0, run.1);
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_within_bounds() {
+        assert_eq!(fit((600, 600), (10, 20), 4, 20), Some((8, 4)));
+        assert_eq!(fit((600, 600), (10, 20), 4, 4), Some((4, 2)));
+        assert_eq!(fit((1200, 600), (10, 10), 6, 100), Some((12, 6)));
+        assert_eq!(fit((600, 600), (10, 20), 4, 0), None);
+        assert_eq!(fit((600, 0), (10, 20), 4, 20), None);
+    }
+
+    #[test]
+    fn sixel_runs() {
+        let red = RgbImage::from_pixel(2, 6, Rgb([255, 0, 0]));
+        assert_eq!(sixel(&red), "\x1bPq\"1;1;2;6#180;2;100;0;0#180~~-\x1b\\");
+
+        let mut image = RgbImage::from_pixel(5, 1, Rgb([0, 0, 0]));
+        image.put_pixel(4, 0, Rgb([255, 255, 255]));
+        assert_eq!(
+            sixel(&image),
+            "\x1bPq\"1;1;5;1#0;2;0;0;0#215;2;100;100;100#0!4@$#215!4?@-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn kitty_chunks() {
+        let mut out = String::new();
+        kitty(&mut out, &[0; 4000], Rect::new(0, 0, 8, 4));
+        assert!(out.starts_with("\x1b_Gf=100,a=T,q=2,C=1,c=8,r=4,m=1;"));
+        assert_eq!(out.matches("\x1b_G").count(), 2);
+        assert!(out.contains("\x1b_Gm=0;"));
+    }
+}
@@ -1,20 +1,19 @@
 use std::time::Duration;
 
-use ratatui::{
-    crossterm::event::{self, Event, KeyCode},
-    widgets::ListState,
-};
+use ratatui::crossterm::event::{self, Event, KeyCode};
 use tui_input::backend::crossterm::EventHandler;
 
 use crate::{
-    config::{SEEK_LENGTH, TICK_RATE},
+    config::{MESSAGE_TIME, TICK_RATE},
+    downloads::Download,
     keymap::UserAction,
     media_control::ControlMessage,
     player::PlayerMessage,
     types::FilterType,
+    utils::parse_timestamp,
 };
 
-use super::{Panel, Popup, UiMsg, UiState};
+use super::{Panel, Popup, UiMsg, UiState, search::Search, sleep_timer::SLEEP_CHOICES};
 
 impl UiState {
     /// Waits for user input and, where necessary, provides `UiMsgs` back to the
@@ -27,9 +26,14 @@ impl UiState {
     /// data back to the main controller.
     #[allow(clippy::too_many_lines)]
     pub(super) fn getch(&mut self) -> Vec<UiMsg> {
-        if event::poll(Duration::from_millis(TICK_RATE)).expect("Can't poll for inputs")
-            && let Event::Key(input) = event::read().expect("Can't read inputs")
-        {
+        if !event::poll(Duration::from_millis(TICK_RATE)).expect("Can't poll for inputs") {
+            return vec![UiMsg::Noop];
+        }
+        let event = event::read().expect("Can't read inputs");
+        if let Event::Mouse(mouse) = event {
+            return self.handle_mouse(mouse);
+        }
+        if let Event::Key(input) = event {
             let action = self.keymap.get_from_input(input).copied();
             if let Some(popup) = self.active_popup.clone() {
                 if action == Some(UserAction::Back) {
@@ -50,6 +54,21 @@ impl UiState {
                             Some(UserAction::Help) => {
                                 self.active_popup = Some(Popup::Help);
                             }
+                            Some(a @ (UserAction::OpenLink | UserAction::CopyLink))
+                                if matches!(popup, Popup::Details) =>
+                            {
+                                self.choose_link(a == UserAction::CopyLink);
+                            }
+                            Some(
+                                a @ (UserAction::IntroShorter
+                                | UserAction::IntroLonger
+                                | UserAction::OutroShorter
+                                | UserAction::OutroLonger),
+                            ) if matches!(popup, Popup::Details) => {
+                                if let Some(ui_msg) = self.change_skips(a) {
+                                    return vec![ui_msg];
+                                }
+                            }
                             _ => {}
                         },
                         Popup::AddPodcast => match input.code {
@@ -61,6 +80,32 @@ impl UiState {
                                 self.input.handle_event(&Event::Key(input));
                             }
                         },
+                        Popup::GoToTimestamp => match input.code {
+                            KeyCode::Enter => {
+                                self.active_popup = None;
+                                match parse_timestamp(self.input.value()) {
+                                    Some(seconds) => self.seek_to(Duration::from_secs(seconds)),
+                                    None => self.notification.timed_notif(
+                                        format!("Invalid timestamp: {}", self.input.value()),
+                                        MESSAGE_TIME,
+                                        true,
+                                    ),
+                                }
+                            }
+                            _ => {
+                                self.input.handle_event(&Event::Key(input));
+                            }
+                        },
+                        Popup::ChooseLink(copy) => match input.code {
+                            KeyCode::Enter => {
+                                if let Some(msg) = self.follow_link(copy) {
+                                    return vec![msg];
+                                }
+                            }
+                            _ => {
+                                self.input.handle_event(&Event::Key(input));
+                            }
+                        },
                         Popup::ConfirmRemovePodcast => match input.code {
                             KeyCode::Char('y') => {
                                 self.active_popup = None;
@@ -73,6 +118,68 @@ impl UiState {
                             }
                             _ => {}
                         },
+                        Popup::SleepTimer => match action {
+                            Some(UserAction::Down) => self.sleep_menu.select_next(),
+                            Some(UserAction::Up) => self.sleep_menu.select_previous(),
+                            Some(UserAction::GoTop) => self.sleep_menu.select_first(),
+                            Some(UserAction::GoBot) => self.sleep_menu.select_last(),
+                            Some(UserAction::Enter) => {
+                                self.active_popup = None;
+                                if let Some(&choice) = self
+                                    .sleep_menu
+                                    .selected()
+                                    .and_then(|index| SLEEP_CHOICES.get(index))
+                                {
+                                    self.set_sleep_timer(choice);
+                                }
+                            }
+                            _ => {}
+                        },
+                        Popup::OutputDevice => match action {
+                            Some(UserAction::Down) => self.device_menu.select_next(),
+                            Some(UserAction::Up) => self.device_menu.select_previous(),
+                            Some(UserAction::GoTop) => self.device_menu.select_first(),
+                            Some(UserAction::GoBot) => self.device_menu.select_last(),
+                            Some(UserAction::Enter) => {
+                                self.active_popup = None;
+                                if let Some(index) = self.device_menu.selected() {
+                                    self.set_output_device(index);
+                                }
+                            }
+                            _ => {}
+                        },
+                        Popup::ConfirmExternal(pod_id, ep_id) => match input.code {
+                            KeyCode::Char('y') => {
+                                self.active_popup = None;
+                                return vec![UiMsg::Play(pod_id, ep_id, true)];
+                            }
+                            KeyCode::Char('n') => {
+                                self.active_popup = None;
+                            }
+                            _ => {}
+                        },
+                        Popup::ConfirmResume(pod_id, ep_id, _, position) => match input.code {
+                            KeyCode::Char('y') => {
+                                self.active_popup = None;
+                                return vec![UiMsg::Resume(pod_id, ep_id, position)];
+                            }
+                            KeyCode::Char('n') => {
+                                self.active_popup = None;
+                            }
+                            _ => {}
+                        },
+                        Popup::Search => match input.code {
+                            KeyCode::Down => self.search.state.select_next(),
+                            KeyCode::Up => self.search.state.select_previous(),
+                            KeyCode::Enter => {
+                                self.active_popup = None;
+                                self.jump_to_match();
+                            }
+                            _ => {
+                                self.input.handle_event(&Event::Key(input));
+                                self.update_search();
+                            }
+                        },
                         Popup::ConfirmQuit => match input.code {
                             KeyCode::Char('y') => {
                                 self.active_popup = None;
@@ -86,6 +193,11 @@ impl UiState {
                     }
                 }
             } else {
+                if let Some(a) = action
+                    && let Some(msgs) = self.apply_to_selection(a)
+                {
+                    return msgs;
+                }
                 match action {
                     Some(
                         a @ (UserAction::Down
@@ -98,22 +210,55 @@ impl UiState {
                         self.move_cursor(a);
                     }
 
-                    Some(UserAction::Left) => {
-                        self.tx_to_player
-                            .blocking_send(PlayerMessage::Seek(SEEK_LENGTH, false))
-                            .inspect_err(|err| {
-                                log::error!("Failed to send PlayerMessage::Seek to player: {err}");
-                            })
-                            .ok();
+                    Some(UserAction::Left) => self.seek(self.seek_steps.short_back, false),
+                    Some(UserAction::Right) => self.seek(self.seek_steps.short_forward, true),
+                    Some(UserAction::SeekBackLong) => {
+                        self.seek(self.seek_steps.long_back, false);
+                    }
+                    Some(UserAction::SeekForwardLong) => {
+                        self.seek(self.seek_steps.long_forward, true);
                     }
 
-                    Some(UserAction::Right) => {
-                        self.tx_to_player
-                            .blocking_send(PlayerMessage::Seek(SEEK_LENGTH, true))
-                            .inspect_err(|err| {
-                                log::error!("Failed to send PlayerMessage::Seek to player: {err}");
-                            })
-                            .ok();
+                    Some(UserAction::GoToTimestamp) => {
+                        self.input.reset();
+                        self.active_popup = Some(Popup::GoToTimestamp);
+                    }
+
+                    Some(UserAction::JumpTo(tenth)) => self.jump_to(tenth),
+                    Some(UserAction::NextChapter) => self.skip_chapter(true),
+                    Some(UserAction::PreviousChapter) => self.skip_chapter(false),
+
+                    Some(a @ (UserAction::SpeedUp | UserAction::SpeedDown)) => {
+                        if let Some(ui_msg) = self.change_speed(a == UserAction::SpeedUp) {
+                            return vec![ui_msg];
+                        }
+                    }
+
+                    Some(a @ (UserAction::VolumeUp | UserAction::VolumeDown)) => {
+                        if let Some(ui_msg) = self.change_volume(a == UserAction::VolumeUp) {
+                            return vec![ui_msg];
+                        }
+                    }
+
+                    Some(UserAction::Mute) => self.toggle_mute(),
+
+                    Some(UserAction::Mono) => self.toggle_mono(),
+
+                    Some(a @ (UserAction::BalanceLeft | UserAction::BalanceRight)) => {
+                        self.change_balance(a == UserAction::BalanceRight);
+                    }
+
+                    Some(UserAction::SleepTimer) => {
+                        self.sleep_menu.select_first();
+                        self.active_popup = Some(Popup::SleepTimer);
+                    }
+
+                    Some(UserAction::OutputDevice) => self.open_device_menu(),
+
+                    Some(UserAction::Equalizer) => {
+                        if let Some(ui_msg) = self.change_equalizer() {
+                            return vec![ui_msg];
+                        }
                     }
 
                     Some(UserAction::ResetPlayer) => {
@@ -135,6 +280,14 @@ impl UiState {
                         }
                     }
 
+                    Some(UserAction::Search) => {
+                        self.input.reset();
+                        self.search = Search::default();
+                        self.active_popup = Some(Popup::Search);
+                    }
+                    Some(UserAction::NextMatch) => self.jump_to_next_match(true),
+                    Some(UserAction::PreviousMatch) => self.jump_to_next_match(false),
+
                     Some(UserAction::AddFeed) => {
                         self.input.reset();
                         self.active_popup = Some(Popup::AddPodcast);
@@ -158,22 +311,13 @@ impl UiState {
                     Some(UserAction::Enter) => match self.active_panel {
                         Panel::Podcasts => {
                             if let Some(pod_id) = self.get_podcast_id() {
-                                self.select_panel(&Panel::Episodes);
-
-                                if let Some(items) = self
-                                    .podcasts
-                                    .items
-                                    .map_single(pod_id, |x| x.episodes.clone())
-                                {
-                                    self.episodes.items = items;
-                                    self.episodes.state =
-                                        ListState::default().with_selected(Some(0));
-                                }
+                                self.open_podcast(pod_id);
                             }
                         }
                         Panel::Queue | Panel::Episodes | Panel::Unplayed => {
                             return self.play_selected_episode();
                         }
+                        Panel::Downloads => {}
                     },
 
                     Some(UserAction::PlayExternal) => match self.active_panel {
@@ -185,7 +329,7 @@ impl UiState {
                                 return vec![UiMsg::Play(pod_id, ep_id, true)];
                             }
                         }
-                        Panel::Podcasts => {}
+                        Panel::Podcasts | Panel::Downloads => {}
                     },
 
                     Some(UserAction::Enqueue) => match self.active_panel {
@@ -206,8 +350,10 @@ impl UiState {
                                 }
                             }
                         }
-                        Panel::Queue | Panel::Podcasts => {}
+                        Panel::Queue | Panel::Podcasts | Panel::Downloads => {}
                     },
+                    Some(UserAction::Select) => self.toggle_selected(),
+                    Some(UserAction::SelectRange) => self.select_range(),
                     Some(UserAction::PlayPause) => {
                         if let Some(msg) = self.play_pause() {
                             return vec![msg];
@@ -219,7 +365,7 @@ impl UiState {
                                 return vec![ui_msg];
                             }
                         }
-                        Panel::Podcasts => {}
+                        Panel::Podcasts | Panel::Downloads => {}
                     },
                     Some(UserAction::MarkAllPlayed) => {
                         if self.active_panel == Panel::Episodes
@@ -229,8 +375,9 @@ impl UiState {
                         }
                     }
 
+                    // in the downloads panel, retries a failed download
                     Some(UserAction::Download) => match self.active_panel {
-                        Panel::Episodes | Panel::Unplayed | Panel::Queue => {
+                        Panel::Episodes | Panel::Unplayed | Panel::Queue | Panel::Downloads => {
                             if let Some(pod_id) = self.get_podcast_id()
                                 && let Some(ep_id) = self.get_episode_id()
                             {
@@ -255,7 +402,7 @@ impl UiState {
                                 return vec![UiMsg::Delete(pod_id, ep_id)];
                             }
                         }
-                        Panel::Podcasts => {}
+                        Panel::Podcasts | Panel::Downloads => {}
                     },
                     Some(UserAction::DeleteAll) => {
                         if self.active_panel == Panel::Podcasts
@@ -275,14 +422,37 @@ impl UiState {
                                 return vec![UiMsg::QueueModified];
                             }
                         }
+                        Panel::Downloads => {
+                            // cancels a running download, forgets a finished one
+                            if let Some(ep_id) = self.get_episode_id() {
+                                match self.downloads.items.map_single(ep_id, Download::is_pending) {
+                                    Some(true) => return vec![UiMsg::CancelDownload(ep_id)],
+                                    Some(false) => self.downloads.items.remove(ep_id),
+                                    None => {}
+                                }
+                            }
+                        }
                         _ => {}
                     },
 
-                    Some(UserAction::FilterPlayed) => {
-                        return vec![UiMsg::FilterChange(FilterType::Played)];
+                    Some(a @ (UserAction::FilterPlayed | UserAction::FilterDownloaded)) => {
+                        let filter_type = if a == UserAction::FilterPlayed {
+                            FilterType::Played
+                        } else {
+                            FilterType::Downloaded
+                        };
+                        if let Some(panel) = self.filter_panel() {
+                            return vec![UiMsg::FilterChange(panel, filter_type)];
+                        }
                     }
-                    Some(UserAction::FilterDownloaded) => {
-                        return vec![UiMsg::FilterChange(FilterType::Downloaded)];
+
+                    Some(UserAction::Sort) => {
+                        if let Some(panel) = self.filter_panel() {
+                            // the episodes shown are those of the selected
+                            // podcast
+                            let pod_id = self.podcasts.selected_item_id;
+                            return vec![UiMsg::SortChange(panel, pod_id)];
+                        }
                     }
 
                     Some(UserAction::Help) => {
@@ -312,31 +482,49 @@ impl UiState {
                             }
                         }
                     }
-                    Some(UserAction::Information) => {
-                        match self.active_panel {
-                            Panel::Episodes | Panel::Queue | Panel::Unplayed => {
-                                self.construct_details_episode();
-                            }
-                            Panel::Podcasts => {
-                                self.construct_details_podcast();
+                    Some(UserAction::DownloadsList) => {
+                        if self.active_popup.is_none() {
+                            if self.active_panel == Panel::Downloads {
+                                self.select_panel(&Panel::Podcasts);
+                            } else {
+                                self.select_panel(&Panel::Downloads);
                             }
                         }
-                        self.active_popup = Some(Popup::Details);
                     }
+                    Some(UserAction::Information) => match self.active_panel {
+                        Panel::Episodes | Panel::Queue | Panel::Unplayed => {
+                            self.construct_details_episode();
+                            self.active_popup = Some(Popup::Details);
+                        }
+                        Panel::Podcasts => {
+                            self.construct_details_podcast();
+                            self.active_popup = Some(Popup::Details);
+                        }
+                        Panel::Downloads => {}
+                    },
                     Some(UserAction::Back) => {
-                        if self.active_panel == Panel::Episodes {
+                        if matches!(self.active_panel, Panel::Episodes | Panel::Downloads) {
                             self.select_panel(&Panel::Podcasts);
                         }
                     }
                     Some(UserAction::Switch) => match self.active_panel {
-                        Panel::Episodes | Panel::Podcasts | Panel::Unplayed => {
+                        Panel::Episodes | Panel::Podcasts | Panel::Unplayed | Panel::Downloads => {
                             self.select_panel(&Panel::Queue);
                         }
                         Panel::Queue => {
                             self.select_panel(&self.left_panel.clone());
                         }
                     },
-                    None => (),
+                    // links and skips are only changed in the details popup
+                    Some(
+                        UserAction::OpenLink
+                        | UserAction::CopyLink
+                        | UserAction::IntroShorter
+                        | UserAction::IntroLonger
+                        | UserAction::OutroShorter
+                        | UserAction::OutroLonger,
+                    )
+                    | None => (),
                 }
             }
         }
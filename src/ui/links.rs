@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::{config::MESSAGE_TIME, show_notes::Link, utils::format_duration};
+
+use super::{Popup, UiMsg, UiState};
+
+/// Puts text on the clipboard through the terminal, with the OSC 52
+/// escape sequence, which also works over ssh
+fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush()
+}
+
+impl UiState {
+    /// Asks which link of the details to open or copy, if there are any
+    pub(super) fn choose_link(&mut self, copy: bool) {
+        let has_links = self
+            .current_details
+            .as_ref()
+            .and_then(|details| details.notes.as_ref())
+            .is_some_and(|notes| !notes.links.is_empty());
+        if has_links {
+            self.input.reset();
+            self.active_popup = Some(Popup::ChooseLink(copy));
+        } else {
+            self.notification
+                .timed_notif("No links in the notes".to_string(), MESSAGE_TIME, false);
+        }
+    }
+
+    /// Opens or copies the link with the number typed, then goes back to
+    /// the details. Timestamps seek when their episode is playing.
+    pub(super) fn follow_link(&mut self, copy: bool) -> Option<UiMsg> {
+        self.active_popup = Some(Popup::Details);
+        let details = self.current_details.as_ref()?;
+        let value = self.input.value().trim().trim_matches(['[', ']']);
+        let Some(link) = value
+            .parse()
+            .ok()
+            .and_then(|number| details.notes.as_ref()?.link(number).cloned())
+        else {
+            self.notification
+                .timed_notif(format!("No link numbered {value}"), MESSAGE_TIME, true);
+            return None;
+        };
+
+        match (link, copy) {
+            (Link::Url(url), true) => self.copy(&url),
+            (Link::Url(url), false) => return Some(UiMsg::OpenLink(url)),
+            (Link::Timestamp(seconds), true) => self.copy(&format_duration(Some(seconds))),
+            (Link::Timestamp(seconds), false) => {
+                let playing = self
+                    .current_episode
+                    .read()
+                    .expect("RwLock read should not fail")
+                    .as_ref()
+                    .map(|ep| ep.read().expect("RwLock read should not fail").id);
+                if playing.is_some() && playing == details.episode_id {
+                    self.seek_to(Duration::from_secs(seconds));
+                    self.active_popup = None;
+                } else {
+                    self.notification.timed_notif(
+                        "Play the episode to jump to its timestamps".to_string(),
+                        MESSAGE_TIME,
+                        false,
+                    );
+                }
+            }
+        }
+        None
+    }
+
+    fn copy(&mut self, text: &str) {
+        let (msg, error) = match copy_to_clipboard(text) {
+            Ok(()) => (format!("Copied {text}"), false),
+            Err(err) => (format!("Could not copy: {err}"), true),
+        };
+        self.notification.timed_notif(msg, MESSAGE_TIME, error);
+    }
+}
@@ -1,28 +1,55 @@
-use std::sync::{Arc, RwLock, mpsc};
+use std::{
+    collections::HashSet,
+    io,
+    path::PathBuf,
+    sync::{Arc, RwLock, mpsc},
+    time::Duration,
+};
 
 use chrono::{DateTime, Utc};
-use ratatui::widgets::ListState;
+use ratatui::{
+    crossterm::{
+        event::{DisableMouseCapture, EnableMouseCapture},
+        execute,
+    },
+    widgets::ListState,
+};
 use tui_input::Input;
 
 use crate::{
     app::MainMessage,
-    config::Config,
+    config::{Config, MAX_VOLUME, SeekSteps},
+    downloads::Download,
     keymap::Keybindings,
     media_control::ControlMessage,
-    player::{PlaybackStatus, PlayerMessage},
-    types::{Episode, LockVec, Menuable, Message, Podcast, ShareableRwLock, SyncProgress},
+    player::{EqPreset, PlaybackStatus, PlayerEvent, PlayerMessage},
+    show_notes::ShowNotes,
+    types::{
+        Episode, FilterPanel, Filters, LockVec, Menuable, Message, Podcast, ShareableRwLock,
+        SortMode, SyncProgress,
+    },
 };
 
+use self::artwork::Artwork;
 use self::colors::AppColors;
+use self::mouse::{ClickAreas, LastClick};
 use self::notification::NotificationManager;
+use self::search::Search;
+use self::sleep_timer::SleepTimer;
 
 pub use types::UiMsg;
+mod artwork;
 pub mod colors;
 mod input;
+mod links;
+mod mouse;
 mod navigation;
 mod notification;
 mod playback;
 mod rendering;
+mod search;
+mod selection;
+mod sleep_timer;
 mod types;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -31,6 +58,7 @@ enum Panel {
     Episodes,
     Unplayed,
     Queue,
+    Downloads,
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +69,18 @@ enum Popup {
     AddPodcast,
     ConfirmRemovePodcast,
     ConfirmQuit,
+    SleepTimer,
+    GoToTimestamp,
+    OutputDevice,
+    /// Podcast and episode the internal player cannot play
+    ConfirmExternal(i64, i64),
+    /// Podcast, episode, title and exact position of the episode that was
+    /// playing when the app last closed
+    ConfirmResume(i64, i64, String, Duration),
+    Search,
+    /// Asks for the number of a link in the details, copied rather than
+    /// opened if set
+    ChooseLink(bool),
 }
 #[derive(Debug)]
 struct MenuList<T: Menuable> {
@@ -48,6 +88,14 @@ struct MenuList<T: Menuable> {
     items: LockVec<T>,
     state: ListState,
     selected_item_id: Option<i64>,
+    /// Filters applied to the items, shown in the title
+    filters: Filters,
+    /// Order of the items, None if it is not sorted
+    sort: Option<SortMode>,
+    /// Ids of the items selected for a bulk action
+    marked: HashSet<i64>,
+    /// Item toggled last, where a range of selected items starts
+    anchor: Option<i64>,
 }
 
 #[derive(Debug)]
@@ -56,12 +104,52 @@ pub struct Details {
     pub position: Option<String>,
     pub duration: Option<String>,
     pub explicit: Option<bool>,
-    pub description: Option<String>,
+    /// Show notes of the episode, or description of the podcast
+    pub notes: Option<ShowNotes>,
     pub author: Option<String>,
     pub last_checked: Option<DateTime<Utc>>,
     pub episode_title: Option<String>,
     pub podcast_title: Option<String>,
+    pub time_saved: Option<String>,
+    pub skip_intro: Option<u64>,
+    pub skip_outro: Option<u64>,
+    /// Start and title of each chapter
+    pub chapters: Vec<String>,
+    /// Set for podcasts, whose skips can be changed in the popup
+    podcast_id: Option<i64>,
+    /// Set for episodes, whose timestamps seek while they play
+    episode_id: Option<i64>,
     url: String,
+    /// Artwork of the episode, or else of its podcast
+    image_url: Option<String>,
+}
+
+/// Playback settings chosen in the UI and forwarded to the player
+#[derive(Debug)]
+struct AudioSettings {
+    speed: f32,
+    /// Volume in percent from the config
+    volume: u16,
+    /// Adjustment of the volume for the current podcast, in percent points
+    volume_offset: i32,
+    muted: bool,
+    sleep_timer: Option<SleepTimer>,
+    /// Name of the output device, None for the default one
+    output_device: Option<String>,
+    /// Waiting for the stream of the current episode to start
+    buffering: bool,
+    equalizer: EqPreset,
+    mono: bool,
+    /// Balance in percent, negative towards the left
+    balance: i8,
+}
+
+impl AudioSettings {
+    /// Volume in percent after applying the podcast adjustment
+    fn effective_volume(&self) -> u16 {
+        let volume = (i32::from(self.volume) + self.volume_offset).clamp(0, i32::from(MAX_VOLUME));
+        u16::try_from(volume).unwrap_or(self.volume)
+    }
 }
 
 pub struct UiState {
@@ -72,34 +160,86 @@ pub struct UiState {
     episodes: MenuList<Episode>,
     unplayed: MenuList<Episode>,
     queue: MenuList<Episode>,
+    downloads: MenuList<Download>,
     active_panel: Panel,
     left_panel: Panel,
     active_popup: Option<Popup>,
     scroll_popup: u16,
+    click_areas: ClickAreas,
+    last_click: Option<LastClick>,
+    sleep_menu: ListState,
+    device_menu: ListState,
+    /// Output devices listed in the popup, after the default one
+    devices: Vec<String>,
+    search: Search,
+    /// Whether the search also looks into the descriptions
+    search_descriptions: bool,
     notification: NotificationManager,
     current_episode: ShareableRwLock<Option<ShareableRwLock<Episode>>>,
     current_podcast_title: Option<String>,
     current_details: Option<Details>,
     input: Input,
     pub tx_to_player: tokio::sync::mpsc::Sender<PlayerMessage>,
-    elapsed: Arc<RwLock<u64>>,
-    playing: Arc<RwLock<PlaybackStatus>>,
+    /// Position in the current episode, as last reported by the player
+    elapsed: u64,
+    playing: PlaybackStatus,
+    rx_from_player: tokio::sync::broadcast::Receiver<PlayerEvent>,
+    time_saved: Arc<RwLock<Duration>>,
+    audio: AudioSettings,
+    seek_steps: SeekSteps,
+    /// Most the position goes back when resuming, zero if disabled
+    smart_rewind: Duration,
+    /// Equalizer preset for podcasts without one of their own
+    default_equalizer: EqPreset,
+    /// Next episode the player was asked to open ahead of time
+    prepared: Option<i64>,
     sync_progress: Arc<RwLock<SyncProgress>>,
+    artwork: Artwork,
     pub rx_from_control: mpsc::Receiver<ControlMessage>,
 }
 
 impl<T: Menuable> MenuList<T> {
+    /// Title with the order, the active filters and how many items are
+    /// selected
+    fn title(&self) -> String {
+        let sort = self
+            .sort
+            .map(|sort| format!(", {}", sort.label()))
+            .unwrap_or_default();
+        let filters = self
+            .filters
+            .label()
+            .map(|label| format!(" ({label})"))
+            .unwrap_or_default();
+        let marked = if self.marked.is_empty() {
+            String::new()
+        } else {
+            format!(" [{} selected]", self.marked.len())
+        };
+        format!("{}{sort}{filters}{marked}", self.title)
+    }
+
     fn sync_selected_with_state(&mut self) {
         self.selected_item_id = match self.state.selected() {
-            Some(index) => self.items.get_id_by_index(index),
+            Some(index) => self.items.get_id_by_index(index, true),
             None => None,
         };
     }
+    /// Selects the item shown at a row of the list, returns its index
+    fn select_row(&mut self, row: usize) -> Option<usize> {
+        let index = self.state.offset() + row;
+        (index < self.items.len(true)).then(|| {
+            self.state.select(Some(index));
+            self.sync_selected_with_state();
+            index
+        })
+    }
+
     fn sync_state_with_selected(&mut self) -> bool {
         match self.selected_item_id {
             None => false,
             Some(id) => {
-                let Some(index) = self.items.get_index(id) else {
+                let Some(index) = self.items.get_index(id, true) else {
                     return false;
                 };
                 self.state.select(Some(index));
@@ -113,12 +253,14 @@ impl UiState {
     #[allow(clippy::too_many_arguments, clippy::too_many_lines)]
     pub fn spawn_blocking(
         config: Arc<Config>, items: LockVec<Podcast>, queue_items: LockVec<Episode>,
-        unplayed_items: LockVec<Episode>, rx_from_main: mpsc::Receiver<MainMessage>,
-        tx_to_main: mpsc::Sender<Message>, tx_to_player: tokio::sync::mpsc::Sender<PlayerMessage>,
+        unplayed_items: LockVec<Episode>, download_items: LockVec<Download>,
+        rx_from_main: mpsc::Receiver<MainMessage>, tx_to_main: mpsc::Sender<Message>,
+        tx_to_player: tokio::sync::mpsc::Sender<PlayerMessage>,
         rx_from_control: mpsc::Receiver<ControlMessage>,
         current_episode: ShareableRwLock<Option<ShareableRwLock<Episode>>>,
-        elapsed: ShareableRwLock<u64>, playing: ShareableRwLock<PlaybackStatus>,
-        sync_progress: ShareableRwLock<SyncProgress>,
+        rx_from_player: tokio::sync::broadcast::Receiver<PlayerEvent>,
+        time_saved: ShareableRwLock<Duration>, sync_progress: ShareableRwLock<SyncProgress>,
+        current_art: ShareableRwLock<Option<PathBuf>>,
     ) -> tokio::task::JoinHandle<()> {
         tokio::task::spawn_blocking(move || {
             let mut ui = Self::new(
@@ -126,27 +268,41 @@ impl UiState {
                 &items,
                 &queue_items,
                 &unplayed_items,
+                &download_items,
                 tx_to_player,
                 rx_from_control,
                 current_episode,
-                elapsed,
-                playing,
+                rx_from_player,
+                time_saved,
                 sync_progress,
+                current_art,
             );
             let mut terminal = ratatui::init();
+            if config.mouse {
+                execute!(io::stdout(), EnableMouseCapture)
+                    .inspect_err(|err| log::warn!("Failed to enable mouse capture: {err}"))
+                    .ok();
+            }
             let mut main_message_iter = rx_from_main.try_iter();
             loop {
                 ui.notification.check_notifs();
+                if let Some(msg) = ui.player_events() {
+                    tx_to_main
+                        .send(Message::Ui(msg))
+                        .inspect_err(|err| log::error!("Failed to send Message::Ui: {err}"))
+                        .ok();
+                }
+                ui.prepare_next();
                 if ui.playback_finished() {
                     let mut msgs = vec![];
                     if let Some(msg) = ui.update_position() {
                         msgs.push(msg);
                     }
-                    *ui.playing.write().expect("RwLock write should not fail") =
-                        PlaybackStatus::Ready;
+                    ui.playing = PlaybackStatus::Ready;
 
                     // make it a config option
                     let mut clear_episode = true;
+                    let sleep = ui.sleep_after_episode();
                     if let Some(ep) = ui
                         .current_episode
                         .read()
@@ -154,8 +310,8 @@ impl UiState {
                         .as_ref()
                     {
                         let ep_id = ep.read().expect("RwLock read should not fail").id;
-                        if let Some(queue_index) = ui.queue.items.get_index(ep_id) {
-                            if let Some(next_ep) = ui.next_from_queue(queue_index) {
+                        if let Some(queue_index) = ui.queue.items.get_index(ep_id, true) {
+                            if !sleep && let Some(next_ep) = ui.next_from_queue(queue_index) {
                                 let (pod_id, id) = {
                                     let next_ep =
                                         next_ep.read().expect("RwLock read should not fail");
@@ -183,6 +339,12 @@ impl UiState {
                             .ok();
                     }
                 }
+                if let Some(msg) = ui.check_sleep_timer() {
+                    tx_to_main
+                        .send(Message::Ui(msg))
+                        .inspect_err(|err| log::error!("Failed to send Message::Ui: {err}"))
+                        .ok();
+                }
                 let msgs = ui.getch();
                 for msg in msgs {
                     match msg {
@@ -213,12 +375,27 @@ impl UiState {
                         MainMessage::ClearPersistentNotif => {
                             ui.notification.clear_persistent_notif();
                         }
-                        MainMessage::PlayCurrent(ep_id) => match ui.play_current(ep_id) {
-                            Ok(()) => {}
+                        MainMessage::PlayCurrent(ep_id, at) => match ui.play_current(ep_id, at) {
+                            Ok(Some(msg)) => {
+                                tx_to_main
+                                    .send(Message::Ui(msg))
+                                    .inspect_err(|err| {
+                                        log::error!("Failed to send Message::Ui: {err}");
+                                    })
+                                    .ok();
+                            }
+                            Ok(None) => {}
                             Err(err) => {
                                 log::warn!("Playing current episode failed: {err}");
                             }
                         },
+                        MainMessage::Filters(filters) => ui.set_filters(filters),
+                        MainMessage::Sort(panel, mode) => ui.set_sort(panel, mode),
+                        MainMessage::OfferResume(pod_id, ep_id, title, position) => {
+                            ui.active_popup =
+                                Some(Popup::ConfirmResume(pod_id, ep_id, title, position));
+                        }
+                        MainMessage::Artwork(url, fetched) => ui.artwork.fetched(&url, fetched),
                         MainMessage::TearDown => {
                             break;
                         }
@@ -228,6 +405,21 @@ impl UiState {
                     .draw(|frame| ui.draw(frame))
                     .inspect_err(|err| log::warn!("terminal.draw failed: {err}"))
                     .ok();
+                ui.artwork
+                    .flush()
+                    .inspect_err(|err| log::warn!("Failed to draw artwork: {err}"))
+                    .ok();
+                for url in ui.artwork.take_requests() {
+                    tx_to_main
+                        .send(Message::Ui(UiMsg::FetchArtwork(url)))
+                        .inspect_err(|err| log::error!("Failed to send Message::Ui: {err}"))
+                        .ok();
+                }
+            }
+            if config.mouse {
+                execute!(io::stdout(), DisableMouseCapture)
+                    .inspect_err(|err| log::warn!("Failed to disable mouse capture: {err}"))
+                    .ok();
             }
             ratatui::restore();
             #[cfg(any(target_os = "macos", target_os = "windows"))]
@@ -238,11 +430,13 @@ impl UiState {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: &Arc<Config>, podcast_items: &LockVec<Podcast>, queue_items: &LockVec<Episode>,
-        unplayed_items: &LockVec<Episode>, tx_to_player: tokio::sync::mpsc::Sender<PlayerMessage>,
+        unplayed_items: &LockVec<Episode>, download_items: &LockVec<Download>,
+        tx_to_player: tokio::sync::mpsc::Sender<PlayerMessage>,
         rx_from_control: mpsc::Receiver<ControlMessage>,
         current_episode: ShareableRwLock<Option<ShareableRwLock<Episode>>>,
-        elapsed: ShareableRwLock<u64>, playing: ShareableRwLock<PlaybackStatus>,
-        sync_progress: ShareableRwLock<SyncProgress>,
+        rx_from_player: tokio::sync::broadcast::Receiver<PlayerEvent>,
+        time_saved: ShareableRwLock<Duration>, sync_progress: ShareableRwLock<SyncProgress>,
+        current_art: ShareableRwLock<Option<PathBuf>>,
     ) -> Self {
         let active_popup = if podcast_items.is_empty() {
             Some(Popup::Welcome)
@@ -258,39 +452,91 @@ impl UiState {
                 title: "Podcasts".to_string(),
                 items: podcast_items.clone(),
                 state: ListState::default().with_selected(Some(0)),
-                selected_item_id: podcast_items.get_id_by_index(0),
+                selected_item_id: podcast_items.get_id_by_index(0, true),
+                filters: Filters::default(),
+                sort: Some(SortMode::default_for(FilterPanel::Podcasts)),
+                marked: HashSet::new(),
+                anchor: None,
             },
             unplayed: MenuList::<Episode> {
                 title: "Unplayed".to_string(),
                 items: unplayed_items.clone(),
                 state: ListState::default().with_selected(Some(0)),
-                selected_item_id: unplayed_items.get_id_by_index(0),
+                selected_item_id: unplayed_items.get_id_by_index(0, true),
+                filters: Filters::default(),
+                sort: Some(SortMode::default_for(FilterPanel::Unplayed)),
+                marked: HashSet::new(),
+                anchor: None,
             },
             episodes: MenuList::<Episode> {
                 title: "Episodes".to_string(),
                 items: LockVec::new(vec![]),
                 state: ListState::default(),
                 selected_item_id: None,
+                filters: Filters::default(),
+                sort: Some(SortMode::default_for(FilterPanel::Episodes)),
+                marked: HashSet::new(),
+                anchor: None,
             },
             queue: MenuList::<Episode> {
                 title: "Queue".to_string(),
                 items: queue_items.clone(),
                 state: ListState::default().with_selected(Some(0)),
-                selected_item_id: queue_items.get_id_by_index(0),
+                selected_item_id: queue_items.get_id_by_index(0, true),
+                filters: Filters::default(),
+                sort: None,
+                marked: HashSet::new(),
+                anchor: None,
+            },
+            downloads: MenuList::<Download> {
+                title: "Downloads".to_string(),
+                items: download_items.clone(),
+                state: ListState::default(),
+                selected_item_id: None,
+                filters: Filters::default(),
+                sort: None,
+                marked: HashSet::new(),
+                anchor: None,
             },
             active_panel: Panel::Podcasts,
             left_panel: Panel::Podcasts,
             active_popup,
             scroll_popup: 0,
+            click_areas: ClickAreas::default(),
+            last_click: None,
+            sleep_menu: ListState::default(),
+            device_menu: ListState::default(),
+            devices: Vec::new(),
+            search: Search::default(),
+            search_descriptions: config.search_descriptions,
             notification: NotificationManager::new(),
             current_episode,
             current_podcast_title: None,
             current_details: None,
             input: Input::default(),
             tx_to_player,
-            elapsed,
-            playing,
+            elapsed: 0,
+            playing: PlaybackStatus::Ready,
+            rx_from_player,
+            time_saved,
+            audio: AudioSettings {
+                speed: 1.0,
+                volume: config.volume,
+                volume_offset: 0,
+                muted: false,
+                sleep_timer: None,
+                output_device: config.output_device.clone(),
+                buffering: false,
+                equalizer: config.equalizer,
+                mono: config.mono,
+                balance: config.balance,
+            },
+            default_equalizer: config.equalizer,
+            seek_steps: config.seek_steps,
+            smart_rewind: config.smart_rewind,
+            prepared: None,
             sync_progress,
+            artwork: Artwork::new(config.artwork, current_art),
             rx_from_control,
         }
     }
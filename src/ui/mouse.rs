@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    crossterm::event::{MouseButton, MouseEvent, MouseEventKind},
+    layout::{Position, Rect},
+};
+
+use crate::keymap::UserAction;
+
+use super::{Panel, Popup, UiMsg, UiState};
+
+/// Longest time between two clicks on an item to play it
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Where the lists and the progress gauge were last drawn, to find what
+/// is under the pointer
+#[derive(Debug, Default)]
+pub(super) struct ClickAreas {
+    pub(super) left: Rect,
+    pub(super) queue: Rect,
+    pub(super) progress: Rect,
+}
+
+/// Item last clicked, to detect double clicks
+#[derive(Debug)]
+pub(super) struct LastClick {
+    panel: Panel,
+    index: usize,
+    time: Instant,
+}
+
+impl UiState {
+    pub(super) fn handle_mouse(&mut self, mouse: MouseEvent) -> Vec<UiMsg> {
+        let position = Position::new(mouse.column, mouse.row);
+        match mouse.kind {
+            MouseEventKind::ScrollDown => self.scroll(position, UserAction::Down),
+            MouseEventKind::ScrollUp => self.scroll(position, UserAction::Up),
+            MouseEventKind::Down(MouseButton::Left) if self.active_popup.is_none() => {
+                return self.click(position);
+            }
+            _ => {}
+        }
+        vec![UiMsg::Noop]
+    }
+
+    /// Scrolls the open popup, or else the list under the pointer
+    fn scroll(&mut self, position: Position, action: UserAction) {
+        let menu = match self.active_popup {
+            Some(Popup::SleepTimer) => &mut self.sleep_menu,
+            Some(Popup::OutputDevice) => &mut self.device_menu,
+            Some(Popup::Search) => &mut self.search.state,
+            Some(_) => return self.move_cursor(action),
+            None => {
+                if let Some(panel) = self.panel_at(position) {
+                    self.select_panel(&panel);
+                    self.move_cursor(action);
+                }
+                return;
+            }
+        };
+        if action == UserAction::Down {
+            menu.select_next();
+        } else {
+            menu.select_previous();
+        }
+    }
+
+    /// The list drawn at a position of the screen
+    fn panel_at(&self, position: Position) -> Option<Panel> {
+        if self.click_areas.queue.contains(position) {
+            Some(Panel::Queue)
+        } else if self.click_areas.left.contains(position) {
+            Some(self.left_panel.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Selects the clicked item and focuses its list, plays it when
+    /// clicked twice. Clicking the progress gauge seeks.
+    fn click(&mut self, position: Position) -> Vec<UiMsg> {
+        let progress = self.click_areas.progress;
+        if progress.contains(position) {
+            self.seek_to_ratio(
+                u64::from(position.x - progress.x),
+                u64::from(progress.width),
+            );
+            return vec![UiMsg::Noop];
+        }
+        let Some(panel) = self.panel_at(position) else {
+            return vec![UiMsg::Noop];
+        };
+        self.select_panel(&panel);
+        let area = if panel == Panel::Queue {
+            self.click_areas.queue
+        } else {
+            self.click_areas.left
+        };
+        // the first row is the border with the title
+        let Some(row) = (position.y - area.y).checked_sub(1) else {
+            return vec![UiMsg::Noop];
+        };
+        let row = usize::from(row);
+        let index = match panel {
+            Panel::Podcasts => self.podcasts.select_row(row),
+            Panel::Episodes => self.episodes.select_row(row),
+            Panel::Unplayed => self.unplayed.select_row(row),
+            Panel::Queue => self.queue.select_row(row),
+            Panel::Downloads => self.downloads.select_row(row),
+        };
+        let Some(index) = index else {
+            return vec![UiMsg::Noop];
+        };
+
+        let double_click = self.last_click.take().is_some_and(|last| {
+            last.panel == panel && last.index == index && last.time.elapsed() < DOUBLE_CLICK
+        });
+        if !double_click {
+            self.last_click = Some(LastClick {
+                panel,
+                index,
+                time: Instant::now(),
+            });
+            return vec![UiMsg::Noop];
+        }
+        match panel {
+            Panel::Podcasts => {
+                if let Some(pod_id) = self.get_podcast_id() {
+                    self.open_podcast(pod_id);
+                }
+                vec![UiMsg::Noop]
+            }
+            Panel::Episodes | Panel::Unplayed | Panel::Queue => self.play_selected_episode(),
+            Panel::Downloads => vec![UiMsg::Noop],
+        }
+    }
+}
@@ -1,12 +1,29 @@
+use std::time::Duration;
+
+use ratatui::widgets::ListState;
+
 use crate::{
     config::SCROLL_AMOUNT,
     keymap::UserAction,
-    types::Menuable,
-    utils::{clean_html, format_duration},
+    show_notes::ShowNotes,
+    types::{Episode, FilterPanel, Menuable, PanelFilters, SortMode},
+    utils::format_duration,
 };
 
 use super::{Details, Panel, UiMsg, UiState};
 
+/// Describes the time saved by skipping silence, None if it never saved
+/// anything
+fn format_time_saved(time_saved: Duration, total: Duration) -> Option<String> {
+    (!total.is_zero()).then(|| {
+        format!(
+            "{} ({} overall)",
+            format_duration(Some(time_saved.as_secs())),
+            format_duration(Some(total.as_secs()))
+        )
+    })
+}
+
 impl UiState {
     pub(super) fn move_cursor(&mut self, action: UserAction) {
         if self.active_popup.is_some() {
@@ -40,6 +57,7 @@ impl UiState {
                     Panel::Unplayed => &mut self.unplayed.state,
                     Panel::Episodes => &mut self.episodes.state,
                     Panel::Queue => &mut self.queue.state,
+                    Panel::Downloads => &mut self.downloads.state,
                 }
             };
             match action {
@@ -48,7 +66,7 @@ impl UiState {
                 UserAction::Up => current_state.select_previous(),
 
                 UserAction::Left => match self.active_panel {
-                    Panel::Podcasts | Panel::Unplayed => {}
+                    Panel::Podcasts | Panel::Unplayed | Panel::Downloads => {}
                     Panel::Episodes => {
                         self.select_panel(&Panel::Podcasts);
                     }
@@ -59,7 +77,7 @@ impl UiState {
                 },
 
                 UserAction::Right => match self.active_panel {
-                    Panel::Podcasts | Panel::Unplayed | Panel::Episodes => {
+                    Panel::Podcasts | Panel::Unplayed | Panel::Episodes | Panel::Downloads => {
                         self.active_panel = Panel::Queue;
                         self.queue.state.select_first();
                     }
@@ -82,10 +100,36 @@ impl UiState {
                 Panel::Unplayed => self.unplayed.sync_selected_with_state(),
                 Panel::Episodes => self.episodes.sync_selected_with_state(),
                 Panel::Queue => self.queue.sync_selected_with_state(),
+                Panel::Downloads => self.downloads.sync_selected_with_state(),
             }
         }
     }
 
+    /// The list whose filters are changed from the active panel, the
+    /// queue and the downloads have none
+    pub(super) const fn filter_panel(&self) -> Option<FilterPanel> {
+        match self.active_panel {
+            Panel::Podcasts => Some(FilterPanel::Podcasts),
+            Panel::Episodes => Some(FilterPanel::Episodes),
+            Panel::Unplayed => Some(FilterPanel::Unplayed),
+            Panel::Queue | Panel::Downloads => None,
+        }
+    }
+
+    pub(super) const fn set_filters(&mut self, filters: PanelFilters) {
+        self.podcasts.filters = filters.podcasts;
+        self.episodes.filters = filters.episodes;
+        self.unplayed.filters = filters.unplayed;
+    }
+
+    pub(super) const fn set_sort(&mut self, panel: FilterPanel, mode: SortMode) {
+        match panel {
+            FilterPanel::Podcasts => self.podcasts.sort = Some(mode),
+            FilterPanel::Episodes => self.episodes.sort = Some(mode),
+            FilterPanel::Unplayed => self.unplayed.sort = Some(mode),
+        }
+    }
+
     pub(super) const fn select_panel(&mut self, panel: &Panel) {
         match panel {
             Panel::Podcasts => {
@@ -103,6 +147,24 @@ impl UiState {
             Panel::Queue => {
                 self.active_panel = Panel::Queue;
             }
+            Panel::Downloads => {
+                self.active_panel = Panel::Downloads;
+                self.left_panel = Panel::Downloads;
+            }
+        }
+    }
+
+    /// Shows the episodes of a podcast, with the first one selected
+    pub(super) fn open_podcast(&mut self, pod_id: i64) {
+        self.select_panel(&Panel::Episodes);
+        if let Some((items, sort)) = self
+            .podcasts
+            .items
+            .map_single(pod_id, |x| (x.episodes.clone(), x.sort))
+        {
+            self.episodes.items = items;
+            self.episodes.sort = Some(sort);
+            self.episodes.state = ListState::default().with_selected(Some(0));
         }
     }
 
@@ -111,15 +173,21 @@ impl UiState {
             Panel::Podcasts => None,
             Panel::Episodes => {
                 let id = self.episodes.state.selected()?;
-                self.episodes.items.map_single_by_index(id, |x| x.id)
+                self.episodes.items.map_single_by_index(id, |x| x.id, true)
             }
             Panel::Unplayed => {
                 let id = self.unplayed.state.selected()?;
-                self.unplayed.items.map_single_by_index(id, |x| x.id)
+                self.unplayed.items.map_single_by_index(id, |x| x.id, true)
             }
             Panel::Queue => {
                 let id = self.queue.state.selected()?;
-                self.queue.items.map_single_by_index(id, |x| x.id)
+                self.queue.items.map_single_by_index(id, |x| x.id, true)
+            }
+            Panel::Downloads => {
+                let id = self.downloads.state.selected()?;
+                self.downloads
+                    .items
+                    .map_single_by_index(id, |x| x.ep_id, true)
             }
         }
     }
@@ -128,19 +196,29 @@ impl UiState {
         match self.active_panel {
             Panel::Podcasts => {
                 let id = self.podcasts.state.selected()?;
-                self.podcasts.items.map_single_by_index(id, |x| x.id)
+                self.podcasts.items.map_single_by_index(id, |x| x.id, true)
             }
             Panel::Episodes => {
                 let id = self.episodes.state.selected()?;
-                self.episodes.items.map_single_by_index(id, |x| x.pod_id)
+                self.episodes
+                    .items
+                    .map_single_by_index(id, |x| x.pod_id, true)
             }
             Panel::Unplayed => {
                 let id = self.unplayed.state.selected()?;
-                self.unplayed.items.map_single_by_index(id, |x| x.pod_id)
+                self.unplayed
+                    .items
+                    .map_single_by_index(id, |x| x.pod_id, true)
             }
             Panel::Queue => {
                 let id = self.queue.state.selected()?;
-                self.queue.items.map_single_by_index(id, |x| x.pod_id)
+                self.queue.items.map_single_by_index(id, |x| x.pod_id, true)
+            }
+            Panel::Downloads => {
+                let id = self.downloads.state.selected()?;
+                self.downloads
+                    .items
+                    .map_single_by_index(id, |x| x.pod_id, true)
             }
         }
     }
@@ -150,10 +228,11 @@ impl UiState {
 
         match action {
             UserAction::MoveDown => {
-                if selected + 1 < self.queue.items.len(false) {
+                if selected + 1 < self.queue.items.len(true) {
                     {
-                        let mut order_vec = self.queue.items.borrow_order();
-                        order_vec.swap(selected, selected + 1);
+                        let (_map, mut order, mut filtered_order) = self.queue.items.borrow();
+                        order.swap(selected, selected + 1);
+                        filtered_order.swap(selected, selected + 1);
                     }
                     self.queue.state.select(Some(selected + 1));
                     self.queue.sync_selected_with_state();
@@ -162,8 +241,9 @@ impl UiState {
             }
             UserAction::MoveUp if selected >= 1 => {
                 {
-                    let mut order_vec = self.queue.items.borrow_order();
-                    order_vec.swap(selected, selected - 1);
+                    let (_map, mut order, mut filtered_order) = self.queue.items.borrow();
+                    order.swap(selected, selected - 1);
+                    filtered_order.swap(selected, selected - 1);
                 }
                 self.queue.state.select(Some(selected - 1));
                 self.queue.sync_selected_with_state();
@@ -192,7 +272,7 @@ impl UiState {
                 let played = self.queue.items.map_single(ep_id, Menuable::is_played)?;
                 Some(UiMsg::MarkPlayed(pod_id, ep_id, !played))
             }
-            Panel::Podcasts => None,
+            Panel::Podcasts | Panel::Downloads => None,
         }
     }
 
@@ -216,31 +296,42 @@ impl UiState {
                 Panel::Episodes => self.episodes.items.get(ep_id),
                 Panel::Queue => self.queue.items.get(ep_id),
                 Panel::Unplayed => self.unplayed.items.get(ep_id),
-                Panel::Podcasts => None,
+                Panel::Podcasts | Panel::Downloads => None,
             };
+            // computed before locking the episode, which they read as well
+            let total = self.total_time_saved();
+            let pending = self.pending_time_saved(|cur| cur.id == ep_id);
             if let Some(ep) = ep {
                 let ep = ep.read().expect("RwLock read should not fail");
-                let desc = clean_html(&ep.description);
-                let podcast_title = {
-                    let pod = self.podcasts.items.get(ep.pod_id);
-                    pod.map(|pod| {
-                        pod.read()
-                            .expect("RwLock read should not fail")
-                            .title
-                            .clone()
-                    })
-                };
+                let (podcast_title, podcast_image) = self
+                    .podcasts
+                    .items
+                    .map_single(ep.pod_id, |pod| (pod.title.clone(), pod.image_url.clone()))
+                    .unzip();
                 self.current_details = Some(Details {
                     pubdate: ep.pubdate,
                     position: Some(format_duration(Some(ep.position))),
                     duration: Some(format_duration(ep.duration)),
                     explicit: None,
-                    description: Some(desc),
+                    notes: Some(ShowNotes::parse(&ep.description)),
                     author: None,
                     last_checked: None,
                     episode_title: Some(ep.title.clone()),
                     podcast_title,
+                    time_saved: format_time_saved(ep.time_saved + pending, total),
+                    skip_intro: None,
+                    skip_outro: None,
+                    chapters: ep
+                        .chapters
+                        .iter()
+                        .map(|chapter| {
+                            format!("{} {}", format_duration(Some(chapter.start)), chapter.title)
+                        })
+                        .collect(),
+                    podcast_id: None,
+                    episode_id: Some(ep.id),
                     url: ep.url.clone(),
+                    image_url: ep.image_url.clone().or_else(|| podcast_image.flatten()),
                 });
             }
         }
@@ -250,29 +341,78 @@ impl UiState {
         if let Some(pod_id) = self.get_podcast_id()
             && let Some(pod) = self.podcasts.items.get(pod_id)
         {
+            let total = self.total_time_saved();
+            let pending = self.pending_time_saved(|cur| cur.pod_id == pod_id);
             let pod = pod.read().expect("RwLock read should not fail");
-            let desc = pod.description.clone().map(|desc| clean_html(&desc));
+            let notes = pod.description.as_deref().map(ShowNotes::parse);
+            let time_saved = pod
+                .episodes
+                .map(|ep| ep.time_saved, false)
+                .into_iter()
+                .sum::<Duration>()
+                + pending;
             self.current_details = Some(Details {
                 pubdate: None,
                 position: None,
                 duration: None,
                 explicit: pod.explicit,
-                description: desc,
+                notes,
                 author: pod.author.clone(),
                 last_checked: Some(pod.last_checked),
                 episode_title: None,
                 podcast_title: Some(pod.title.clone()),
+                time_saved: format_time_saved(time_saved, total),
+                skip_intro: Some(pod.skip_intro),
+                skip_outro: Some(pod.skip_outro),
+                chapters: Vec::new(),
+                podcast_id: Some(pod.id),
+                episode_id: None,
                 url: pod.url.clone(),
+                image_url: pod.image_url.clone(),
             });
         }
     }
 
+    /// Time saved by skipping silence over all episodes, including what
+    /// the player has not reported yet
+    fn total_time_saved(&self) -> Duration {
+        self.podcasts
+            .items
+            .map(
+                |pod| {
+                    pod.episodes
+                        .map(|ep| ep.time_saved, false)
+                        .into_iter()
+                        .sum::<Duration>()
+                },
+                false,
+            )
+            .into_iter()
+            .sum::<Duration>()
+            + self.pending_time_saved(|_| true)
+    }
+
+    /// Time saved in the current episode that is not stored yet, if the
+    /// episode matches
+    fn pending_time_saved(&self, matches: impl Fn(&Episode) -> bool) -> Duration {
+        let current = self
+            .current_episode
+            .read()
+            .expect("RwLock read should not fail");
+        match current.as_ref() {
+            Some(ep) if matches(&ep.read().expect("RwLock read should not fail")) => {
+                *self.time_saved.read().expect("RwLock read should not fail")
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
     pub(super) fn construct_current_episode(&mut self, ep_id: i64) {
         let ep = match self.active_panel {
             Panel::Episodes => self.episodes.items.get(ep_id),
             Panel::Queue => self.queue.items.get(ep_id),
             Panel::Unplayed => self.unplayed.items.get(ep_id),
-            Panel::Podcasts => None,
+            Panel::Podcasts | Panel::Downloads => None,
         };
         if let Some(ep_arc) = ep {
             let ep_pod_id = ep_arc.read().expect("RwLock read should not fail").pod_id;
@@ -1,20 +1,39 @@
+use std::time::Duration;
+
 use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast::error::TryRecvError;
 
 use crate::{
-    player::{PlaybackStatus, PlayerMessage},
+    chapters,
+    config::{
+        BALANCE_STEP, MAX_BALANCE, MAX_SKIP, MAX_SPEED, MAX_VOLUME, MESSAGE_TIME, MIN_SPEED,
+        PREBUFFER_TIME, SKIP_STEP, SPEED_STEP, VOLUME_STEP,
+    },
+    keymap::UserAction,
+    player::{self, EqPreset, PlaybackStatus, PlayerEvent, PlayerMessage, PodcastSettings},
     types::{Episode, ShareableRwLock},
+    utils::smart_rewind,
 };
 
-use super::{UiMsg, UiState};
+use super::{Popup, UiMsg, UiState, sleep_timer::SleepTimer};
 
 impl UiState {
-    pub(super) fn play_current(&mut self, ep_id: i64) -> Result<()> {
+    /// Starts playing the episode, from `resume_at` if given, else from its
+    /// saved position. The message saves its position when it was rewound.
+    pub(super) fn play_current(
+        &mut self, ep_id: i64, resume_at: Option<Duration>,
+    ) -> Result<Option<UiMsg>> {
         self.construct_current_episode(ep_id);
-        let ep = self
-            .current_episode
-            .read()
-            .expect("RwLock read should not fail");
-        let (path, position, duration, url) = {
+        // the chapter belonged to the previous episode
+        if matches!(self.audio.sleep_timer, Some(SleepTimer::EndOfChapter(_))) {
+            self.audio.sleep_timer = None;
+        }
+        let (path, saved_position, duration, loudness, url, pod_id, last_played) = {
+            let ep = self
+                .current_episode
+                .read()
+                .expect("RwLock read should not fail");
             let ep = ep
                 .as_ref()
                 .ok_or_else(|| anyhow!("Failed to get current episode"))?
@@ -24,19 +43,387 @@ impl UiState {
                 ep.path.clone(),
                 ep.position,
                 ep.duration.unwrap_or(0),
+                ep.loudness,
                 ep.url.clone(),
+                ep.pod_id,
+                ep.last_played,
             )
         };
 
-        *self.elapsed.write().expect("RwLock write should not fail") = position;
+        let (speed, volume_offset, skip_intro, skip_outro, equalizer) = self
+            .podcasts
+            .items
+            .map_single(pod_id, |pod| {
+                (
+                    pod.speed,
+                    pod.volume_offset,
+                    pod.skip_intro,
+                    pod.skip_outro,
+                    pod.equalizer,
+                )
+            })
+            .unwrap_or((1.0, 0, 0, 0, None));
+        self.set_speed(speed);
+        self.set_equalizer(equalizer.unwrap_or(self.default_equalizer));
+        self.audio.volume_offset = volume_offset;
+        self.send_volume();
+        self.send_skip_outro(skip_outro);
+        let position = self.start_position(
+            resume_at.unwrap_or_else(|| Duration::from_secs(saved_position)),
+            duration,
+            last_played,
+            skip_intro,
+        );
+        self.prepared = None;
+        self.elapsed = position.as_secs();
+        *self
+            .time_saved
+            .write()
+            .expect("RwLock write should not fail") = Duration::ZERO;
         if let Some(path) = path {
-            self.tx_to_player
-                .blocking_send(PlayerMessage::PlayFile(path, position, duration))?;
+            self.tx_to_player.blocking_send(PlayerMessage::PlayFile(
+                ep_id, path, position, duration, loudness,
+            ))?;
         } else {
             self.tx_to_player
-                .blocking_send(PlayerMessage::PlayUrl(url, position, duration))?;
+                .blocking_send(PlayerMessage::PlayUrl(ep_id, url, position, duration))?;
+        }
+        Ok(
+            (self.elapsed < saved_position).then_some(UiMsg::UpdatePosition(
+                pod_id,
+                ep_id,
+                self.elapsed,
+                Duration::ZERO,
+            )),
+        )
+    }
+
+    /// Where an episode starts: after the intro of its podcast when it was
+    /// not started yet, else the saved position, rewound when it was last
+    /// played a while ago.
+    fn start_position(
+        &self, position: Duration, duration: u64, last_played: Option<DateTime<Utc>>,
+        skip_intro: u64,
+    ) -> Duration {
+        if position.is_zero() && (duration == 0 || skip_intro < duration) {
+            return Duration::from_secs(skip_intro);
+        }
+        let paused = last_played
+            .and_then(|last_played| (Utc::now() - last_played).to_std().ok())
+            .unwrap_or_default();
+        position.saturating_sub(smart_rewind(paused, self.smart_rewind))
+    }
+
+    pub(super) fn seek(&self, shift: Duration, forward: bool) {
+        self.tx_to_player
+            .blocking_send(PlayerMessage::Seek(shift, forward))
+            .inspect_err(|err| log::error!("Failed to send PlayerMessage::Seek to player: {err}"))
+            .ok();
+    }
+
+    pub(super) fn seek_to(&self, position: Duration) {
+        self.tx_to_player
+            .blocking_send(PlayerMessage::SeekTo(position))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::SeekTo to player: {err}");
+            })
+            .ok();
+    }
+
+    /// Seeks to the given tenth of the current episode, if its duration is
+    /// known.
+    pub(super) fn jump_to(&self, tenth: u8) {
+        self.seek_to_ratio(u64::from(tenth), 10);
+    }
+
+    /// Seeks to the given part of the current episode, if its duration is
+    /// known.
+    pub(super) fn seek_to_ratio(&self, part: u64, whole: u64) {
+        let duration = self
+            .current_episode
+            .read()
+            .expect("RwLock read should not fail")
+            .as_ref()
+            .and_then(|ep| ep.read().expect("RwLock read should not fail").duration);
+        if let Some(duration) = duration.filter(|&duration| duration > 0) {
+            self.seek_to(Duration::from_secs(duration * part / whole.max(1)));
+        }
+    }
+
+    /// Seeks to the start of the next chapter, or back to the start of
+    /// the current or previous one.
+    pub(super) fn skip_chapter(&self, forward: bool) {
+        let position = self.elapsed;
+        let start = self
+            .current_episode
+            .read()
+            .expect("RwLock read should not fail")
+            .as_ref()
+            .and_then(|ep| {
+                let ep = ep.read().expect("RwLock read should not fail");
+                if forward {
+                    chapters::next_start(&ep.chapters, position)
+                } else {
+                    chapters::previous_start(&ep.chapters, position)
+                }
+            });
+        if let Some(start) = start {
+            self.seek_to(Duration::from_secs(start));
+        }
+    }
+
+    fn send_skip_outro(&self, outro: u64) {
+        self.tx_to_player
+            .blocking_send(PlayerMessage::SkipOutro(outro))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::SkipOutro to player: {err}");
+            })
+            .ok();
+    }
+
+    /// Changes the intro or outro skipped for the podcast shown in the
+    /// details popup.
+    pub(super) fn change_skips(&mut self, action: UserAction) -> Option<UiMsg> {
+        let details = self.current_details.as_mut()?;
+        let pod_id = details.podcast_id?;
+        let (intro, outro) = (details.skip_intro?, details.skip_outro?);
+        let (intro, outro) = match action {
+            UserAction::IntroShorter => (intro.saturating_sub(SKIP_STEP), outro),
+            UserAction::IntroLonger => ((intro + SKIP_STEP).min(MAX_SKIP), outro),
+            UserAction::OutroShorter => (intro, outro.saturating_sub(SKIP_STEP)),
+            UserAction::OutroLonger => (intro, (outro + SKIP_STEP).min(MAX_SKIP)),
+            _ => return None,
+        };
+        details.skip_intro = Some(intro);
+        details.skip_outro = Some(outro);
+        if self.current_podcast_id() == Some(pod_id) {
+            self.send_skip_outro(outro);
+        }
+        Some(UiMsg::SetSkips(pod_id, intro, outro))
+    }
+
+    fn current_podcast_id(&self) -> Option<i64> {
+        Some(
+            self.current_episode
+                .read()
+                .expect("RwLock read should not fail")
+                .as_ref()?
+                .read()
+                .expect("RwLock read should not fail")
+                .pod_id,
+        )
+    }
+
+    /// Steps the speed of the current episode up or down, the new speed is
+    /// remembered for its podcast.
+    pub(super) fn change_speed(&mut self, faster: bool) -> Option<UiMsg> {
+        let pod_id = self.current_podcast_id()?;
+        let step = if faster { SPEED_STEP } else { -SPEED_STEP };
+        // round to avoid drifting away from the steps
+        let speed = (((self.audio.speed + step) / SPEED_STEP).round() * SPEED_STEP)
+            .clamp(MIN_SPEED, MAX_SPEED);
+        self.set_speed(speed);
+        Some(UiMsg::SetSpeed(pod_id, speed))
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.audio.speed = speed;
+        self.tx_to_player
+            .blocking_send(PlayerMessage::SetSpeed(speed))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::SetSpeed to player: {err}");
+            })
+            .ok();
+    }
+
+    /// Switches to the next equalizer preset, which is remembered for the
+    /// podcast of the current episode.
+    pub(super) fn change_equalizer(&mut self) -> Option<UiMsg> {
+        let pod_id = self.current_podcast_id()?;
+        let preset = self.audio.equalizer.next();
+        self.set_equalizer(preset);
+        self.notification.timed_notif(
+            format!("Equalizer: {}", preset.label()),
+            MESSAGE_TIME,
+            false,
+        );
+        Some(UiMsg::SetEqualizer(pod_id, preset))
+    }
+
+    fn set_equalizer(&mut self, preset: EqPreset) {
+        self.audio.equalizer = preset;
+        self.tx_to_player
+            .blocking_send(PlayerMessage::SetEqualizer(preset))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::SetEqualizer to player: {err}");
+            })
+            .ok();
+    }
+
+    /// Steps the volume of the current episode up or down, the change is
+    /// remembered as an adjustment for its podcast.
+    pub(super) fn change_volume(&mut self, louder: bool) -> Option<UiMsg> {
+        let pod_id = self.current_podcast_id()?;
+        let volume = if louder {
+            (self.audio.effective_volume() + VOLUME_STEP).min(MAX_VOLUME)
+        } else {
+            self.audio.effective_volume().saturating_sub(VOLUME_STEP)
+        };
+        self.audio.volume_offset = i32::from(volume) - i32::from(self.audio.volume);
+        self.send_volume();
+        if self.audio.muted {
+            self.toggle_mute();
+        }
+        Some(UiMsg::SetVolumeOffset(pod_id, self.audio.volume_offset))
+    }
+
+    fn send_volume(&self) {
+        let volume = f32::from(self.audio.effective_volume()) / 100.0;
+        self.tx_to_player
+            .blocking_send(PlayerMessage::SetVolume(volume))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::SetVolume to player: {err}");
+            })
+            .ok();
+    }
+
+    pub(super) fn toggle_mute(&mut self) {
+        self.audio.muted = !self.audio.muted;
+        self.tx_to_player
+            .blocking_send(PlayerMessage::Mute(self.audio.muted))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::Mute to player: {err}");
+            })
+            .ok();
+    }
+
+    pub(super) fn toggle_mono(&mut self) {
+        self.audio.mono = !self.audio.mono;
+        self.tx_to_player
+            .blocking_send(PlayerMessage::Mono(self.audio.mono))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::Mono to player: {err}");
+            })
+            .ok();
+        let state = if self.audio.mono { "on" } else { "off" };
+        self.notification
+            .timed_notif(format!("Mono: {state}"), MESSAGE_TIME, false);
+    }
+
+    /// Steps the balance towards the left or right channel.
+    pub(super) fn change_balance(&mut self, right: bool) {
+        let step = if right { BALANCE_STEP } else { -BALANCE_STEP };
+        self.audio.balance = (self.audio.balance + step).clamp(-MAX_BALANCE, MAX_BALANCE);
+        self.tx_to_player
+            .blocking_send(PlayerMessage::Balance(self.audio.balance))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::Balance to player: {err}");
+            })
+            .ok();
+        let balance = match self.audio.balance {
+            0 => "center".to_string(),
+            balance if balance < 0 => format!("L{}", balance.unsigned_abs()),
+            balance => format!("R{balance}"),
+        };
+        self.notification
+            .timed_notif(format!("Balance: {balance}"), MESSAGE_TIME, false);
+    }
+
+    /// Lists the output devices and opens the popup on the one in use
+    pub(super) fn open_device_menu(&mut self) {
+        self.devices = player::output_device_names();
+        // the default device comes first, and is used if the chosen one is gone
+        let selected = self
+            .audio
+            .output_device
+            .as_ref()
+            .and_then(|name| self.devices.iter().position(|device| device == name))
+            .map_or(0, |index| index + 1);
+        self.device_menu.select(Some(selected));
+        self.active_popup = Some(Popup::OutputDevice);
+    }
+
+    /// Switches to the device at `index` in the popup, 0 being the default
+    pub(super) fn set_output_device(&mut self, index: usize) {
+        let device = index
+            .checked_sub(1)
+            .and_then(|index| self.devices.get(index).cloned());
+        let name = device.clone().unwrap_or_else(|| "default".to_string());
+        self.audio.output_device.clone_from(&device);
+        self.tx_to_player
+            .blocking_send(PlayerMessage::SetDevice(device))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::SetDevice to player: {err}");
+            })
+            .ok();
+        self.notification
+            .timed_notif(format!("Output device: {name}"), MESSAGE_TIME, false);
+    }
+
+    /// Applies the state changes reported by the player since the last
+    /// call, returns the latest position to save.
+    pub(super) fn player_events(&mut self) -> Option<UiMsg> {
+        let mut checkpoint = None;
+        loop {
+            let event = match self.rx_from_player.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Lagged(skipped)) => {
+                    log::warn!("Missed {skipped} player events");
+                    continue;
+                }
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            };
+            match event {
+                PlayerEvent::Started | PlayerEvent::Resumed => {
+                    self.playing = PlaybackStatus::Playing;
+                }
+                PlayerEvent::Paused => self.playing = PlaybackStatus::Paused,
+                PlayerEvent::Finished => self.playing = PlaybackStatus::Finished,
+                PlayerEvent::Position(position) => self.elapsed = position,
+                PlayerEvent::Checkpoint(ep_id, position) => {
+                    checkpoint = self.checkpoint(ep_id, position).or(checkpoint);
+                }
+                PlayerEvent::Buffering(buffering) => self.audio.buffering = buffering,
+                // reported by the app
+                PlayerEvent::Error(_) => {}
+                PlayerEvent::Unsupported => {
+                    self.playing = PlaybackStatus::Ready;
+                    self.offer_external();
+                }
+            }
+        }
+        checkpoint
+    }
+
+    /// Saves the position of the current episode. It is dropped if the
+    /// player was still on the previous one.
+    fn checkpoint(&self, ep_id: i64, position: Duration) -> Option<UiMsg> {
+        let cur_ep = self
+            .current_episode
+            .read()
+            .expect("RwLock read should not fail");
+        let cur_ep = cur_ep
+            .as_ref()?
+            .read()
+            .expect("RwLock read should not fail");
+        (cur_ep.id == ep_id).then(|| UiMsg::Checkpoint(cur_ep.pod_id, ep_id, position))
+    }
+
+    /// Asks whether to play the current episode with the external player,
+    /// since the internal one cannot decode it.
+    fn offer_external(&mut self) {
+        let current = self
+            .current_episode
+            .read()
+            .expect("RwLock read should not fail")
+            .as_ref()
+            .map(|ep| {
+                let ep = ep.read().expect("RwLock read should not fail");
+                (ep.pod_id, ep.id)
+            });
+        if let Some((pod_id, ep_id)) = current {
+            self.active_popup = Some(Popup::ConfirmExternal(pod_id, ep_id));
         }
-        Ok(())
     }
 
     pub(super) fn playback_finished(&self) -> bool {
@@ -44,8 +431,7 @@ impl UiState {
             .read()
             .expect("RwLock read should not fail")
             .is_some()
-            && *self.playing.read().expect("RwLock read should not fail")
-                == PlaybackStatus::Finished
+            && self.playing == PlaybackStatus::Finished
     }
 
     pub(super) fn update_position(&self) -> Option<UiMsg> {
@@ -53,21 +439,35 @@ impl UiState {
             .current_episode
             .read()
             .expect("RwLock read should not fail");
-        let position = *self.elapsed.read().expect("RwLock read should not fail");
+        let position = self.elapsed;
         let cur_ep = cur_ep
             .as_ref()?
             .read()
             .expect("RwLock read should not fail");
-        Some(UiMsg::UpdatePosition(cur_ep.pod_id, cur_ep.id, position))
+        Some(UiMsg::UpdatePosition(
+            cur_ep.pod_id,
+            cur_ep.id,
+            position,
+            self.take_time_saved(),
+        ))
+    }
+
+    /// Returns the silence skipped since the last position update
+    fn take_time_saved(&self) -> Duration {
+        std::mem::take(
+            &mut *self
+                .time_saved
+                .write()
+                .expect("RwLock write should not fail"),
+        )
     }
 
     pub(super) fn play_pause(&self) -> Option<UiMsg> {
-        let playing = self.playing.read().expect("RwLock read should not fail");
         self.tx_to_player
             .blocking_send(PlayerMessage::PlayPause)
             .ok()?;
         // only updates position after Pause
-        match *playing {
+        match self.playing {
             PlaybackStatus::Playing => self.update_position(),
             _ => None,
         }
@@ -92,24 +492,21 @@ impl UiState {
                 let cur_ep = cur_ep.read().expect("RwLock read should not fail");
                 (
                     cur_ep.id == ep_id && cur_ep.pod_id == pod_id,
-                    *self.playing.read().expect("RwLock read should not fail")
-                        == PlaybackStatus::Playing,
+                    self.playing == PlaybackStatus::Playing,
                     cur_ep.id,
                     cur_ep.pod_id,
                 )
             });
         if !same {
             if playing {
-                let position = *self.elapsed.read().expect("RwLock read should not fail");
+                let position = self.elapsed;
                 return vec![
-                    UiMsg::UpdatePosition(cur_pod_id, cur_ep_id, position),
+                    UiMsg::UpdatePosition(cur_pod_id, cur_ep_id, position, self.take_time_saved()),
                     UiMsg::Play(pod_id, ep_id, false),
                 ];
             }
             return vec![UiMsg::Play(pod_id, ep_id, false)];
-        } else if *self.playing.read().expect("RwLock read should not fail")
-            == PlaybackStatus::Paused
-        {
+        } else if self.playing == PlaybackStatus::Paused {
             self.tx_to_player
                 .blocking_send(PlayerMessage::PlayPause)
                 .inspect_err(|err| {
@@ -120,9 +517,98 @@ impl UiState {
         vec![]
     }
 
+    /// Asks the player to open the next episode in the queue once the
+    /// current one gets close to its end, so it follows without a gap.
+    pub(super) fn prepare_next(&mut self) {
+        if self.playing != PlaybackStatus::Playing {
+            return;
+        }
+        let Some((ep_id, duration)) = self
+            .current_episode
+            .read()
+            .expect("RwLock read should not fail")
+            .as_ref()
+            .map(|ep| {
+                let ep = ep.read().expect("RwLock read should not fail");
+                (ep.id, ep.duration.unwrap_or(0))
+            })
+        else {
+            return;
+        };
+        let elapsed = self.elapsed;
+        if duration == 0 || elapsed + PREBUFFER_TIME < duration {
+            return;
+        }
+        let next = if self.audio.sleep_timer == Some(SleepTimer::EndOfEpisode) {
+            None
+        } else {
+            self.queue
+                .items
+                .get_index(ep_id, true)
+                .and_then(|index| self.next_from_queue(index))
+        };
+        let next_id = next
+            .as_ref()
+            .map(|ep| ep.read().expect("RwLock read should not fail").id);
+        if next_id == self.prepared {
+            return;
+        }
+        self.prepared = next_id;
+        let message = next.map_or(PlayerMessage::CancelPrepared, |ep| {
+            let ep = ep.read().expect("RwLock read should not fail");
+            let (skip_intro, settings) = self
+                .podcasts
+                .items
+                .map_single(ep.pod_id, |pod| {
+                    (
+                        pod.skip_intro,
+                        PodcastSettings {
+                            speed: pod.speed,
+                            equalizer: pod.equalizer.unwrap_or(self.default_equalizer),
+                            skip_outro: pod.skip_outro,
+                        },
+                    )
+                })
+                .unwrap_or((
+                    0,
+                    PodcastSettings {
+                        speed: 1.0,
+                        equalizer: self.default_equalizer,
+                        skip_outro: 0,
+                    },
+                ));
+            let duration = ep.duration.unwrap_or(0);
+            let position = self.start_position(
+                Duration::from_secs(ep.position),
+                duration,
+                ep.last_played,
+                skip_intro,
+            );
+            ep.path.clone().map_or_else(
+                || PlayerMessage::PrepareUrl(ep.id, ep.url.clone(), position, duration, settings),
+                |path| {
+                    PlayerMessage::PrepareFile(
+                        ep.id,
+                        path,
+                        position,
+                        duration,
+                        ep.loudness,
+                        settings,
+                    )
+                },
+            )
+        });
+        self.tx_to_player
+            .blocking_send(message)
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::Prepare to player: {err}");
+            })
+            .ok();
+    }
+
     pub(super) fn next_from_queue(&self, queue_index: usize) -> Option<ShareableRwLock<Episode>> {
-        if queue_index + 1 < self.queue.items.len(false) {
-            let ep_id = self.queue.items.get_id_by_index(queue_index + 1)?;
+        if queue_index + 1 < self.queue.items.len(true) {
+            let ep_id = self.queue.items.get_id_by_index(queue_index + 1, true)?;
             self.queue.items.get(ep_id)
         } else {
             None
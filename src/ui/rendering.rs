@@ -2,21 +2,30 @@ use ratatui::{
     Frame,
     layout::{Alignment, Constraint, Flex, Layout},
     prelude::Rect,
-    style::{Style, Stylize},
-    text::Line,
-    widgets::{Block, Clear, Gauge, HighlightSpacing, List, ListItem, Paragraph, Wrap},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{
+        Block, Clear, Gauge, HighlightSpacing, LineGauge, List, ListItem, ListState, Paragraph,
+        Wrap,
+    },
 };
 use tui_input::Input;
 
 use crate::{
+    config::MAX_VOLUME,
     keymap::{Keybindings, UserAction},
+    player::EqPreset,
+    show_notes::{Link, ShowNotes},
     types::{Episode, Menuable, ShareableRwLock},
     utils::format_duration,
 };
 
+use super::artwork::DETAILS_ART_ROWS;
 use super::colors::AppColors;
 use super::notification::render_notification_line;
-use super::{Details, MenuList, Panel, Popup, UiState};
+use super::search;
+use super::sleep_timer::SLEEP_CHOICES;
+use super::{AudioSettings, Details, MenuList, Panel, Popup, UiState};
 
 impl UiState {
     #[allow(clippy::too_many_lines)]
@@ -34,14 +43,30 @@ impl UiState {
         let [play_area, center_area, notif_area, help_area] = vertical_layout.areas(area);
         let [select_area, queue_area] = horizontal_layout.areas(center_area);
 
-        render_play_area(
+        self.click_areas.left = select_area;
+        self.click_areas.queue = queue_area;
+        // art on the left of the playing episode, inside the borders
+        let art_url = self.current_art_url();
+        let art_size = art_url.as_deref().and_then(|url| {
+            self.artwork
+                .fit(url, play_area.height.saturating_sub(2), play_area.width / 4)
+        });
+        let (progress_area, art_area) = render_play_area(
             frame,
             play_area,
             &self.current_episode,
             self.current_podcast_title.as_ref(),
-            *self.elapsed.read().expect("RwLock read should not fail"),
+            self.elapsed,
+            &self.audio,
             &self.colors,
+            art_size,
         );
+        self.click_areas.progress = progress_area;
+        if let (Some(url), Some(art_area)) = (art_url, art_area)
+            && (self.active_popup.is_none() || !self.artwork.is_overlay())
+        {
+            self.artwork.render(frame, art_area, &url);
+        }
         match self.left_panel {
             Panel::Podcasts => render_menuable_area(
                 frame,
@@ -64,6 +89,13 @@ impl UiState {
                 &self.colors,
                 self.active_panel == Panel::Unplayed,
             ),
+            Panel::Downloads => render_menuable_area(
+                frame,
+                select_area,
+                &mut self.downloads,
+                &self.colors,
+                self.active_panel == Panel::Downloads,
+            ),
             Panel::Queue => {}
         }
         render_menuable_area(
@@ -95,13 +127,30 @@ impl UiState {
                     );
                 }
                 Popup::Details => {
-                    render_details_popup(
+                    let popup_area = compute_popup_area(area, 70, 70);
+                    let art_url = self
+                        .current_details
+                        .as_ref()
+                        .and_then(|details| details.image_url.clone());
+                    let art_size = art_url.as_deref().and_then(|url| {
+                        self.artwork.fit(
+                            url,
+                            (popup_area.height / 3).min(DETAILS_ART_ROWS),
+                            popup_area.width.saturating_sub(2),
+                        )
+                    });
+                    let art_area = render_details_popup(
                         frame,
-                        compute_popup_area(area, 70, 70),
+                        popup_area,
                         self.current_details.as_ref(),
                         self.scroll_popup,
+                        &self.keymap,
                         &self.colors,
+                        art_size,
                     );
+                    if let (Some(url), Some(art_area)) = (art_url, art_area) {
+                        self.artwork.render(frame, art_area, &url);
+                    }
                 }
                 Popup::Help => {
                     render_shortcut_help_popup(
@@ -113,10 +162,33 @@ impl UiState {
                     );
                 }
                 Popup::AddPodcast => {
-                    render_add_podcast_popup(
+                    render_input_popup(
                         frame,
                         compute_popup_area(area, 30, 80),
                         &self.input,
+                        "Podcast feed url:",
+                        &self.colors,
+                    );
+                }
+                Popup::GoToTimestamp => {
+                    render_input_popup(
+                        frame,
+                        compute_popup_area(area, 30, 30),
+                        &self.input,
+                        "Go to timestamp (h:mm:ss):",
+                        &self.colors,
+                    );
+                }
+                Popup::ChooseLink(copy) => {
+                    render_input_popup(
+                        frame,
+                        compute_popup_area(area, 30, 30),
+                        &self.input,
+                        if *copy {
+                            "Copy link number:"
+                        } else {
+                            "Open link number:"
+                        },
                         &self.colors,
                     );
                 }
@@ -128,6 +200,59 @@ impl UiState {
                         &self.colors,
                     );
                 }
+                Popup::SleepTimer => {
+                    render_list_popup(
+                        frame,
+                        compute_popup_area(area, 30, 50),
+                        "Sleep timer",
+                        SLEEP_CHOICES.iter().map(|choice| choice.label()).collect(),
+                        &mut self.sleep_menu,
+                        &self.colors,
+                    );
+                }
+                Popup::OutputDevice => {
+                    let mut devices = vec!["Default".to_string()];
+                    devices.extend(self.devices.iter().cloned());
+                    render_list_popup(
+                        frame,
+                        compute_popup_area(area, 30, 50),
+                        "Output device",
+                        devices,
+                        &mut self.device_menu,
+                        &self.colors,
+                    );
+                }
+                Popup::ConfirmExternal(..) => {
+                    render_confirmation_popup(
+                        frame,
+                        compute_popup_area(area, 30, 70),
+                        "The audio format is not supported. Play it with the external player?"
+                            .to_string(),
+                        &self.colors,
+                    );
+                }
+                Popup::ConfirmResume(_, _, title, _) => {
+                    render_confirmation_popup(
+                        frame,
+                        compute_popup_area(area, 30, 70),
+                        format!("Resume {title}?"),
+                        &self.colors,
+                    );
+                }
+                Popup::Search => {
+                    let [input_area, results_area] =
+                        Layout::vertical([Constraint::Length(3), Constraint::Fill(1)])
+                            .areas(compute_popup_area(area, 60, 70));
+                    render_list_popup(
+                        frame,
+                        results_area,
+                        "Results",
+                        search::match_labels(&self.search),
+                        &mut self.search.state,
+                        &self.colors,
+                    );
+                    render_input_popup(frame, input_area, &self.input, "Search:", &self.colors);
+                }
                 Popup::ConfirmQuit => {
                     render_confirmation_popup(
                         frame,
@@ -138,6 +263,7 @@ impl UiState {
                 }
             }
         }
+        self.artwork.finish(frame);
     }
 }
 
@@ -158,8 +284,8 @@ pub(super) fn render_confirmation_popup(
 }
 
 #[allow(clippy::cast_possible_truncation)]
-pub(super) fn render_add_podcast_popup(
-    frame: &mut Frame, area: Rect, input: &Input, colors: &AppColors,
+pub(super) fn render_input_popup(
+    frame: &mut Frame, area: Rect, input: &Input, title: &str, colors: &AppColors,
 ) {
     let [_, input_area, _] = Layout::vertical([
         Constraint::Fill(1),
@@ -172,7 +298,7 @@ pub(super) fn render_add_podcast_popup(
     let input_text = Paragraph::new(input.value())
         .style(colors.normal)
         .scroll((0, scroll as u16))
-        .block(Block::bordered().title("Podcast feed url:"));
+        .block(Block::bordered().title(title));
     frame.render_widget(Clear, input_area);
     frame.render_widget(input_text, input_area);
     let x = input.visual_cursor().max(scroll) - scroll + 1;
@@ -199,16 +325,48 @@ pub(super) fn render_shortcut_help_popup(
         (Some(UserAction::PlayPause), "Play/Pause:"),
         (Some(UserAction::Left), "Seek backward:"),
         (Some(UserAction::Right), "Seek forward:"),
+        (Some(UserAction::SeekBackLong), "Seek backward more:"),
+        (Some(UserAction::SeekForwardLong), "Seek forward more:"),
+        (Some(UserAction::OpenLink), "Open link in details:"),
+        (Some(UserAction::CopyLink), "Copy link in details:"),
+        (Some(UserAction::IntroShorter), "Shorten skipped intro:"),
+        (Some(UserAction::IntroLonger), "Lengthen skipped intro:"),
+        (Some(UserAction::OutroShorter), "Shorten skipped outro:"),
+        (Some(UserAction::OutroLonger), "Lengthen skipped outro:"),
+        (Some(UserAction::GoToTimestamp), "Go to timestamp:"),
+        (Some(UserAction::JumpTo(0)), "Jump to 0% ... 90%:"),
+        (Some(UserAction::NextChapter), "Next chapter:"),
+        (Some(UserAction::PreviousChapter), "Previous chapter:"),
+        (Some(UserAction::Search), "Search:"),
+        (Some(UserAction::NextMatch), "Next match:"),
+        (Some(UserAction::PreviousMatch), "Previous match:"),
+        (Some(UserAction::SpeedUp), "Increase speed:"),
+        (Some(UserAction::SpeedDown), "Decrease speed:"),
+        (Some(UserAction::VolumeUp), "Increase volume:"),
+        (Some(UserAction::VolumeDown), "Decrease volume:"),
+        (Some(UserAction::Mute), "Mute/Unmute:"),
+        (Some(UserAction::SleepTimer), "Sleep timer:"),
+        (Some(UserAction::OutputDevice), "Output device:"),
+        (Some(UserAction::Equalizer), "Equalizer:"),
+        (Some(UserAction::Mono), "Mono:"),
+        (Some(UserAction::BalanceLeft), "Balance to the left:"),
+        (Some(UserAction::BalanceRight), "Balance to the right:"),
         (Some(UserAction::MarkPlayed), "Mark as played:"),
         (Some(UserAction::MarkAllPlayed), "Mark all as played:"),
         //(None, ""),
         (Some(UserAction::Enqueue), "Enqueue:"),
+        (Some(UserAction::Select), "Select episode:"),
+        (Some(UserAction::SelectRange), "Select up to here:"),
         (Some(UserAction::Remove), "Remove from queue:"),
         (Some(UserAction::Download), "Download:"),
         (Some(UserAction::DownloadAll), "Download all:"),
         (Some(UserAction::Delete), "Delete file:"),
         (Some(UserAction::DeleteAll), "Delete all files:"),
         (Some(UserAction::UnplayedList), "Show/Hide Unplayed Panel"),
+        (Some(UserAction::DownloadsList), "Show/Hide Downloads Panel"),
+        (Some(UserAction::FilterPlayed), "Filter played:"),
+        (Some(UserAction::FilterDownloaded), "Filter downloaded:"),
+        (Some(UserAction::Sort), "Change order:"),
         (Some(UserAction::Help), "Help:"),
         (Some(UserAction::Back), "Back:"),
         (Some(UserAction::Quit), "Quit:"),
@@ -221,6 +379,12 @@ pub(super) fn render_shortcut_help_popup(
                 if let Some(keys) = keymap.keys_for_action(action) {
                     // longest prefix is 21 chars long
                     let key_str = match keys.len() {
+                        // the keys of the other tenths follow in order
+                        _ if action == UserAction::JumpTo(0) => format!(
+                            "{action_str:>28} {} ... {}",
+                            key_hint(keymap, action),
+                            key_hint(keymap, UserAction::JumpTo(9))
+                        ),
                         0 => format!("{action_str:>28} <missing>"),
                         1 => format!("{:>28} \"{}\"", action_str, &keys[0]),
                         _ => format!("{:>28} \"{}\" or \"{}\"", action_str, &keys[0], &keys[1]),
@@ -253,6 +417,20 @@ pub(super) fn render_shortcut_help_popup(
     frame.render_widget(last_line, last);
 }
 
+pub(super) fn render_list_popup(
+    frame: &mut Frame, area: Rect, title: &str, items: Vec<String>, state: &mut ListState,
+    colors: &AppColors,
+) {
+    let list = List::new(items)
+        .block(Block::bordered().title(format!(" {title} ")))
+        .style(colors.normal)
+        .highlight_style(colors.highlighted)
+        .highlight_spacing(HighlightSpacing::Always);
+
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, state);
+}
+
 pub(super) fn render_welcome_popup(
     frame: &mut Frame, area: Rect, scroll: u16, keymap: &Keybindings, colors: &AppColors,
 ) {
@@ -289,9 +467,20 @@ pub(super) fn render_welcome_popup(
     frame.render_widget(paragraph, inner);
 }
 
+/// First key of an action, quoted, to mention in the text
+fn key_hint(keymap: &Keybindings, action: UserAction) -> String {
+    keymap
+        .keys_for_action(action)
+        .and_then(|keys| keys.first())
+        .map_or_else(|| "<missing>".to_string(), |key| format!("\"{key}\""))
+}
+
+/// Draws the details, leaving room at the top for artwork of the given
+/// columns and rows. Returns where the artwork goes.
 pub(super) fn render_details_popup(
-    frame: &mut Frame, area: Rect, details: Option<&Details>, scroll: u16, colors: &AppColors,
-) {
+    frame: &mut Frame, area: Rect, details: Option<&Details>, scroll: u16, keymap: &Keybindings,
+    colors: &AppColors, art_size: Option<(u16, u16)>,
+) -> Option<Rect> {
     if let Some(details) = details {
         let mut v = vec![];
         v.push(Line::from(""));
@@ -335,6 +524,25 @@ pub(super) fn render_details_popup(
             v.push(Line::from(""));
         }
 
+        if let Some(time_saved) = &details.time_saved {
+            v.push(Line::from("Time saved: ".to_string() + time_saved));
+            v.push(Line::from(""));
+        }
+
+        if let (Some(intro), Some(outro)) = (details.skip_intro, details.skip_outro) {
+            v.push(Line::from(format!(
+                "Skip intro: {intro} s ({} / {} to change)",
+                key_hint(keymap, UserAction::IntroShorter),
+                key_hint(keymap, UserAction::IntroLonger)
+            )));
+            v.push(Line::from(format!(
+                "Skip outro: {outro} s ({} / {} to change)",
+                key_hint(keymap, UserAction::OutroShorter),
+                key_hint(keymap, UserAction::OutroLonger)
+            )));
+            v.push(Line::from(""));
+        }
+
         v.push(Line::from("URL: ".to_string() + &details.url));
         v.push(Line::from(""));
 
@@ -345,27 +553,87 @@ pub(super) fn render_details_popup(
             v.push(Line::from(""));
         }
 
-        match &details.description {
-            Some(desc) => {
+        match &details.notes {
+            Some(notes) => {
                 v.push(Line::from("Description: "));
-                for line in desc.lines() {
-                    v.push(Line::from(line));
-                }
+                v.extend(notes_lines(notes, colors));
             }
             None => {
                 v.push(Line::from("No description."));
             }
         }
+
+        if !details.chapters.is_empty() {
+            v.push(Line::from(""));
+            v.push(Line::from("Chapters: "));
+            for chapter in &details.chapters {
+                v.push(Line::from(chapter.as_str()));
+            }
+        }
         let paragraph = Paragraph::new(v)
             .wrap(Wrap { trim: true })
             .scroll((scroll, 0));
         let block = Block::bordered().title(" Details ").style(colors.normal);
         let inner = block.inner(area);
+        let (art_cols, art_rows) = art_size.unwrap_or_default();
+        let [art_area, text_area] =
+            Layout::vertical([Constraint::Length(art_rows), Constraint::Fill(1)])
+                .spacing(u16::from(art_rows > 0))
+                .areas(inner);
+        let [art_area] = Layout::horizontal([Constraint::Length(art_cols)])
+            .flex(Flex::Center)
+            .areas(art_area);
 
         frame.render_widget(Clear, area);
         frame.render_widget(block, area);
-        frame.render_widget(paragraph, inner);
+        frame.render_widget(paragraph, text_area);
+        return (!art_area.is_empty()).then_some(art_area);
     }
+    None
+}
+
+/// Lines of show notes with their emphasis, followed by the urls they
+/// refer to
+fn notes_lines<'a>(notes: &'a ShowNotes, colors: &AppColors) -> Vec<Line<'a>> {
+    let mut lines: Vec<Line> = notes
+        .lines
+        .iter()
+        .map(|line| {
+            Line::from(
+                line.iter()
+                    .map(|span| {
+                        let mut style = Style::from(if span.reference {
+                            colors.highlighted
+                        } else {
+                            colors.normal
+                        });
+                        if span.bold {
+                            style = style.add_modifier(Modifier::BOLD);
+                        }
+                        if span.italic {
+                            style = style.add_modifier(Modifier::ITALIC);
+                        }
+                        Span::styled(span.text.as_str(), style)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+    let urls: Vec<_> = notes
+        .links
+        .iter()
+        .enumerate()
+        .filter_map(|(i, link)| match link {
+            Link::Url(url) => Some(Line::from(format!("[{}] {url}", i + 1))),
+            Link::Timestamp(_) => None,
+        })
+        .collect();
+    if !urls.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from("Links: "));
+        lines.extend(urls);
+    }
+    lines
 }
 
 pub(super) fn render_help_line(
@@ -419,7 +687,7 @@ pub(super) fn render_menuable_area<T: Menuable>(
     frame: &mut Frame, area: Rect, menu: &mut MenuList<T>, colors: &AppColors, active: bool,
 ) {
     let block = Block::bordered().title({
-        let line = Line::from(format!(" {} ", menu.title));
+        let line = Line::from(format!(" {} ", menu.title()));
         if active {
             line.style(colors.highlighted)
         } else {
@@ -427,9 +695,17 @@ pub(super) fn render_menuable_area<T: Menuable>(
         }
     });
     let text_width = block.inner(area).width as usize;
+    let marked = &menu.marked;
     let items: Vec<ListItem> = menu.items.map(
-        |x| ListItem::from(x.get_title(text_width)).style(colors.normal),
-        false,
+        |x| {
+            let style = if marked.contains(&x.get_id()) {
+                colors.bold
+            } else {
+                colors.normal
+            };
+            ListItem::from(x.get_title(text_width)).style(style)
+        },
+        true,
     );
 
     let list = List::new(items)
@@ -456,40 +732,79 @@ pub(super) fn compute_ratio(elapsed: u64, total: u64) -> f64 {
     (elapsed as f64 / total as f64).min(1.0)
 }
 
+/// Draws the current episode, with room on the left for artwork of the
+/// given columns and rows. Returns where its progress gauge and the
+/// artwork are.
+#[allow(clippy::too_many_arguments)]
 pub(super) fn render_play_area(
     frame: &mut Frame, area: Rect, ep: &ShareableRwLock<Option<ShareableRwLock<Episode>>>,
-    pod_title: Option<&String>, elapsed: u64, colors: &AppColors,
-) {
+    pod_title: Option<&String>, elapsed: u64, audio: &AudioSettings, colors: &AppColors,
+    art_size: Option<(u16, u16)>,
+) -> (Rect, Option<Rect>) {
     let block = Block::bordered()
         .title(Line::from(" Playing "))
         .style(colors.normal);
     let mut ratio = 0.0;
     let mut title = String::new();
     let mut podcast_title = String::new();
+    let mut status = String::new();
+    let mut chapter_starts = Vec::new();
     let label = ep
         .read()
         .expect("RwLock read should not fail")
         .as_ref()
         .map_or_else(String::new, |ep| {
-            let (ep_title, duration) = {
+            let (ep_title, duration, starts) = {
                 let ep = ep.read().expect("RwLock read should not fail");
-                (ep.title.clone(), ep.duration)
+                let starts: Vec<u64> = ep.chapters.iter().map(|chapter| chapter.start).collect();
+                (ep.title.clone(), ep.duration, starts)
             };
 
             if let Some(total) = duration {
                 ratio = compute_ratio(elapsed, total);
+                chapter_starts = starts.into_iter().map(|start| (start, total)).collect();
             }
             let total_label = format_duration(duration);
             title = ep_title;
             podcast_title = pod_title.map_or_else(String::new, std::clone::Clone::clone);
+            status = format!("Speed: {:.1}x", audio.speed);
+            if audio.equalizer != EqPreset::Flat {
+                status.push_str("  EQ: ");
+                status.push_str(audio.equalizer.label());
+            }
+            if audio.mono {
+                status.push_str("  Mono");
+            }
+            if audio.balance != 0 {
+                status.push_str(if audio.balance < 0 {
+                    "  Balance: L"
+                } else {
+                    "  Balance: R"
+                });
+                status.push_str(&audio.balance.unsigned_abs().to_string());
+            }
+            if let Some(sleep_timer) = &audio.sleep_timer {
+                status.push_str("  ");
+                status.push_str(&sleep_timer.label());
+            }
+            if audio.buffering {
+                status.push_str("  Buffering...");
+            }
             format!("{}/{}", format_duration(Some(elapsed)), total_label)
         });
     let progress = Gauge::default()
         .gauge_style(Style::new().green().on_black())
         .label(label)
         .ratio(ratio);
-    let inner_area = block.inner(area);
-    let [episode_area, podcast_area, _, bottom] = Layout::vertical([
+    let (art_cols, art_rows) = art_size.unwrap_or_default();
+    let [art_area, inner_area] =
+        Layout::horizontal([Constraint::Length(art_cols), Constraint::Fill(1)])
+            .spacing(u16::from(art_cols > 0))
+            .areas(block.inner(area));
+    let [art_area] = Layout::vertical([Constraint::Length(art_rows)])
+        .flex(Flex::Center)
+        .areas(art_area);
+    let [episode_area, podcast_area, status_area, bottom] = Layout::vertical([
         Constraint::Length(1),
         Constraint::Length(1),
         Constraint::Length(1),
@@ -499,5 +814,49 @@ pub(super) fn render_play_area(
     frame.render_widget(block, area);
     frame.render_widget(Line::from(title), episode_area);
     frame.render_widget(Line::from(podcast_title), podcast_area);
+    let [speed_area, volume_area] =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Length(24)]).areas(status_area);
+    frame.render_widget(Line::from(status), speed_area);
+    frame.render_widget(render_volume_gauge(audio), volume_area);
     frame.render_widget(progress, bottom);
+    mark_chapters(frame, bottom, &chapter_starts);
+    (bottom, (!art_area.is_empty()).then_some(art_area))
+}
+
+/// Draws a tick on the progress gauge where each chapter starts, given
+/// with the duration of the episode.
+fn mark_chapters(frame: &mut Frame, area: Rect, starts: &[(u64, u64)]) {
+    for &(start, total) in starts {
+        if start == 0 || start >= total {
+            continue;
+        }
+        let offset = u16::try_from(u64::from(area.width) * start / total).unwrap_or(0);
+        let Some(cell) = frame.buffer_mut().cell_mut((area.x + offset, area.y)) else {
+            continue;
+        };
+        // ticks on the played part are drawn in the colors of the rest
+        match cell.symbol() {
+            "█" => {
+                cell.set_symbol("│")
+                    .set_style(Style::new().black().on_green());
+            }
+            " " => {
+                cell.set_symbol("│");
+            }
+            _ => {}
+        }
+    }
+}
+
+fn render_volume_gauge(audio: &AudioSettings) -> LineGauge<'static> {
+    let volume = audio.effective_volume();
+    let label = if audio.muted {
+        "Muted   ".to_string()
+    } else {
+        format!("Vol {volume:>3}%")
+    };
+    LineGauge::default()
+        .filled_style(Style::new().green())
+        .label(label)
+        .ratio(compute_ratio(u64::from(volume), u64::from(MAX_VOLUME)))
 }
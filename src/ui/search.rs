@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use ratatui::widgets::ListState;
+
+use crate::{config::MESSAGE_TIME, utils::clean_html};
+
+use super::{Panel, UiState};
+
+/// Most results listed in the search popup
+const MAX_RESULTS: usize = 200;
+
+/// A podcast or episode matching the search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SearchMatch {
+    pub(super) pod_id: i64,
+    /// None when the podcast itself matched
+    pub(super) ep_id: Option<i64>,
+    pub(super) label: String,
+    score: i64,
+}
+
+/// Results of the last search, kept to move between them once the popup
+/// is closed
+#[derive(Debug, Default)]
+pub(super) struct Search {
+    pub(super) matches: Vec<SearchMatch>,
+    /// Result selected in the popup, and the last one jumped to
+    pub(super) state: ListState,
+    /// Descriptions as lowercase plain text, by podcast and episode id.
+    /// Made on the first search after the popup opened, not on every key.
+    descriptions: Option<HashMap<(i64, Option<i64>), String>>,
+}
+
+/// Scores how well `text` matches the lowercase characters of `query`,
+/// None unless all of them appear in order. Consecutive characters and
+/// characters at the start of words score higher, gaps lower.
+fn fuzzy_score(query: &[char], text: &str) -> Option<i64> {
+    let mut pending = query.iter().peekable();
+    let mut score = 0;
+    let mut previous = ' ';
+    let mut consecutive = false;
+    let mut started = false;
+    for c in text.chars().flat_map(char::to_lowercase) {
+        let Some(&&next) = pending.peek() else {
+            break;
+        };
+        if c == next {
+            score += 1;
+            if consecutive {
+                score += 4;
+            }
+            if !previous.is_alphanumeric() {
+                score += 3;
+            }
+            consecutive = true;
+            started = true;
+            pending.next();
+        } else {
+            if started {
+                score -= 1;
+            }
+            consecutive = false;
+        }
+        previous = c;
+    }
+    pending.peek().is_none().then_some(score)
+}
+
+/// Scores a title, or its description when enabled, which only matches
+/// if it contains the query as is and ranks below any title. The
+/// description is the one made by `search_text`.
+fn match_score(
+    query: &[char], lowercase_query: &str, title: &str, description: Option<&str>,
+) -> Option<i64> {
+    fuzzy_score(query, title).or_else(|| {
+        description
+            .filter(|description| description.contains(lowercase_query))
+            .map(|_| i64::MIN / 2)
+    })
+}
+
+/// Text of a description that is searched, without the HTML tags, so they
+/// do not match
+fn search_text(description: &str) -> String {
+    clean_html(description).to_lowercase()
+}
+
+impl UiState {
+    /// Finds the podcasts and episodes matching the text typed in the
+    /// search popup, best first.
+    pub(super) fn update_search(&mut self) {
+        let lowercase_query = self.input.value().trim().to_lowercase();
+        let query: Vec<char> = lowercase_query.chars().collect();
+        let mut matches = Vec::new();
+        if self.search_descriptions && self.search.descriptions.is_none() {
+            self.search.descriptions = Some(self.search_texts());
+        }
+        if !query.is_empty() {
+            let descriptions = self.search.descriptions.as_ref();
+            let searched = |key| descriptions.and_then(|d| d.get(&key)).map(String::as_str);
+            self.podcasts.items.map(
+                |pod| {
+                    let description = searched((pod.id, None));
+                    if let Some(score) =
+                        match_score(&query, &lowercase_query, &pod.title, description)
+                    {
+                        matches.push(SearchMatch {
+                            pod_id: pod.id,
+                            ep_id: None,
+                            label: pod.title.clone(),
+                            score,
+                        });
+                    }
+                    pod.episodes.map(
+                        |ep| {
+                            let description = searched((pod.id, Some(ep.id)));
+                            if let Some(score) =
+                                match_score(&query, &lowercase_query, &ep.title, description)
+                            {
+                                matches.push(SearchMatch {
+                                    pod_id: pod.id,
+                                    ep_id: Some(ep.id),
+                                    label: format!("{} > {}", pod.title, ep.title),
+                                    score,
+                                });
+                            }
+                        },
+                        false,
+                    );
+                },
+                false,
+            );
+        }
+        // shorter titles first among equal scores, they match more closely
+        matches.sort_by_key(|m| (std::cmp::Reverse(m.score), m.label.len()));
+        matches.truncate(MAX_RESULTS);
+        self.search.state = ListState::default().with_selected((!matches.is_empty()).then_some(0));
+        self.search.matches = matches;
+    }
+
+    /// The searched text of all descriptions
+    fn search_texts(&self) -> HashMap<(i64, Option<i64>), String> {
+        let mut texts = HashMap::new();
+        self.podcasts.items.map(
+            |pod| {
+                if let Some(description) = &pod.description {
+                    texts.insert((pod.id, None), search_text(description));
+                }
+                pod.episodes.map(
+                    |ep| {
+                        texts.insert((pod.id, Some(ep.id)), search_text(&ep.description));
+                    },
+                    false,
+                );
+            },
+            false,
+        );
+        texts
+    }
+
+    /// Selects the podcast or episode of the result selected in the
+    /// search.
+    pub(super) fn jump_to_match(&mut self) {
+        let Some(found) = self
+            .search
+            .state
+            .selected()
+            .and_then(|index| self.search.matches.get(index))
+            .cloned()
+        else {
+            return;
+        };
+        self.select_panel(&Panel::Podcasts);
+        self.podcasts.selected_item_id = Some(found.pod_id);
+        let mut shown = self.podcasts.sync_state_with_selected();
+        if let Some(ep_id) = found.ep_id
+            && shown
+        {
+            self.open_podcast(found.pod_id);
+            self.episodes.selected_item_id = Some(ep_id);
+            shown = self.episodes.sync_state_with_selected();
+        }
+        if !shown {
+            self.notification.timed_notif(
+                format!("Hidden by the filters: {}", found.label),
+                MESSAGE_TIME,
+                true,
+            );
+        }
+    }
+
+    /// Jumps to the next or previous result of the last search.
+    pub(super) fn jump_to_next_match(&mut self, forward: bool) {
+        let len = self.search.matches.len();
+        if len == 0 {
+            self.notification
+                .timed_notif("No search results".to_string(), MESSAGE_TIME, true);
+            return;
+        }
+        let index = match self.search.state.selected() {
+            Some(index) if forward => (index + 1) % len,
+            Some(index) => (index + len - 1) % len,
+            None => 0,
+        };
+        self.search.state.select(Some(index));
+        self.jump_to_match();
+    }
+}
+
+/// Labels of the results, for the popup
+pub(super) fn match_labels(search: &Search) -> Vec<String> {
+    search.matches.iter().map(|m| m.label.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, text: &str) -> Option<i64> {
+        fuzzy_score(&query.chars().collect::<Vec<_>>(), text)
+    }
+
+    #[test]
+    fn matches_characters_in_order() {
+        assert!(score("rstp", "Rust Podcast").is_some());
+        assert!(score("ptr", "Rust Podcast").is_none());
+        assert!(score("rustx", "Rust Podcast").is_none());
+    }
+
+    #[test]
+    fn prefers_consecutive_and_word_starts() {
+        let consecutive = score("pod", "The Podcast").unwrap();
+        let scattered = score("pod", "Paid or done").unwrap();
+        assert!(consecutive > scattered);
+        let word_start = score("ch", "The Chat").unwrap();
+        let inside = score("ch", "Tech talk").unwrap();
+        assert!(word_start > inside);
+    }
+
+    #[test]
+    fn descriptions_need_the_whole_query() {
+        let query: Vec<char> = "ferris".chars().collect();
+        assert_eq!(
+            match_score(&query, "ferris", "Episode 1", Some("about ferris the crab")),
+            Some(i64::MIN / 2)
+        );
+        assert_eq!(
+            match_score(
+                &query,
+                "ferris",
+                "Episode 1",
+                Some("far from it, really sure")
+            ),
+            None
+        );
+        assert_eq!(match_score(&query, "ferris", "Episode 1", None), None);
+    }
+
+    #[test]
+    fn descriptions_are_searched_without_tags() {
+        let text = search_text("<p>About <a href=\"https://ferris.example\">Ferris</a></p>");
+        assert_eq!(text, "about ferris");
+        let query: Vec<char> = "href".chars().collect();
+        assert_eq!(match_score(&query, "href", "Episode 1", Some(&text)), None);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{keymap::UserAction, types::Episode};
+
+use super::{MenuList, Panel, UiMsg, UiState};
+
+/// Ids listed between two items, both included, in list order
+fn ids_between(order: &[i64], from: i64, to: i64) -> Vec<i64> {
+    let (Some(from), Some(to)) = (
+        order.iter().position(|&id| id == from),
+        order.iter().position(|&id| id == to),
+    ) else {
+        return Vec::new();
+    };
+    order[from.min(to)..=from.max(to)].to_vec()
+}
+
+impl UiState {
+    /// The list of the active panel, if its episodes can be selected
+    const fn selectable_list(&mut self) -> Option<&mut MenuList<Episode>> {
+        match self.active_panel {
+            Panel::Episodes => Some(&mut self.episodes),
+            Panel::Unplayed => Some(&mut self.unplayed),
+            Panel::Queue => Some(&mut self.queue),
+            Panel::Podcasts | Panel::Downloads => None,
+        }
+    }
+
+    /// Adds the episode under the cursor to the selection, or removes it,
+    /// and moves to the next one.
+    pub(super) fn toggle_selected(&mut self) {
+        let Some(list) = self.selectable_list() else {
+            return;
+        };
+        let Some(ep_id) = list.selected_item_id else {
+            return;
+        };
+        if !list.marked.remove(&ep_id) {
+            list.marked.insert(ep_id);
+        }
+        list.anchor = Some(ep_id);
+        list.state.select_next();
+        list.sync_selected_with_state();
+    }
+
+    /// Selects every episode between the one toggled last and the one
+    /// under the cursor.
+    pub(super) fn select_range(&mut self) {
+        let Some(list) = self.selectable_list() else {
+            return;
+        };
+        let Some(ep_id) = list.selected_item_id else {
+            return;
+        };
+        let anchor = list.anchor.unwrap_or(ep_id);
+        let order = list.items.borrow_filtered_order().clone();
+        list.marked.extend(ids_between(&order, anchor, ep_id));
+        list.anchor = Some(ep_id);
+    }
+
+    /// Applies an action to the selected episodes of the active panel,
+    /// None if nothing is selected or the action does not apply to them,
+    /// which keeps the selection. It is cleared afterwards otherwise.
+    pub(super) fn apply_to_selection(&mut self, action: UserAction) -> Option<Vec<UiMsg>> {
+        let panel = self.active_panel.clone();
+        let applies = match action {
+            UserAction::MarkPlayed
+            | UserAction::Download
+            | UserAction::Delete
+            | UserAction::Back => true,
+            UserAction::Enqueue => panel != Panel::Queue,
+            UserAction::Remove => panel == Panel::Queue,
+            _ => false,
+        };
+        let list = self.selectable_list()?;
+        if list.marked.is_empty() || !applies {
+            return None;
+        }
+        let marked = std::mem::take(&mut list.marked);
+        list.anchor = None;
+        let episodes: Vec<_> = list
+            .items
+            .borrow_filtered_order()
+            .iter()
+            .filter(|id| marked.contains(id))
+            .filter_map(|&id| list.items.get(id))
+            .collect();
+        let ids: Vec<(i64, i64)> = episodes
+            .iter()
+            .map(|ep| {
+                let ep = ep.read().expect("RwLock read should not fail");
+                (ep.pod_id, ep.id)
+            })
+            .collect();
+
+        let msg = match action {
+            UserAction::MarkPlayed => {
+                // played unless all of them already are
+                let played = episodes
+                    .iter()
+                    .any(|ep| !ep.read().expect("RwLock read should not fail").played);
+                UiMsg::MarkPlayedBatch(ids, played)
+            }
+            UserAction::Download => UiMsg::DownloadBatch(ids),
+            UserAction::Delete => UiMsg::DeleteBatch(ids),
+            UserAction::Enqueue => {
+                for ep in episodes {
+                    let ep_id = ep.read().expect("RwLock read should not fail").id;
+                    if !self.queue.items.contains_key(ep_id) {
+                        self.queue.items.push_arc(ep);
+                    }
+                }
+                UiMsg::QueueModified
+            }
+            UserAction::Remove => {
+                for (_, ep_id) in ids {
+                    self.queue.items.remove(ep_id);
+                }
+                UiMsg::QueueModified
+            }
+            // only clears the selection
+            _ => UiMsg::Noop,
+        };
+        Some(vec![msg])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_in_either_direction() {
+        let order = [4, 2, 7, 1];
+        assert_eq!(ids_between(&order, 2, 1), [2, 7, 1]);
+        assert_eq!(ids_between(&order, 1, 2), [2, 7, 1]);
+        assert_eq!(ids_between(&order, 7, 7), [7]);
+        assert!(ids_between(&order, 3, 7).is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    chapters,
+    config::{MESSAGE_TIME, SLEEP_FADE_TIME},
+    player::{PlaybackStatus, PlayerMessage},
+    utils::format_duration,
+};
+
+use super::{UiMsg, UiState};
+
+/// Pending sleep timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SleepTimer {
+    /// Pauses at `deadline`, after fading out during the time before it
+    At { deadline: Instant, fading: bool },
+    /// Stops when the current episode finishes instead of continuing with
+    /// the queue
+    EndOfEpisode,
+    /// Pauses once the position reaches the end of the chapter, in seconds
+    EndOfChapter(u64),
+}
+
+impl SleepTimer {
+    /// Short description for the play area
+    pub(super) fn label(&self) -> String {
+        match self {
+            Self::At { deadline, .. } => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                // rounded up, so the countdown reaches zero when it fires
+                let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+                format!("Sleep in {}", format_duration(Some(seconds)))
+            }
+            Self::EndOfEpisode => "Sleep after episode".to_string(),
+            Self::EndOfChapter(_) => "Sleep after chapter".to_string(),
+        }
+    }
+}
+
+/// Choices offered in the sleep timer popup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SleepChoice {
+    Off,
+    Minutes(u64),
+    EndOfChapter,
+    EndOfEpisode,
+}
+
+pub(super) const SLEEP_CHOICES: [SleepChoice; 10] = [
+    SleepChoice::Off,
+    SleepChoice::Minutes(5),
+    SleepChoice::Minutes(10),
+    SleepChoice::Minutes(15),
+    SleepChoice::Minutes(30),
+    SleepChoice::Minutes(45),
+    SleepChoice::Minutes(60),
+    SleepChoice::Minutes(90),
+    SleepChoice::EndOfChapter,
+    SleepChoice::EndOfEpisode,
+];
+
+impl SleepChoice {
+    pub(super) fn label(self) -> String {
+        match self {
+            Self::Off => "Off".to_string(),
+            Self::Minutes(minutes) => format!("{minutes} minutes"),
+            Self::EndOfChapter => "End of chapter".to_string(),
+            Self::EndOfEpisode => "End of episode".to_string(),
+        }
+    }
+}
+
+impl UiState {
+    /// Replaces the sleep timer with the choice selected in the popup.
+    pub(super) fn set_sleep_timer(&mut self, choice: SleepChoice) {
+        if matches!(
+            self.audio.sleep_timer,
+            Some(SleepTimer::At { fading: true, .. })
+        ) {
+            self.send_fade_out(None);
+        }
+        self.audio.sleep_timer = match choice {
+            SleepChoice::Off => None,
+            SleepChoice::Minutes(minutes) => Some(SleepTimer::At {
+                deadline: Instant::now() + Duration::from_secs(minutes * 60),
+                fading: false,
+            }),
+            SleepChoice::EndOfChapter => self.end_of_chapter_timer(),
+            SleepChoice::EndOfEpisode => Some(SleepTimer::EndOfEpisode),
+        };
+    }
+
+    fn end_of_chapter_timer(&mut self) -> Option<SleepTimer> {
+        let position = self.elapsed;
+        let chapter_end = self
+            .current_episode
+            .read()
+            .expect("RwLock read should not fail")
+            .as_ref()
+            .map(|ep| {
+                let ep = ep.read().expect("RwLock read should not fail");
+                (
+                    ep.chapters.is_empty(),
+                    chapters::next_start(&ep.chapters, position),
+                )
+            });
+        match chapter_end {
+            Some((false, Some(end))) => Some(SleepTimer::EndOfChapter(end)),
+            // the last chapter ends with the episode
+            Some((false, None)) => Some(SleepTimer::EndOfEpisode),
+            _ => {
+                self.notification.timed_notif(
+                    "Sleep timer: the episode has no chapters".to_string(),
+                    MESSAGE_TIME,
+                    true,
+                );
+                None
+            }
+        }
+    }
+
+    /// Starts the fade out when the timer gets close, and saves the
+    /// position once the player paused.
+    pub(super) fn check_sleep_timer(&mut self) -> Option<UiMsg> {
+        if let Some(SleepTimer::EndOfChapter(end)) = self.audio.sleep_timer {
+            // the time left in the chapter passes faster when sped up
+            let remaining =
+                Duration::from_secs(end.saturating_sub(self.elapsed)).div_f32(self.audio.speed);
+            if remaining <= SLEEP_FADE_TIME {
+                // becomes a timer, which fades out until the chapter ends
+                self.audio.sleep_timer = Some(SleepTimer::At {
+                    deadline: Instant::now() + remaining,
+                    fading: false,
+                });
+            }
+        }
+        let Some(SleepTimer::At { deadline, fading }) = self.audio.sleep_timer else {
+            return None;
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if !fading && remaining <= SLEEP_FADE_TIME {
+            self.send_fade_out(Some(remaining));
+            self.audio.sleep_timer = Some(SleepTimer::At {
+                deadline,
+                fading: true,
+            });
+            return None;
+        }
+        if remaining.is_zero() {
+            if fading && self.playing == PlaybackStatus::Playing {
+                // the player pauses when the fade completes
+                return None;
+            }
+            self.audio.sleep_timer = None;
+            self.notification.timed_notif(
+                "Sleep timer: playback paused".to_string(),
+                MESSAGE_TIME,
+                false,
+            );
+            return self.update_position();
+        }
+        None
+    }
+
+    /// Consumes an end of episode timer, true if playback should stop
+    /// instead of continuing with the queue.
+    pub(super) fn sleep_after_episode(&mut self) -> bool {
+        if self.audio.sleep_timer == Some(SleepTimer::EndOfEpisode) {
+            self.audio.sleep_timer = None;
+            self.notification.timed_notif(
+                "Sleep timer: playback stopped".to_string(),
+                MESSAGE_TIME,
+                false,
+            );
+            return true;
+        }
+        false
+    }
+
+    fn send_fade_out(&self, length: Option<Duration>) {
+        self.tx_to_player
+            .blocking_send(PlayerMessage::FadeOut(length))
+            .inspect_err(|err| {
+                log::error!("Failed to send PlayerMessage::FadeOut to player: {err}");
+            })
+            .ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn countdown_rounds_up() {
+        let timer = SleepTimer::At {
+            deadline: Instant::now() + Duration::from_millis(90_500),
+            fading: false,
+        };
+        assert_eq!(timer.label(), "Sleep in 00:01:31");
+        assert_eq!(SleepTimer::EndOfEpisode.label(), "Sleep after episode");
+        assert_eq!(SleepTimer::EndOfChapter(60).label(), "Sleep after chapter");
+    }
+
+    #[test]
+    fn choices_start_with_off() {
+        assert_eq!(SLEEP_CHOICES[0], SleepChoice::Off);
+        assert_eq!(SleepChoice::Minutes(15).label(), "15 minutes");
+    }
+}
@@ -1,21 +1,52 @@
-use crate::types::FilterType;
+use std::time::Duration;
+
+use crate::{
+    player::EqPreset,
+    types::{FilterPanel, FilterType},
+};
 
 #[derive(Debug)]
 pub enum UiMsg {
     AddFeed(String),
     Play(i64, i64, bool),
+    /// Podcast, episode and the exact position of its checkpoint to play
+    /// it from
+    Resume(i64, i64, Duration),
     MarkPlayed(i64, i64, bool),
     MarkAllPlayed(i64, bool),
-    UpdatePosition(i64, i64, u64),
+    /// Podcast and id of each episode, and whether they are played
+    MarkPlayedBatch(Vec<(i64, i64)>, bool),
+    /// Podcast, episode, position and time saved since the last update
+    UpdatePosition(i64, i64, u64, Duration),
+    /// Podcast, episode and exact position, saved in case the app does
+    /// not exit cleanly
+    Checkpoint(i64, i64, Duration),
+    SetSpeed(i64, f32),
+    SetVolumeOffset(i64, i32),
+    SetEqualizer(i64, EqPreset),
+    /// Podcast and the seconds skipped at the start and the end
+    SetSkips(i64, u64, u64),
     Sync(i64),
     SyncAll,
     SyncGpodder,
     Download(i64, i64),
     DownloadAll(i64),
+    /// Podcast and id of each episode
+    DownloadBatch(Vec<(i64, i64)>),
+    /// Episode whose download is stopped
+    CancelDownload(i64),
+    /// Url from show notes to open with the configured command
+    OpenLink(String),
+    /// Url of artwork to download into the cache
+    FetchArtwork(String),
     Delete(i64, i64),
     DeleteAll(i64),
+    /// Podcast and id of each episode
+    DeleteBatch(Vec<(i64, i64)>),
     RemovePodcast(i64, bool),
-    FilterChange(FilterType),
+    FilterChange(FilterPanel, FilterType),
+    /// List to order next, with the podcast whose episodes are shown
+    SortChange(FilterPanel, Option<i64>),
     QueueModified,
     Quit,
     Noop,
@@ -2,19 +2,21 @@ use anyhow::{Context, Result, anyhow};
 use chrono::{DateTime, Utc};
 use regex::Regex;
 use std::fs;
-use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::{Arc, RwLock};
-use std::time::{SystemTime, UNIX_EPOCH};
-use symphonia::core::codecs::CODEC_TYPE_NULL;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::{MediaSourceStream, MediaSourceStreamOptions};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use symphonia::core::audio::AudioBufferRef;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, CodecParameters, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::{MediaSource, MediaSourceStream, MediaSourceStreamOptions};
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
-use symphonia::default::get_probe;
+use symphonia::default::{get_codecs, get_probe};
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::config::SMART_REWIND_STEPS;
 use crate::types::{Episode, LockVec, Podcast};
 
 pub static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
@@ -42,37 +44,95 @@ pub fn evaluate_in_shell(value: &str) -> Result<String> {
     Ok(String::from_utf8_lossy(&res.stdout).to_string())
 }
 
-pub fn audio_duration(audio_bytes: Vec<u8>) -> Result<u64> {
-    let cursor = Cursor::new(audio_bytes);
-    let mss = MediaSourceStream::new(Box::new(cursor), MediaSourceStreamOptions::default());
-    let probed = get_probe().format(
-        &Hint::new(),
-        mss,
-        &FormatOptions::default(),
-        &MetadataOptions::default(),
-    )?;
-    let mut duration = 0;
-    for track in probed.format.tracks() {
-        if track.codec_params.codec != CODEC_TYPE_NULL {
-            let tt = track
-                .codec_params
-                .time_base
-                .ok_or_else(|| anyhow!("time_base is None"))?
-                .calc_time(
-                    track
-                        .codec_params
-                        .n_frames
-                        .ok_or_else(|| anyhow!("n_frames is None"))?,
-                );
-            duration += tt.seconds;
+/// The first audio track of a file, opened for decoding
+pub struct AudioTrack {
+    format: Box<dyn FormatReader>,
+    id: u32,
+    params: CodecParameters,
+}
+
+impl AudioTrack {
+    /// Probes the format of the audio and finds its first audio track, the
+    /// file extension helps finding the format.
+    pub fn open(source: Box<dyn MediaSource>, extension: Option<&str>) -> Result<Self> {
+        let mss = MediaSourceStream::new(source, MediaSourceStreamOptions::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = extension {
+            hint.with_extension(extension);
         }
+        let format = get_probe()
+            .format(
+                &hint,
+                mss,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )?
+            .format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| anyhow!("No audio track found"))?;
+        let (id, params) = (track.id, track.codec_params.clone());
+        Ok(Self { format, id, params })
+    }
+
+    /// Duration in seconds, if the container tells it
+    fn duration(&self) -> Option<u64> {
+        let time = self.params.time_base?.calc_time(self.params.n_frames?);
+        Some(time.seconds)
     }
-    Ok(duration)
+
+    /// Decodes the whole track, handing each decoded buffer to `on_audio`.
+    /// Corrupted frames are skipped, as players do.
+    pub fn decode(mut self, mut on_audio: impl FnMut(AudioBufferRef<'_>)) -> Result<()> {
+        let mut decoder = get_codecs().make(&self.params, &DecoderOptions::default())?;
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(err))
+                    if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if packet.track_id() != self.id {
+                continue;
+            }
+            match decoder.decode(&packet) {
+                Ok(audio) => on_audio(audio),
+                Err(SymphoniaError::DecodeError(_)) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+pub fn audio_duration_file(file_path: &Path) -> Result<u64> {
+    let file = fs::File::open(file_path)?;
+    audio_duration(
+        Box::new(file),
+        file_path.extension().and_then(|ext| ext.to_str()),
+    )
 }
 
-pub fn audio_duration_file(file_path: PathBuf) -> Result<u64> {
-    let bytes = fs::read(file_path)?;
-    audio_duration(bytes)
+/// Duration of the audio in seconds, counting the decoded frames when the
+/// container does not tell it. The file extension helps finding the format.
+fn audio_duration(source: Box<dyn MediaSource>, extension: Option<&str>) -> Result<u64> {
+    let track = AudioTrack::open(source, extension)?;
+    if let Some(duration) = track.duration() {
+        return Ok(duration);
+    }
+    let (mut frames, mut rate) = (0, 0);
+    track.decode(|audio| {
+        frames += audio.frames() as u64;
+        rate = audio.spec().rate;
+    })?;
+    if rate == 0 {
+        return Err(anyhow!("No audio found"));
+    }
+    Ok(frames / u64::from(rate))
 }
 
 /// Some helper functions for dealing with Unicode strings.
@@ -140,6 +200,13 @@ pub fn resolve_redirection(url: &str) -> Result<String> {
     Ok(final_url)
 }
 
+/// Extension of the file a url points to, if it has one
+pub fn url_extension(url: &str) -> Option<&str> {
+    let path = url.split(['?', '#']).next()?;
+    let (name, ext) = path.rsplit('/').next()?.rsplit_once('.')?;
+    (!name.is_empty() && !ext.is_empty()).then_some(ext)
+}
+
 /// Normalizes a URL for comparison: lowercase host, sorted query params.
 pub fn normalize_url(url: &str) -> String {
     reqwest::Url::parse(url).map_or_else(
@@ -226,8 +293,54 @@ pub fn format_duration(duration: Option<u64>) -> String {
     )
 }
 
+/// Formats a number of bytes with the largest unit that keeps it above
+/// one, e.g. "12.3 MB".
+#[allow(clippy::cast_precision_loss)]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Parses a timestamp like "1:02:03", "2:03" or "123" into seconds.
+/// Minutes and seconds after the first field must be below 60.
+pub fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let fields = timestamp
+        .trim()
+        .split(':')
+        .map(|field| field.trim().parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if fields.len() > 3 || fields.iter().skip(1).any(|&field| field >= 60) {
+        return None;
+    }
+    fields.into_iter().try_fold(0_u64, |total, field| {
+        total.checked_mul(60)?.checked_add(field)
+    })
+}
+
+/// How far to go back when resuming after a pause of the given length,
+/// growing with the pause up to `max`.
+pub fn smart_rewind(paused: Duration, max: Duration) -> Duration {
+    SMART_REWIND_STEPS
+        .iter()
+        .rev()
+        .find(|&&(pause, _)| paused >= Duration::from_secs(pause))
+        .map_or(Duration::ZERO, |&(_, rewind)| Duration::from_secs(rewind))
+        .min(max)
+}
+
 #[cfg(test)]
-mod tests {
+pub mod tests {
+    use std::io::Cursor;
+
     use super::*;
 
     #[test]
@@ -249,11 +362,86 @@ mod tests {
         assert_eq!(dt.to_rfc3339(), "1969-12-31T00:00:00+00:00");
     }
 
+    /// Silent 16 bit mono wav of the given length
+    pub fn wav_bytes(seconds: u32) -> Vec<u8> {
+        let rate = 8000_u32;
+        let data_len = rate * 2 * seconds;
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((36 + data_len).to_le_bytes());
+        bytes.extend(b"WAVEfmt ");
+        bytes.extend(16_u32.to_le_bytes());
+        bytes.extend(1_u16.to_le_bytes());
+        bytes.extend(1_u16.to_le_bytes());
+        bytes.extend(rate.to_le_bytes());
+        bytes.extend((rate * 2).to_le_bytes());
+        bytes.extend(2_u16.to_le_bytes());
+        bytes.extend(16_u16.to_le_bytes());
+        bytes.extend(b"data");
+        bytes.extend(data_len.to_le_bytes());
+        bytes.resize(bytes.len() + data_len as usize, 0);
+        bytes
+    }
+
+    #[test]
+    fn audio_duration_of_wav() {
+        let wav = |seconds| Box::new(Cursor::new(wav_bytes(seconds)));
+        assert_eq!(audio_duration(wav(3), Some("wav")).unwrap(), 3);
+        assert_eq!(audio_duration(wav(2), None).unwrap(), 2);
+    }
+
+    #[test]
+    fn audio_track_decodes_all_frames() {
+        let track = AudioTrack::open(Box::new(Cursor::new(wav_bytes(2))), Some("wav")).unwrap();
+        let mut frames = 0;
+        track.decode(|audio| frames += audio.frames()).unwrap();
+        assert_eq!(frames, 16_000);
+    }
+
+    #[test]
+    fn audio_duration_unknown_format() {
+        let garbage = Box::new(Cursor::new(vec![0; 1024]));
+        assert!(audio_duration(garbage, Some("opus")).is_err());
+    }
+
+    #[test]
+    fn url_extension_ignores_query() {
+        assert_eq!(
+            url_extension("https://example.com/ep/1.m4a?source=feed"),
+            Some("m4a")
+        );
+        assert_eq!(
+            url_extension("https://example.com/ep.opus#t=10"),
+            Some("opus")
+        );
+        assert_eq!(url_extension("https://example.com/episode"), None);
+        assert_eq!(url_extension("https://example.com/ep/.hidden"), None);
+    }
+
     #[test]
     fn format_duration_none() {
         assert_eq!(format_duration(None), "--:--:--");
     }
 
+    #[test]
+    fn parse_timestamp_formats() {
+        assert_eq!(parse_timestamp("45"), Some(45));
+        assert_eq!(parse_timestamp("125"), Some(125));
+        assert_eq!(parse_timestamp("2:05"), Some(125));
+        assert_eq!(parse_timestamp(" 1:01:01 "), Some(3661));
+        assert_eq!(parse_timestamp("1:75"), None);
+        assert_eq!(parse_timestamp("1:2:3:4"), None);
+        assert_eq!(parse_timestamp("abc"), None);
+        assert_eq!(parse_timestamp(""), None);
+    }
+
+    #[test]
+    fn format_size_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1_500), "1.5 KB");
+        assert_eq!(format_size(12_345_678), "12.3 MB");
+        assert_eq!(format_size(2_000_000_000), "2.0 GB");
+    }
+
     #[test]
     fn format_duration_zero() {
         assert_eq!(format_duration(Some(0)), "00:00:00");
@@ -389,4 +577,23 @@ mod tests {
             normalize_url("https://EXAMPLE.COM/feed.xml")
         );
     }
+
+    #[test]
+    fn smart_rewind_grows_with_pause() {
+        let max = Duration::from_secs(30);
+        assert_eq!(smart_rewind(Duration::from_secs(20), max), Duration::ZERO);
+        assert_eq!(
+            smart_rewind(Duration::from_mins(2), max),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            smart_rewind(Duration::from_hours(3), max),
+            Duration::from_secs(20)
+        );
+        assert_eq!(smart_rewind(Duration::from_hours(72), max), max);
+        assert_eq!(
+            smart_rewind(Duration::from_hours(72), Duration::from_secs(8)),
+            Duration::from_secs(8)
+        );
+    }
 }